@@ -0,0 +1,545 @@
+//! # Overview
+//! Typed view on the concrete syntax tree. The AST types do not own any data; each of them wraps a
+//! reference to a [`SyntaxNode`] of the matching [`SyntaxKind`] and offers accessors for its parts.
+//! The underlying node is always available through `syntax()`, so positions and trivia are never
+//! lost.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::{Expr, Program, Statement, Type};
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("int answer() { return 6 * 7; }").unwrap();
+//! let program = Program::cast(&tree.root).unwrap();
+//!
+//! let function = program.functions().next().unwrap();
+//! assert_eq!(function.name(), "answer");
+//! assert_eq!(function.return_type(), Type::Int);
+//!
+//! let first = function.body().statements().next();
+//! match first {
+//!     Some(Statement::Return(ret)) => assert!(matches!(ret.value(), Some(Expr::Binary(_)))),
+//!     _ => panic!("expected a return statement"),
+//! }
+//! ```
+
+use crate::cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::lexer::{C1Token, TokenValue};
+use std::fmt;
+
+macro_rules! ast_node {
+    ($(#[$meta:meta])* $name:ident, $kind:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Copy, Clone)]
+        pub struct $name<'t>(&'t SyntaxNode<'t>);
+
+        impl<'t> $name<'t> {
+            /// Wrap the given node if it has the matching kind
+            pub fn cast(node: &'t SyntaxNode<'t>) -> Option<Self> {
+                if node.kind == SyntaxKind::$kind {
+                    Some($name(node))
+                } else {
+                    None
+                }
+            }
+
+            /// Return the underlying node of the concrete syntax tree
+            pub fn syntax(&self) -> &'t SyntaxNode<'t> {
+                self.0
+            }
+        }
+    };
+}
+
+ast_node!(
+    /// `( functiondefinition )*`
+    Program,
+    Program
+);
+ast_node!(
+    /// `type <ID> "(" ")" "{" statementlist "}"`
+    FunctionDefinition,
+    FunctionDefinition
+);
+ast_node!(
+    /// `( block )*`
+    StatementList,
+    StatementList
+);
+ast_node!(
+    /// `"{" statementlist "}"`
+    Block,
+    Block
+);
+ast_node!(
+    /// `<KW_IF> "(" assignment ")" block`
+    IfStatement,
+    IfStatement
+);
+ast_node!(
+    /// `<KW_RETURN> ( assignment )?`
+    ReturnStatement,
+    ReturnStatement
+);
+ast_node!(
+    /// `<KW_PRINTF> "(" assignment ")"`
+    Printf,
+    Printf
+);
+ast_node!(
+    /// `<ID> "=" assignment` as a statement
+    StatAssignment,
+    StatAssignment
+);
+ast_node!(
+    /// `<ID> "(" ")"`
+    FunctionCall,
+    FunctionCall
+);
+ast_node!(
+    /// `<ID> "=" assignment` as an expression
+    Assignment,
+    Assignment
+);
+ast_node!(
+    /// Two operands combined by an arithmetic, logical or comparison operator
+    BinaryExpr,
+    BinaryExpr
+);
+ast_node!(
+    /// A negated operand
+    UnaryExpr,
+    UnaryExpr
+);
+ast_node!(
+    /// `<CONST_INT>`, `<CONST_FLOAT>` or `<CONST_BOOLEAN>`
+    Literal,
+    Literal
+);
+ast_node!(
+    /// A variable
+    NameRef,
+    NameRef
+);
+ast_node!(
+    /// `"(" assignment ")"`
+    ParenExpr,
+    ParenExpr
+);
+
+/// The return type of a function
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Type {
+    Bool,
+    Float,
+    Int,
+    Void,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Bool => "bool",
+            Type::Float => "float",
+            Type::Int => "int",
+            Type::Void => "void",
+        })
+    }
+}
+
+/// One of the alternatives of the `block` and `statement` rules
+#[derive(Debug, Copy, Clone)]
+pub enum Statement<'t> {
+    Block(Block<'t>),
+    If(IfStatement<'t>),
+    Return(ReturnStatement<'t>),
+    Printf(Printf<'t>),
+    Assignment(StatAssignment<'t>),
+    Call(FunctionCall<'t>),
+}
+
+/// Any expression, from `assignment` down to `factor`
+#[derive(Debug, Copy, Clone)]
+pub enum Expr<'t> {
+    Assignment(Assignment<'t>),
+    Binary(BinaryExpr<'t>),
+    Unary(UnaryExpr<'t>),
+    Literal(Literal<'t>),
+    Name(NameRef<'t>),
+    Call(FunctionCall<'t>),
+    Paren(ParenExpr<'t>),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LiteralKind {
+    Int,
+    Float,
+    Bool,
+}
+
+impl<'t> Program<'t> {
+    pub fn functions(&self) -> impl Iterator<Item = FunctionDefinition<'t>> {
+        self.0.child_nodes().filter_map(FunctionDefinition::cast)
+    }
+}
+
+impl<'t> FunctionDefinition<'t> {
+    pub fn return_type(&self) -> Type {
+        let type_node = self
+            .0
+            .child_nodes()
+            .find(|node| node.kind == SyntaxKind::Type);
+        match type_node
+            .and_then(SyntaxNode::first_token)
+            .map(|token| token.kind)
+        {
+            Some(C1Token::KwBoolean) => Type::Bool,
+            Some(C1Token::KwFloat) => Type::Float,
+            Some(C1Token::KwInt) => Type::Int,
+            _ => Type::Void,
+        }
+    }
+
+    pub fn name_token(&self) -> &'t SyntaxToken<'t> {
+        expect_token(self.0, C1Token::Identifier)
+    }
+
+    pub fn name(&self) -> &'t str {
+        self.name_token().text
+    }
+
+    pub fn body(&self) -> StatementList<'t> {
+        expect_node(self.0, StatementList::cast)
+    }
+}
+
+impl<'t> StatementList<'t> {
+    pub fn statements(&self) -> impl Iterator<Item = Statement<'t>> {
+        self.0.child_nodes().filter_map(Statement::cast)
+    }
+}
+
+impl<'t> Block<'t> {
+    pub fn statement_list(&self) -> StatementList<'t> {
+        expect_node(self.0, StatementList::cast)
+    }
+
+    pub fn statements(&self) -> impl Iterator<Item = Statement<'t>> {
+        self.statement_list().statements()
+    }
+}
+
+impl<'t> Statement<'t> {
+    /// Wrap a `Block` node, or the statement inside a `Statement` node
+    pub fn cast(node: &'t SyntaxNode<'t>) -> Option<Self> {
+        match node.kind {
+            SyntaxKind::Block => Some(Statement::Block(Block(node))),
+            SyntaxKind::Statement => {
+                let inner = node.child_nodes().next()?;
+                match inner.kind {
+                    SyntaxKind::IfStatement => Some(Statement::If(IfStatement(inner))),
+                    SyntaxKind::ReturnStatement => Some(Statement::Return(ReturnStatement(inner))),
+                    SyntaxKind::Printf => Some(Statement::Printf(Printf(inner))),
+                    SyntaxKind::StatAssignment => {
+                        Some(Statement::Assignment(StatAssignment(inner)))
+                    }
+                    SyntaxKind::FunctionCall => Some(Statement::Call(FunctionCall(inner))),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn syntax(&self) -> &'t SyntaxNode<'t> {
+        match self {
+            Statement::Block(block) => block.syntax(),
+            Statement::If(statement) => statement.syntax(),
+            Statement::Return(statement) => statement.syntax(),
+            Statement::Printf(statement) => statement.syntax(),
+            Statement::Assignment(statement) => statement.syntax(),
+            Statement::Call(call) => call.syntax(),
+        }
+    }
+}
+
+impl<'t> IfStatement<'t> {
+    pub fn condition(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+
+    pub fn then_branch(&self) -> Statement<'t> {
+        let branch = self.0.child_nodes().nth(1).and_then(Statement::cast);
+        branch.expect("if statement without a branch")
+    }
+}
+
+impl<'t> ReturnStatement<'t> {
+    pub fn value(&self) -> Option<Expr<'t>> {
+        self.0.child_nodes().find_map(Expr::cast)
+    }
+}
+
+impl<'t> Printf<'t> {
+    pub fn argument(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+}
+
+impl<'t> StatAssignment<'t> {
+    pub fn target_token(&self) -> &'t SyntaxToken<'t> {
+        expect_token(self.0, C1Token::Identifier)
+    }
+
+    pub fn target(&self) -> &'t str {
+        self.target_token().text
+    }
+
+    pub fn value(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+}
+
+impl<'t> FunctionCall<'t> {
+    pub fn name_token(&self) -> &'t SyntaxToken<'t> {
+        expect_token(self.0, C1Token::Identifier)
+    }
+
+    pub fn name(&self) -> &'t str {
+        self.name_token().text
+    }
+}
+
+impl<'t> Expr<'t> {
+    pub fn cast(node: &'t SyntaxNode<'t>) -> Option<Self> {
+        match node.kind {
+            SyntaxKind::Assignment => Some(Expr::Assignment(Assignment(node))),
+            SyntaxKind::BinaryExpr => Some(Expr::Binary(BinaryExpr(node))),
+            SyntaxKind::UnaryExpr => Some(Expr::Unary(UnaryExpr(node))),
+            SyntaxKind::Literal => Some(Expr::Literal(Literal(node))),
+            SyntaxKind::NameRef => Some(Expr::Name(NameRef(node))),
+            SyntaxKind::FunctionCall => Some(Expr::Call(FunctionCall(node))),
+            SyntaxKind::ParenExpr => Some(Expr::Paren(ParenExpr(node))),
+            _ => None,
+        }
+    }
+
+    pub fn syntax(&self) -> &'t SyntaxNode<'t> {
+        match self {
+            Expr::Assignment(expr) => expr.syntax(),
+            Expr::Binary(expr) => expr.syntax(),
+            Expr::Unary(expr) => expr.syntax(),
+            Expr::Literal(expr) => expr.syntax(),
+            Expr::Name(expr) => expr.syntax(),
+            Expr::Call(expr) => expr.syntax(),
+            Expr::Paren(expr) => expr.syntax(),
+        }
+    }
+}
+
+impl<'t> Assignment<'t> {
+    pub fn target_token(&self) -> &'t SyntaxToken<'t> {
+        expect_token(self.0, C1Token::Identifier)
+    }
+
+    pub fn target(&self) -> &'t str {
+        self.target_token().text
+    }
+
+    pub fn value(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+}
+
+impl<'t> BinaryExpr<'t> {
+    pub fn lhs(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+
+    pub fn rhs(&self) -> Expr<'t> {
+        let rhs = self.0.child_nodes().filter_map(Expr::cast).nth(1);
+        rhs.expect("binary expression without a right operand")
+    }
+
+    pub fn operator_token(&self) -> &'t SyntaxToken<'t> {
+        let operator = self.0.child_tokens().next();
+        operator.expect("binary expression without an operator")
+    }
+
+    pub fn operator(&self) -> BinaryOp {
+        match self.operator_token().kind {
+            C1Token::Plus => BinaryOp::Add,
+            C1Token::Minus => BinaryOp::Sub,
+            C1Token::Asterisk => BinaryOp::Mul,
+            C1Token::Slash => BinaryOp::Div,
+            C1Token::Or => BinaryOp::Or,
+            C1Token::And => BinaryOp::And,
+            C1Token::Equal => BinaryOp::Equal,
+            C1Token::NotEqual => BinaryOp::NotEqual,
+            C1Token::Less => BinaryOp::Less,
+            C1Token::Greater => BinaryOp::Greater,
+            C1Token::LessEqual => BinaryOp::LessEqual,
+            C1Token::GreaterEqual => BinaryOp::GreaterEqual,
+            other => unreachable!("{:?} is not a binary operator", other),
+        }
+    }
+}
+
+impl<'t> UnaryExpr<'t> {
+    pub fn operand(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+}
+
+impl<'t> Literal<'t> {
+    pub fn token(&self) -> &'t SyntaxToken<'t> {
+        let token = self.0.child_tokens().next();
+        token.expect("literal without a token")
+    }
+
+    pub fn kind(&self) -> LiteralKind {
+        match self.token().kind {
+            C1Token::ConstInt => LiteralKind::Int,
+            C1Token::ConstFloat => LiteralKind::Float,
+            _ => LiteralKind::Bool,
+        }
+    }
+
+    pub fn text(&self) -> &'t str {
+        self.token().text
+    }
+
+    /// The decoded value of the literal
+    pub fn value(&self) -> TokenValue {
+        let token = self.token();
+        TokenValue::decode(token.kind, token.text).expect("the lexer only accepts valid literals")
+    }
+}
+
+impl<'t> NameRef<'t> {
+    pub fn token(&self) -> &'t SyntaxToken<'t> {
+        expect_token(self.0, C1Token::Identifier)
+    }
+
+    pub fn name(&self) -> &'t str {
+        self.token().text
+    }
+}
+
+impl<'t> ParenExpr<'t> {
+    pub fn inner(&self) -> Expr<'t> {
+        expect_node(self.0, Expr::cast)
+    }
+}
+
+/// Find the first child of the given node that can be cast. The parser guarantees the presence of
+/// all mandatory children, so a missing child is a bug.
+fn expect_node<'t, T>(node: &'t SyntaxNode<'t>, cast: fn(&'t SyntaxNode<'t>) -> Option<T>) -> T {
+    let child = node.children.iter().find_map(|child| match child {
+        SyntaxElement::Node(child) => cast(child),
+        SyntaxElement::Token(_) => None,
+    });
+    child.unwrap_or_else(|| panic!("malformed {:?} node", node.kind))
+}
+
+fn expect_token<'t>(node: &'t SyntaxNode<'t>, kind: C1Token) -> &'t SyntaxToken<'t> {
+    let token = node.child_token(kind);
+    token.unwrap_or_else(|| panic!("{:?} node without {:?} token", node.kind, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{BinaryOp, Expr, LiteralKind, Program, Statement, Type};
+    use crate::C1Parser;
+
+    #[test]
+    fn example_functions() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let tree = C1Parser::parse_tree(&text).unwrap();
+        let program = Program::cast(&tree.root).unwrap();
+
+        let signatures: Vec<(&str, Type)> = program
+            .functions()
+            .map(|function| (function.name(), function.return_type()))
+            .collect();
+        assert_eq!(
+            signatures,
+            vec![
+                ("blub", Type::Int),
+                ("blah", Type::Float),
+                ("main", Type::Void)
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_and_expressions() {
+        let tree = C1Parser::parse_tree(
+            "void main() {\n\
+             x = -1 + 2 * y;\n\
+             if (x < 3) { printf(x = foo()); }\n\
+             return;\n\
+             }",
+        )
+        .unwrap();
+        let program = Program::cast(&tree.root).unwrap();
+        let main = program.functions().next().unwrap();
+        let statements: Vec<Statement> = main.body().statements().collect();
+        assert_eq!(statements.len(), 3);
+
+        let Statement::Assignment(assignment) = statements[0] else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(assignment.target(), "x");
+        let Expr::Binary(sum) = assignment.value() else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(sum.operator(), BinaryOp::Add);
+        assert!(matches!(sum.lhs(), Expr::Unary(_)));
+        let Expr::Binary(product) = sum.rhs() else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(product.operator(), BinaryOp::Mul);
+        let Expr::Literal(two) = product.lhs() else {
+            panic!("expected a literal");
+        };
+        assert_eq!((two.kind(), two.text()), (LiteralKind::Int, "2"));
+
+        let Statement::If(if_statement) = statements[1] else {
+            panic!("expected an if statement");
+        };
+        assert!(matches!(if_statement.condition(), Expr::Binary(_)));
+        let Statement::Block(block) = if_statement.then_branch() else {
+            panic!("expected a block");
+        };
+        let Some(Statement::Printf(printf)) = block.statements().next() else {
+            panic!("expected printf");
+        };
+        let Expr::Assignment(nested) = printf.argument() else {
+            panic!("expected an assignment expression");
+        };
+        assert!(matches!(nested.value(), Expr::Call(call) if call.name() == "foo"));
+
+        let Statement::Return(ret) = statements[2] else {
+            panic!("expected a return statement");
+        };
+        assert!(ret.value().is_none());
+        assert_eq!(ret.syntax().first_token().unwrap().line, 4);
+    }
+}
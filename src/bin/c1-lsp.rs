@@ -0,0 +1,15 @@
+use std::io;
+use std::process;
+
+/// Language server for C(-1) over stdin/stdout
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match cb_3::lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(error) => {
+            eprintln!("c1-lsp: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use cb_3::ast::Program;
+use cb_3::bytecode::{self, Module};
+use cb_3::codegen::{c, llvm, wasm, x86_64};
+use cb_3::highlight;
+use cb_3::ir;
+use cb_3::json::JsonValue;
+use cb_3::preprocessor::{self, FileLoader, Preprocessed};
+use cb_3::typeck::{self, CheckedProgram};
+use cb_3::vm::Vm;
+use cb_3::{tokenize, C1Parser, FileId, SourceMap, SyntaxTree, Token};
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+const USAGE: &str = "\
+usage: c1 <command> [options] <file>
+       c1 check <file>...
+
+Use '-' as file to read from stdin. All commands but cat and tokens run the preprocessor
+first, which looks up included files relative to the including file.
+
+commands:
+    check                 check the files for syntax and type errors, as one program
+    cat [--color|--html]  print the file, highlighted for a terminal or as HTML
+    tokens [--format json|text]
+                          print the tokens of the file, one per line or as a JSON array
+    preprocess            print the file after including files and replacing macros
+    bytecode              print the bytecode listing of the compiled file
+    run                   compile the file and run its main function
+    ir [--dot|--ssa]      print the intermediate representation, its control flow graphs in
+                          Graphviz DOT format, or its SSA form
+    callgraph [--dot]     print which functions call each other, recursion and functions
+                          never called from main, or the call graph in Graphviz DOT format
+    lint                  warn about unreachable code and unused assignments
+    regalloc              print the registers and stack slots of the variables for x86-64
+    optimize [--before] [--inline] [--remove-dead-code]
+                          print the intermediate representation after constant folding and
+                          optionally inlining and dead code removal, or before and after
+    emit-c                print the file translated to C99
+    emit-llvm             print the file translated to LLVM IR
+    emit-asm              print the file translated to x86-64 assembly (GNU as)
+    emit-wasm             print the file translated to WebAssembly text";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("c1: {}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (command, args) = args.split_first().ok_or(USAGE)?;
+    if command == "check" && !args.is_empty() {
+        return check(args);
+    }
+    let (options, file) = match args.split_last() {
+        Some((file, options)) => (options, file),
+        None => return Err(USAGE.to_string()),
+    };
+    let text = read_source(file)?;
+    match command.as_str() {
+        "cat" => {
+            match expect_options(options, &["--color", "--html"])? {
+                Some("--color") => print!("{}", highlight::to_ansi(&text)),
+                Some(_) => println!("{}", highlight::to_html(&text)),
+                None => print!("{}", text),
+            }
+            Ok(())
+        }
+        "tokens" => {
+            let tokens = tokenize(&text);
+            match options {
+                [] => print_tokens(&tokens),
+                [option, format] if option == "--format" && format == "text" => {
+                    print_tokens(&tokens)
+                }
+                [option, format] if option == "--format" && format == "json" => {
+                    let tokens: Vec<JsonValue> = tokens.iter().map(Token::to_json).collect();
+                    println!("{}", JsonValue::from(tokens));
+                }
+                _ => return Err(USAGE.to_string()),
+            }
+            Ok(())
+        }
+        "preprocess" => {
+            expect_options(options, &[])?;
+            print!("{}", preprocess(file, &text)?.text);
+            Ok(())
+        }
+        "bytecode" => {
+            expect_options(options, &[])?;
+            let module = compile(file, &preprocess(file, &text)?)?;
+            print!("{}", bytecode::disassemble(&module));
+            Ok(())
+        }
+        "run" => {
+            expect_options(options, &[])?;
+            let source = preprocess(file, &text)?;
+            let module = compile(file, &source)?;
+            Vm::new(&module)
+                .run("main", &mut io::stdout().lock())
+                .map(|_| ())
+                .map_err(|error| match error.location() {
+                    Some(location) => relocate(file, &source, &error.to_string(), location.line),
+                    None => format!("{}: {}", file, error),
+                })
+        }
+        "ir" => {
+            let option = expect_options(options, &["--dot", "--ssa"])?;
+            let output = translate(file, &preprocess(file, &text)?, |checked| {
+                let module = ir::lower(checked);
+                match option {
+                    Some("--dot") => module.to_dot(),
+                    Some(_) => {
+                        let functions: Vec<String> = module
+                            .functions
+                            .iter()
+                            .map(|function| ir::to_ssa(function).to_string())
+                            .collect();
+                        functions.join("\n")
+                    }
+                    None => module.to_string(),
+                }
+            })?;
+            print!("{}", output);
+            Ok(())
+        }
+        "callgraph" => {
+            let dot = expect_options(options, &["--dot"])?.is_some();
+            let output = translate(file, &preprocess(file, &text)?, |checked| {
+                let graph = ir::CallGraph::new(&ir::lower(checked));
+                if dot {
+                    return graph.to_dot();
+                }
+                let mut output = String::new();
+                for function in graph.functions() {
+                    let callees = graph.callees(function);
+                    if callees.is_empty() {
+                        output.push_str(&format!("{}:\n", function));
+                    } else {
+                        output.push_str(&format!("{}: {}\n", function, callees.join(", ")));
+                    }
+                }
+                for group in graph.recursive_groups() {
+                    output.push_str(&format!("recursive: {}\n", group.join(", ")));
+                }
+                let unreachable = graph.unreachable_from_main();
+                if !unreachable.is_empty() {
+                    output.push_str(&format!(
+                        "never called from main: {}\n",
+                        unreachable.join(", ")
+                    ));
+                }
+                output
+            })?;
+            print!("{}", output);
+            Ok(())
+        }
+        "lint" => {
+            expect_options(options, &[])?;
+            let source = preprocess(file, &text)?;
+            let warnings = translate(file, &source, |checked| {
+                let mut output = String::new();
+                for function in &ir::lower(checked).functions {
+                    for warning in ir::dead_code_warnings(function) {
+                        let message = format!("warning: {}", warning);
+                        output.push_str(&relocate(file, &source, &message, warning.line));
+                        output.push('\n');
+                    }
+                }
+                output
+            })?;
+            print!("{}", warnings);
+            Ok(())
+        }
+        "regalloc" => {
+            expect_options(options, &[])?;
+            let registers = ir::RegisterFile::x86_64();
+            let source = preprocess(file, &text)?;
+            let output = translate(file, &source, |checked| -> Result<String, String> {
+                let mut output = String::new();
+                for function in &ir::lower(checked).functions {
+                    let allocation = ir::allocate_registers(function, &registers);
+                    ir::verify(function, &allocation, &registers).map_err(|error| {
+                        let message = format!("{}: {}", function.name, error);
+                        relocate(file, &source, &message, error.line)
+                    })?;
+                    output.push_str(&allocation.listing(function, &registers));
+                }
+                Ok(output)
+            })??;
+            print!("{}", output);
+            Ok(())
+        }
+        "optimize" => {
+            let flags = expect_flags(options, &["--before", "--inline", "--remove-dead-code"])?;
+            let output = translate(file, &preprocess(file, &text)?, |checked| {
+                let mut module = ir::lower(checked);
+                let original = module.to_string();
+                if flags.contains(&"--inline") {
+                    ir::inline_calls(&mut module, ir::DEFAULT_INLINE_SIZE);
+                }
+                for function in &mut module.functions {
+                    ir::fold_constants(function);
+                    if flags.contains(&"--remove-dead-code") {
+                        ir::remove_dead_code(function);
+                    }
+                }
+                if flags.contains(&"--before") {
+                    format!("// before\n{}\n// after\n{}", original, module)
+                } else {
+                    module.to_string()
+                }
+            })?;
+            print!("{}", output);
+            Ok(())
+        }
+        "emit-c" => {
+            expect_options(options, &[])?;
+            print!("{}", translate(file, &preprocess(file, &text)?, c::emit)?);
+            Ok(())
+        }
+        "emit-llvm" => {
+            expect_options(options, &[])?;
+            print!(
+                "{}",
+                translate(file, &preprocess(file, &text)?, llvm::emit)?
+            );
+            Ok(())
+        }
+        "emit-asm" => {
+            expect_options(options, &[])?;
+            print!(
+                "{}",
+                translate(file, &preprocess(file, &text)?, x86_64::emit)?
+            );
+            Ok(())
+        }
+        "emit-wasm" => {
+            expect_options(options, &[])?;
+            print!(
+                "{}",
+                translate(file, &preprocess(file, &text)?, wasm::emit)?
+            );
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Parse and type check the files as one program
+fn check(files: &[String]) -> Result<(), String> {
+    let mut sources = SourceMap::new();
+    let mut line_maps = Vec::new();
+    for file in files {
+        let source = preprocess(file, &read_source(file)?)?;
+        line_maps.push((sources.add(file.as_str(), source.text), source.line_map));
+    }
+    // Point a diagnostic about the preprocessed file at the original file and line
+    let relocate = |file: Option<FileId>, message: &str, line: Option<usize>| {
+        let file = file.expect("the files of a source map are known");
+        let (_, line_map) = line_maps.iter().find(|(id, _)| *id == file).unwrap();
+        match line.filter(|&line| line_map.locate(line).is_some()) {
+            Some(line) => line_map.relocate(message, line),
+            None => format!("{}: {}", sources.file(file).name(), message),
+        }
+    };
+    let trees = line_maps
+        .iter()
+        .map(|&(file, _)| {
+            C1Parser::parse_file(&sources, file)
+                .map(|tree| (file, tree))
+                .map_err(|error| relocate(error.file, &error.message, error.line))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let programs: Vec<_> = trees
+        .iter()
+        .map(|(file, tree)| {
+            let program =
+                Program::cast(&tree.root).expect("the root of a syntax tree is a program");
+            (*file, program)
+        })
+        .collect();
+    typeck::check_files(&programs)
+        .map(|_| ())
+        .map_err(|error| relocate(error.file, &error.to_string(), Some(error.line)))
+}
+
+/// Run the preprocessor on the file
+fn preprocess(file: &str, text: &str) -> Result<Preprocessed, String> {
+    preprocessor::preprocess(file, text, &mut FileLoader).map_err(|error| error.to_string())
+}
+
+/// Type check the preprocessed file and translate it with one of the code generators
+fn translate<T>(
+    file: &str,
+    source: &Preprocessed,
+    emit: impl FnOnce(&CheckedProgram) -> T,
+) -> Result<T, String> {
+    let tree = parse(file, source)?;
+    let program = Program::cast(&tree.root).expect("the root of a syntax tree is a program");
+    let checked = typeck::check(program)
+        .map_err(|error| relocate(file, source, &error.to_string(), error.line))?;
+    Ok(emit(&checked))
+}
+
+fn print_tokens(tokens: &[Token]) {
+    for token in tokens {
+        println!("{}", token);
+    }
+}
+
+fn compile(file: &str, source: &Preprocessed) -> Result<Module, String> {
+    let tree = parse(file, source)?;
+    let program = Program::cast(&tree.root).expect("the root of a syntax tree is a program");
+    bytecode::compile(program)
+        .map_err(|error| relocate(file, source, &error.to_string(), error.line))
+}
+
+fn parse<'a>(file: &str, source: &'a Preprocessed) -> Result<SyntaxTree<'a>, String> {
+    C1Parser::parse_tree(&source.text).map_err(|error| match error.line {
+        Some(line) => relocate(file, source, &error.message, line),
+        None => format!("{}: {}", file, error),
+    })
+}
+
+/// Point a diagnostic about the preprocessed file at the original file and line. The message only
+/// starts with the file if the line is not in the preprocessed file, as it names the file otherwise.
+fn relocate(file: &str, source: &Preprocessed, message: &str, line: usize) -> String {
+    match source.line_map.locate(line) {
+        Some(_) => source.line_map.relocate(message, line),
+        None => format!("{}: {}", file, message),
+    }
+}
+
+/// Allow at most one option out of the given ones
+fn expect_options<'a>(options: &'a [String], allowed: &[&str]) -> Result<Option<&'a str>, String> {
+    match options {
+        [] => Ok(None),
+        [option] if allowed.contains(&option.as_str()) => Ok(Some(option)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Allow any of the given options, each at most once
+fn expect_flags<'a>(options: &'a [String], allowed: &[&str]) -> Result<Vec<&'a str>, String> {
+    let mut flags: Vec<&str> = Vec::new();
+    for option in options {
+        if !allowed.contains(&option.as_str()) || flags.contains(&option.as_str()) {
+            return Err(USAGE.to_string());
+        }
+        flags.push(option);
+    }
+    Ok(flags)
+}
+
+fn read_source(file: &str) -> Result<String, String> {
+    if file == "-" {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|error| format!("stdin: {}", error))?;
+        Ok(text)
+    } else {
+        fs::read_to_string(file).map_err(|error| format!("{}: {}", file, error))
+    }
+}
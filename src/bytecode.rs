@@ -0,0 +1,522 @@
+//! # Overview
+//! Compiler from the typed AST to a compact, stack-based bytecode that is executed by the
+//! [`vm`](crate::vm). Every function definition becomes a [`Function`] with its own code and a
+//! table of local variables. Variables are not declared in C(-1); every name that is assigned
+//! somewhere in a function is a local of that function.
+//!
+//! The instructions operate on an operand stack. Jump targets are indices into the code of the
+//! current function, calls refer to functions by their index in the [`Module`].
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::bytecode::{compile, Instruction};
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("int two() { x = 2; return x; }").unwrap();
+//! let module = compile(Program::cast(&tree.root).unwrap()).unwrap();
+//!
+//! assert_eq!(
+//!     module.functions[0].code,
+//!     vec![
+//!         Instruction::PushInt(2),
+//!         Instruction::Store(0),
+//!         Instruction::Load(0),
+//!         Instruction::Return,
+//!         Instruction::ReturnVoid,
+//!     ]
+//! );
+//! ```
+
+use crate::ast::{BinaryOp, Expr, FunctionDefinition, Program, Statement, Type};
+use crate::cst::SyntaxNode;
+use crate::TokenValue;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Instruction {
+    PushInt(i32),
+    PushFloat(f64),
+    PushBool(bool),
+    /// Push the value of a local variable
+    Load(u32),
+    /// Pop a value into a local variable
+    Store(u32),
+    /// Duplicate the topmost value
+    Dup,
+    /// Drop the topmost value
+    Pop,
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Jump(u32),
+    /// Pop a boolean and jump if it is false
+    JumpIfFalse(u32),
+    /// Call the function with the given index and push its result, if any
+    Call(u32),
+    /// Pop the return value and return to the caller
+    Return,
+    /// Return from a function without a value
+    ReturnVoid,
+    /// Pop a value and print it on a line of its own
+    Print,
+}
+
+/// A compiled function
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub return_type: Type,
+    /// Names of the local variables, indexed by slot
+    pub locals: Vec<String>,
+    pub code: Vec<Instruction>,
+    /// Source line of each instruction
+    pub lines: Vec<usize>,
+}
+
+/// A compiled program
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn function_index(&self, name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+    }
+}
+
+/// An error that prevents a syntactically valid program from being compiled
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileError {
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}", self.message, self.line)
+    }
+}
+
+/// Compile all functions of the program
+pub fn compile(program: Program) -> Result<Module, CompileError> {
+    let definitions: Vec<FunctionDefinition> = program.functions().collect();
+    let mut signatures = HashMap::new();
+    for (index, definition) in definitions.iter().enumerate() {
+        let previous =
+            signatures.insert(definition.name(), (index as u32, definition.return_type()));
+        if previous.is_some() {
+            return Err(CompileError {
+                message: format!("function '{}' is defined more than once", definition.name()),
+                line: definition.name_token().line,
+            });
+        }
+    }
+    let functions = definitions
+        .iter()
+        .map(|definition| FunctionCompiler::compile(definition, &signatures))
+        .collect::<Result<_, _>>()?;
+    Ok(Module { functions })
+}
+
+struct FunctionCompiler<'s> {
+    signatures: &'s HashMap<&'s str, (u32, Type)>,
+    return_type: Type,
+    locals: Vec<String>,
+    code: Vec<Instruction>,
+    lines: Vec<usize>,
+    line: usize,
+}
+
+impl<'s> FunctionCompiler<'s> {
+    fn compile(
+        definition: &FunctionDefinition,
+        signatures: &'s HashMap<&'s str, (u32, Type)>,
+    ) -> Result<Function, CompileError> {
+        let mut compiler = FunctionCompiler {
+            signatures,
+            return_type: definition.return_type(),
+            locals: Vec::new(),
+            code: Vec::new(),
+            lines: Vec::new(),
+            line: definition.name_token().line,
+        };
+        collect_locals(definition.body().syntax(), &mut compiler.locals);
+        for statement in definition.body().statements() {
+            compiler.statement(statement)?;
+        }
+        // Falling off the end of a function; an error at runtime unless the function is void
+        compiler.line = definition
+            .syntax()
+            .tokens()
+            .last()
+            .map_or(compiler.line, |token| token.line);
+        compiler.emit(Instruction::ReturnVoid);
+        Ok(Function {
+            name: definition.name().to_string(),
+            return_type: compiler.return_type,
+            locals: compiler.locals,
+            code: compiler.code,
+            lines: compiler.lines,
+        })
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.lines.push(self.line);
+        self.code.len() - 1
+    }
+
+    /// Point the jump at the given position to the next instruction
+    fn patch(&mut self, position: usize) {
+        let target = self.code.len() as u32;
+        match &mut self.code[position] {
+            Instruction::Jump(destination) | Instruction::JumpIfFalse(destination) => {
+                *destination = target
+            }
+            other => unreachable!("{:?} is not a jump", other),
+        }
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError {
+            message,
+            line: self.line,
+        }
+    }
+
+    fn set_line(&mut self, node: &SyntaxNode) {
+        if let Some(token) = node.first_token() {
+            self.line = token.line;
+        }
+    }
+
+    fn local(&self, name: &str) -> Result<u32, CompileError> {
+        match self.locals.iter().position(|local| local == name) {
+            Some(slot) => Ok(slot as u32),
+            None => Err(self.error(format!("variable '{}' is never assigned", name))),
+        }
+    }
+
+    fn statement(&mut self, statement: Statement) -> Result<(), CompileError> {
+        self.set_line(statement.syntax());
+        match statement {
+            Statement::Block(block) => {
+                for statement in block.statements() {
+                    self.statement(statement)?;
+                }
+            }
+            Statement::If(if_statement) => {
+                self.expr(if_statement.condition())?;
+                let skip = self.emit(Instruction::JumpIfFalse(0));
+                self.statement(if_statement.then_branch())?;
+                self.patch(skip);
+            }
+            Statement::Return(ret) => match (ret.value(), self.return_type) {
+                (None, Type::Void) => {
+                    self.emit(Instruction::ReturnVoid);
+                }
+                (None, _) => return Err(self.error("missing return value".to_string())),
+                (Some(_), Type::Void) => {
+                    return Err(self.error("void function cannot return a value".to_string()))
+                }
+                (Some(value), _) => {
+                    self.expr(value)?;
+                    self.emit(Instruction::Return);
+                }
+            },
+            Statement::Printf(printf) => {
+                self.expr(printf.argument())?;
+                self.emit(Instruction::Print);
+            }
+            Statement::Assignment(assignment) => {
+                self.expr(assignment.value())?;
+                let slot = self.local(assignment.target())?;
+                self.emit(Instruction::Store(slot));
+            }
+            Statement::Call(call) => {
+                let (index, return_type) = self.function(call.name())?;
+                self.emit(Instruction::Call(index));
+                if return_type != Type::Void {
+                    self.emit(Instruction::Pop);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&self, name: &str) -> Result<(u32, Type), CompileError> {
+        match self.signatures.get(name) {
+            Some(signature) => Ok(*signature),
+            None => Err(self.error(format!("function '{}' is not defined", name))),
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Result<(), CompileError> {
+        self.set_line(expr.syntax());
+        match expr {
+            Expr::Assignment(assignment) => {
+                self.expr(assignment.value())?;
+                let slot = self.local(assignment.target())?;
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Store(slot));
+            }
+            Expr::Binary(binary) => match binary.operator() {
+                BinaryOp::And => {
+                    // a && b: both operands are only evaluated as far as necessary
+                    self.expr(binary.lhs())?;
+                    let lhs_false = self.emit(Instruction::JumpIfFalse(0));
+                    self.expr(binary.rhs())?;
+                    let rhs_false = self.emit(Instruction::JumpIfFalse(0));
+                    self.emit(Instruction::PushBool(true));
+                    let end = self.emit(Instruction::Jump(0));
+                    self.patch(lhs_false);
+                    self.patch(rhs_false);
+                    self.emit(Instruction::PushBool(false));
+                    self.patch(end);
+                }
+                BinaryOp::Or => {
+                    // a || b == !(!a && !b), spelled out with jumps
+                    self.expr(binary.lhs())?;
+                    let lhs_false = self.emit(Instruction::JumpIfFalse(0));
+                    self.emit(Instruction::PushBool(true));
+                    let lhs_true = self.emit(Instruction::Jump(0));
+                    self.patch(lhs_false);
+                    self.expr(binary.rhs())?;
+                    let rhs_false = self.emit(Instruction::JumpIfFalse(0));
+                    self.emit(Instruction::PushBool(true));
+                    let rhs_true = self.emit(Instruction::Jump(0));
+                    self.patch(rhs_false);
+                    self.emit(Instruction::PushBool(false));
+                    self.patch(lhs_true);
+                    self.patch(rhs_true);
+                }
+                operator => {
+                    self.expr(binary.lhs())?;
+                    self.expr(binary.rhs())?;
+                    self.set_line(binary.syntax());
+                    self.emit(match operator {
+                        BinaryOp::Add => Instruction::Add,
+                        BinaryOp::Sub => Instruction::Sub,
+                        BinaryOp::Mul => Instruction::Mul,
+                        BinaryOp::Div => Instruction::Div,
+                        BinaryOp::Equal => Instruction::Equal,
+                        BinaryOp::NotEqual => Instruction::NotEqual,
+                        BinaryOp::Less => Instruction::Less,
+                        BinaryOp::LessEqual => Instruction::LessEqual,
+                        BinaryOp::Greater => Instruction::Greater,
+                        BinaryOp::GreaterEqual => Instruction::GreaterEqual,
+                        BinaryOp::And | BinaryOp::Or => unreachable!(),
+                    });
+                }
+            },
+            Expr::Unary(unary) => {
+                self.expr(unary.operand())?;
+                self.emit(Instruction::Neg);
+            }
+            Expr::Literal(literal) => {
+                let instruction = match literal.value() {
+                    TokenValue::Int(value) => i32::try_from(value)
+                        .map(Instruction::PushInt)
+                        .map_err(|_| {
+                            self.error(format!("integer literal {} is too large", literal.text()))
+                        })?,
+                    TokenValue::Float(value) => Instruction::PushFloat(value),
+                    TokenValue::Bool(value) => Instruction::PushBool(value),
+                };
+                self.emit(instruction);
+            }
+            Expr::Name(name) => {
+                let slot = self.local(name.name())?;
+                self.emit(Instruction::Load(slot));
+            }
+            Expr::Call(call) => {
+                let (index, return_type) = self.function(call.name())?;
+                if return_type == Type::Void {
+                    return Err(self.error(format!(
+                        "void function '{}' cannot be used as a value",
+                        call.name()
+                    )));
+                }
+                self.emit(Instruction::Call(index));
+            }
+            Expr::Paren(paren) => self.expr(paren.inner())?,
+        }
+        Ok(())
+    }
+}
+
+/// Collect the targets of all assignments below the node, in order of appearance
+fn collect_locals(node: &SyntaxNode, locals: &mut Vec<String>) {
+    use crate::ast::{Assignment, StatAssignment};
+    let target = StatAssignment::cast(node)
+        .map(|assignment| assignment.target())
+        .or_else(|| Assignment::cast(node).map(|assignment| assignment.target()));
+    if let Some(target) = target {
+        if !locals.iter().any(|local| local == target) {
+            locals.push(target.to_string());
+        }
+    }
+    for child in node.child_nodes() {
+        collect_locals(child, locals);
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::PushInt(value) => write!(f, "push_int {}", value),
+            Instruction::PushFloat(value) => write!(f, "push_float {:?}", value),
+            Instruction::PushBool(value) => write!(f, "push_bool {}", value),
+            Instruction::Load(slot) => write!(f, "load {}", slot),
+            Instruction::Store(slot) => write!(f, "store {}", slot),
+            Instruction::Dup => f.write_str("dup"),
+            Instruction::Pop => f.write_str("pop"),
+            Instruction::Neg => f.write_str("neg"),
+            Instruction::Add => f.write_str("add"),
+            Instruction::Sub => f.write_str("sub"),
+            Instruction::Mul => f.write_str("mul"),
+            Instruction::Div => f.write_str("div"),
+            Instruction::Equal => f.write_str("eq"),
+            Instruction::NotEqual => f.write_str("ne"),
+            Instruction::Less => f.write_str("lt"),
+            Instruction::LessEqual => f.write_str("le"),
+            Instruction::Greater => f.write_str("gt"),
+            Instruction::GreaterEqual => f.write_str("ge"),
+            Instruction::Jump(target) => write!(f, "jump {:04}", target),
+            Instruction::JumpIfFalse(target) => write!(f, "jump_if_false {:04}", target),
+            Instruction::Call(index) => write!(f, "call {}", index),
+            Instruction::Return => f.write_str("ret"),
+            Instruction::ReturnVoid => f.write_str("ret_void"),
+            Instruction::Print => f.write_str("print"),
+        }
+    }
+}
+
+/// Produce a human readable listing of the module
+pub fn disassemble(module: &Module) -> String {
+    let mut listing = String::new();
+    for (index, function) in module.functions.iter().enumerate() {
+        if index > 0 {
+            listing.push('\n');
+        }
+        listing.push_str(&format!(
+            "function {} {} {}()\n",
+            index, function.return_type, function.name
+        ));
+        for (slot, local) in function.locals.iter().enumerate() {
+            listing.push_str(&format!("    local {} {}\n", slot, local));
+        }
+        let mut previous_line = None;
+        for (position, instruction) in function.code.iter().enumerate() {
+            let mut text = format!("    {:04}  {}", position, instruction);
+            // Annotate operands with names and the first instruction of each source line
+            let comment = match instruction {
+                Instruction::Load(slot) | Instruction::Store(slot) => {
+                    Some(function.locals[*slot as usize].clone())
+                }
+                Instruction::Call(callee) => {
+                    Some(format!("{}()", module.functions[*callee as usize].name))
+                }
+                _ => None,
+            };
+            let line = function.lines[position];
+            let comment = match (comment, previous_line != Some(line)) {
+                (Some(comment), true) => Some(format!("{}, line {}", comment, line)),
+                (None, true) => Some(format!("line {}", line)),
+                (comment, false) => comment,
+            };
+            if let Some(comment) = comment {
+                text = format!("{:<32}; {}", text, comment);
+            }
+            previous_line = Some(line);
+            listing.push_str(&text);
+            listing.push('\n');
+        }
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::bytecode::{compile, disassemble, CompileError, Instruction, Module};
+    use crate::C1Parser;
+
+    fn compile_text(text: &str) -> Result<Module, CompileError> {
+        let tree = C1Parser::parse_tree(text).unwrap();
+        compile(Program::cast(&tree.root).unwrap())
+    }
+
+    #[test]
+    fn compile_example() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let module = compile_text(&text).unwrap();
+        let names: Vec<&str> = module.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["blub", "blah", "main"]);
+        assert_eq!(
+            module.functions[0].locals,
+            vec!["blub1", "blub2", "blub3", "blub4"]
+        );
+        assert_eq!(module.function_index("main"), Some(2));
+        for function in &module.functions {
+            assert_eq!(function.code.len(), function.lines.len());
+        }
+    }
+
+    #[test]
+    fn if_and_calls() {
+        let module = compile_text("void f() { if (true) g(); }\nint g() { return 1; }").unwrap();
+        assert_eq!(
+            module.functions[0].code,
+            vec![
+                Instruction::PushBool(true),
+                Instruction::JumpIfFalse(4),
+                Instruction::Call(1),
+                Instruction::Pop,
+                Instruction::ReturnVoid,
+            ]
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let module = compile_text("int f() {\n  x = 1;\n  return x + 2;\n}").unwrap();
+        assert_eq!(
+            disassemble(&module),
+            "function 0 int f()\n\
+             \x20   local 0 x\n\
+             \x20   0000  push_int 1            ; line 2\n\
+             \x20   0001  store 0               ; x\n\
+             \x20   0002  load 0                ; x, line 3\n\
+             \x20   0003  push_int 2\n\
+             \x20   0004  add\n\
+             \x20   0005  ret\n\
+             \x20   0006  ret_void              ; line 4\n"
+        );
+    }
+
+    #[test]
+    fn compile_errors() {
+        let error = compile_text("void f() {\n x = y;\n}").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(compile_text("void f() { g(); }").is_err());
+        assert!(compile_text("void f() {} void f() {}").is_err());
+        assert!(compile_text("void f() { return 1; }").is_err());
+        assert!(compile_text("int f() { return; }").is_err());
+        assert!(compile_text("void f() {} int g() { return f(); }").is_err());
+        assert!(compile_text("int f() { return 99999999999; }").is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! # Overview
+//! Translation of a type-checked program to portable C99 source.
+//!
+//! C(-1) is almost a subset of C, so the translation mostly prints the program again. The
+//! differences are taken care of here:
+//! - The implicit local variables are declared at the start of each function.
+//! - `float` becomes `double`, matching the precision of the other backends, and `bool` comes from
+//!   `<stdbool.h>`.
+//! - `printf(expr)` gets a format string for the type of `expr`; booleans print `true` or `false`.
+//! - All functions are declared before the first definition, so they can call each other in any
+//!   order.
+//! - Expressions are parenthesized where the precedence of C differs. In C(-1), `&&` binds like
+//!   `*` and `||` like `+`, and both bind tighter than the comparisons.
+//! - Every name gets the prefix `c1_`, so that it cannot clash with a keyword of C or with a
+//!   name from `<stdio.h>` or `<stdbool.h>` such as `puts` or `EOF`. Only `main` keeps its name.
+//! - `int` addition, subtraction, multiplication and negation are done on `unsigned` and cast
+//!   back, and division goes through a helper that treats a divisor of -1 as negation. So they
+//!   wrap around like in the other backends instead of overflowing, which C leaves undefined.
+//! - `void main()` becomes `int main(void)` returning 0, and a function that reaches its end
+//!   without a `return` returns zero like in the other backends.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::codegen::c;
+//! use cb_3::typeck::check;
+//! use cb_3::C1Parser;
+//!
+//! let text = "void main() { x = true == false && false; printf(x); }";
+//! let tree = C1Parser::parse_tree(text).unwrap();
+//! let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+//!
+//! assert!(c::emit(&checked).ends_with(
+//!     "int main(void) {\n\
+//!     \tbool c1_x;\n\
+//!     \tc1_x = true == (false && false);\n\
+//!     \tprintf(\"%s\\n\", c1_x ? \"true\" : \"false\");\n\
+//!     \treturn 0;\n\
+//!     }\n"
+//! ));
+//! ```
+
+use crate::ast::{BinaryOp, Expr, Statement, Type};
+use crate::codegen::{constant, Constant};
+use crate::typeck::{CheckedProgram, FunctionInfo};
+use std::fmt::Write;
+
+/// The helper for integer division. `INT_MIN / -1` overflows, which C leaves undefined, so the
+/// helper negates through `unsigned` for a divisor of -1 to wrap around like the other backends.
+/// Its name cannot clash with the names of the program, as they all start with `c1_`.
+const WRAPPING_DIV: &str = "wrapping_div";
+
+/// Translate the program into a C source file
+pub fn emit(program: &CheckedProgram) -> String {
+    let mut declarations = String::from("#include <stdbool.h>\n#include <stdio.h>\n\n");
+    for function in program.functions() {
+        writeln!(declarations, "{};", signature(function)).unwrap();
+    }
+    let mut source = String::new();
+    let mut divides = false;
+    for (definition, function) in program.definitions() {
+        writeln!(source, "\n{} {{", signature(function)).unwrap();
+        for local in &function.locals {
+            writeln!(source, "\t{} {};", c_type(local.ty), name(&local.name)).unwrap();
+        }
+        let mut emitter = FunctionEmitter {
+            program,
+            function,
+            source: &mut source,
+            indent: 1,
+            divides: false,
+        };
+        let mut returns = false;
+        for statement in definition.body().statements() {
+            returns = matches!(statement, Statement::Return(_));
+            emitter.statement(statement);
+        }
+        if !returns {
+            if let Some(zero) = zero(function) {
+                emitter.line(&format!("return {};", zero));
+            }
+        }
+        divides |= emitter.divides;
+        source.push_str("}\n");
+    }
+    if divides {
+        writeln!(
+            declarations,
+            "\nstatic int {}(int lhs, int rhs) {{\n\
+             \treturn rhs == -1 ? (int)-(unsigned)lhs : lhs / rhs;\n\
+             }}",
+            WRAPPING_DIV
+        )
+        .unwrap();
+    }
+    declarations + &source
+}
+
+fn c_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Bool => "bool",
+        Type::Float => "double",
+        Type::Int => "int",
+        Type::Void => "void",
+    }
+}
+
+/// The name of a variable in C, which cannot clash with the keywords of C or the names of the
+/// included headers
+fn name(name: &str) -> String {
+    format!("c1_{}", name)
+}
+
+/// The name of a function in C. `main` stays the entry point.
+fn function_name(function: &str) -> String {
+    if function == "main" {
+        function.to_string()
+    } else {
+        name(function)
+    }
+}
+
+fn is_void_main(function: &FunctionInfo) -> bool {
+    function.name == "main" && function.return_type == Type::Void
+}
+
+fn signature(function: &FunctionInfo) -> String {
+    let return_type = if is_void_main(function) {
+        "int"
+    } else {
+        c_type(function.return_type)
+    };
+    format!("{} {}(void)", return_type, function_name(&function.name))
+}
+
+/// The value returned when a function reaches its end
+fn zero(function: &FunctionInfo) -> Option<&'static str> {
+    match function.return_type {
+        _ if is_void_main(function) => Some("0"),
+        Type::Bool => Some("false"),
+        Type::Float => Some("0.0"),
+        Type::Int => Some("0"),
+        Type::Void => None,
+    }
+}
+
+/// Precedence levels of the C operators, from loosest to tightest
+const ASSIGNMENT: u8 = 0;
+const PRIMARY: u8 = 8;
+
+fn precedence(operator: BinaryOp) -> u8 {
+    match operator {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Equal | BinaryOp::NotEqual => 3,
+        BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => 4,
+        BinaryOp::Add | BinaryOp::Sub => 5,
+        BinaryOp::Mul | BinaryOp::Div => 6,
+    }
+}
+
+fn symbol(operator: BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Or => "||",
+        BinaryOp::And => "&&",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::Less => "<",
+        BinaryOp::Greater => ">",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::GreaterEqual => ">=",
+    }
+}
+
+struct FunctionEmitter<'p, 's> {
+    program: &'p CheckedProgram<'p>,
+    function: &'p FunctionInfo,
+    source: &'s mut String,
+    /// Indentation of the next line, in tabs
+    indent: usize,
+    /// Whether the function divides integers, which needs the [`WRAPPING_DIV`] helper
+    divides: bool,
+}
+
+impl<'p, 's> FunctionEmitter<'p, 's> {
+    fn line(&mut self, text: &str) {
+        writeln!(self.source, "{}{}", "\t".repeat(self.indent), text).unwrap();
+    }
+
+    fn statement(&mut self, statement: Statement) {
+        match statement {
+            Statement::Block(block) => {
+                self.line("{");
+                self.indent += 1;
+                for statement in block.statements() {
+                    self.statement(statement);
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            Statement::If(if_statement) => {
+                let condition = self.expr(if_statement.condition(), ASSIGNMENT);
+                // The branch is always a block in C, even if it is not one in C(-1)
+                let statements: Vec<Statement> = match if_statement.then_branch() {
+                    Statement::Block(block) => block.statements().collect(),
+                    statement => vec![statement],
+                };
+                self.line(&format!("if ({}) {{", condition));
+                self.indent += 1;
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            Statement::Return(ret) => match ret.value() {
+                Some(value) => {
+                    let value = self.expr(value, ASSIGNMENT);
+                    self.line(&format!("return {};", value));
+                }
+                None if is_void_main(self.function) => self.line("return 0;"),
+                None => self.line("return;"),
+            },
+            Statement::Printf(printf) => {
+                let argument = printf.argument();
+                let line = match self.program.expr_type(self.function, argument) {
+                    Type::Int => format!("printf(\"%d\\n\", {});", self.expr(argument, ASSIGNMENT)),
+                    Type::Float => {
+                        format!("printf(\"%f\\n\", {});", self.expr(argument, ASSIGNMENT))
+                    }
+                    _ => format!(
+                        "printf(\"%s\\n\", {} ? \"true\" : \"false\");",
+                        self.expr(argument, PRIMARY)
+                    ),
+                };
+                self.line(&line);
+            }
+            Statement::Assignment(assignment) => {
+                let value = self.expr(assignment.value(), ASSIGNMENT);
+                self.line(&format!("{} = {};", name(assignment.target()), value));
+            }
+            Statement::Call(call) => self.line(&format!("{}();", function_name(call.name()))),
+        }
+    }
+
+    /// Format an expression that appears where C expects at least the given precedence
+    fn expr(&mut self, expr: Expr, context: u8) -> String {
+        let (text, precedence) = match expr {
+            Expr::Assignment(assignment) => (
+                format!(
+                    "{} = {}",
+                    name(assignment.target()),
+                    self.expr(assignment.value(), ASSIGNMENT)
+                ),
+                ASSIGNMENT,
+            ),
+            Expr::Binary(binary) => {
+                let operator = binary.operator();
+                let int = self.program.expr_type(self.function, expr) == Type::Int;
+                match operator {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul if int => {
+                        let lhs = self.expr(binary.lhs(), PRIMARY - 1);
+                        let rhs = self.expr(binary.rhs(), PRIMARY - 1);
+                        let text = format!(
+                            "(int)((unsigned){} {} (unsigned){})",
+                            lhs,
+                            symbol(operator),
+                            rhs
+                        );
+                        (text, PRIMARY - 1)
+                    }
+                    BinaryOp::Div if int => {
+                        self.divides = true;
+                        let lhs = self.expr(binary.lhs(), ASSIGNMENT);
+                        let rhs = self.expr(binary.rhs(), ASSIGNMENT);
+                        (format!("{}({}, {})", WRAPPING_DIV, lhs, rhs), PRIMARY)
+                    }
+                    _ => {
+                        let precedence = precedence(operator);
+                        // All binary operators are left associative
+                        let lhs = self.expr(binary.lhs(), precedence);
+                        let rhs = self.expr(binary.rhs(), precedence + 1);
+                        (format!("{} {} {}", lhs, symbol(operator), rhs), precedence)
+                    }
+                }
+            }
+            Expr::Unary(unary) if self.program.expr_type(self.function, expr) == Type::Int => {
+                let operand = self.expr(unary.operand(), PRIMARY - 1);
+                (format!("(int)-(unsigned){}", operand), PRIMARY - 1)
+            }
+            Expr::Unary(unary) => {
+                // A nested minus must not turn into a decrement
+                let operand = match unary.operand() {
+                    Expr::Unary(_) => format!("({})", self.expr(unary.operand(), ASSIGNMENT)),
+                    operand => self.expr(operand, PRIMARY - 1),
+                };
+                (format!("-{}", operand), PRIMARY - 1)
+            }
+            Expr::Literal(literal) => {
+                let text = match constant(literal) {
+                    // Leading zeros would make an integer octal in C
+                    Constant::Int(value) => value.to_string(),
+                    Constant::Float(_) => literal.text().replace('_', ""),
+                    Constant::Bool(_) => literal.text().to_string(),
+                };
+                (text, PRIMARY)
+            }
+            Expr::Name(reference) => (name(reference.name()), PRIMARY),
+            Expr::Call(call) => (format!("{}()", function_name(call.name())), PRIMARY),
+            Expr::Paren(paren) => (
+                format!("({})", self.expr(paren.inner(), ASSIGNMENT)),
+                PRIMARY,
+            ),
+        };
+        if precedence < context {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::codegen::c::emit;
+    use crate::typeck::check;
+    use crate::C1Parser;
+
+    fn source(text: &str) -> String {
+        let tree = C1Parser::parse_tree(text).unwrap();
+        emit(&check(Program::cast(&tree.root).unwrap()).unwrap())
+    }
+
+    fn body_of_main(text: &str) -> String {
+        let source = source(text);
+        let start = source.rfind("int main(void) {\n").unwrap();
+        source[start..]
+            .lines()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn expressions_keep_their_meaning() {
+        assert_eq!(
+            body_of_main(
+                "void main() { a = 010; b = -(-a); c = a - (b - 1.0) * 2; d = true; d = d == d || false; }"
+            ),
+            "\tint c1_a;\n\
+             \tint c1_b;\n\
+             \tdouble c1_c;\n\
+             \tbool c1_d;\n\
+             \tc1_a = 10;\n\
+             \tc1_b = (int)-(unsigned)((int)-(unsigned)c1_a);\n\
+             \tc1_c = c1_a - (c1_b - 1.0) * 2;\n\
+             \tc1_d = true;\n\
+             \tc1_d = c1_d == (c1_d || false);\n\
+             \treturn 0;\n\
+             }"
+        );
+    }
+
+    #[test]
+    fn names_get_a_prefix() {
+        assert_eq!(
+            body_of_main(
+                "void puts() {} void main() { EOF = 1; double = EOF; puts(); if (double > 0) return; }"
+            ),
+            "\tint c1_EOF;\n\
+             \tint c1_double;\n\
+             \tc1_EOF = 1;\n\
+             \tc1_double = c1_EOF;\n\
+             \tc1_puts();\n\
+             \tif (c1_double > 0) {\n\
+             \t\treturn 0;\n\
+             \t}\n\
+             \treturn 0;\n\
+             }"
+        );
+    }
+
+    #[test]
+    fn int_arithmetic_wraps() {
+        assert_eq!(
+            body_of_main("void main() { printf(2147483647 + 1); a = -1 * 2 - 3; }"),
+            "\tint c1_a;\n\
+             \tprintf(\"%d\\n\", (int)((unsigned)2147483647 + (unsigned)1));\n\
+             \tc1_a = (int)((unsigned)(int)-(unsigned)(int)((unsigned)1 * (unsigned)2) - (unsigned)3);\n\
+             \treturn 0;\n\
+             }"
+        );
+    }
+
+    #[test]
+    fn int_division_wraps() {
+        let divisions = source(
+            "void main() { a = 0 - 2147483647 - 1; b = 0 - 1; printf(a / b); c = a / 2.0; }",
+        );
+        assert!(divisions.contains(
+            "static int wrapping_div(int lhs, int rhs) {\n\
+             \treturn rhs == -1 ? (int)-(unsigned)lhs : lhs / rhs;\n\
+             }\n"
+        ));
+        assert!(divisions.contains("printf(\"%d\\n\", wrapping_div(c1_a, c1_b));"));
+        assert!(divisions.contains("c1_c = c1_a / 2.0;"));
+        // The helper is only there when it is needed
+        assert!(!source("void main() { printf(1 + 2.0 / 2); }").contains("wrapping_div"));
+    }
+}
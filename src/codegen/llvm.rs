@@ -0,0 +1,484 @@
+//! # Overview
+//! Lowering of a type-checked program to textual LLVM IR, as read by `llc`, `lli` or `clang`.
+//! Pointers are opaque `ptr`s, which LLVM understands by default since version 15.
+//!
+//! `int`, `float` and `bool` become `i32`, `double` and `i1`. Every local variable gets an
+//! `alloca` in the entry block and is read and written with `load` and `store`, leaving the
+//! promotion to registers to LLVM's `mem2reg`. `if` statements and the short-circuit operators
+//! `&&` and `||` become basic blocks. `printf` calls the `printf` of the C library with `%d` or
+//! `%f` as format; booleans are printed as `true` or `false`. A function that reaches its end
+//! without a `return` returns zero. `void main()` becomes `i32 @main()` returning 0, so that the
+//! exit code of a native program is 0.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::codegen::llvm;
+//! use cb_3::typeck::check;
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("int one() { return 1; }").unwrap();
+//! let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+//!
+//! assert_eq!(llvm::emit(&checked), "define i32 @one() {\nentry:\n  ret i32 1\n}\n");
+//! ```
+
+use crate::ast::{BinaryOp, Expr, Statement, Type};
+use crate::codegen::{constant, Constant};
+use crate::typeck::{CheckedProgram, FunctionInfo};
+use std::fmt::Write;
+
+/// Translate the program into an LLVM module
+pub fn emit(program: &CheckedProgram) -> String {
+    let mut formats = Formats::default();
+    let functions: Vec<String> = program
+        .definitions()
+        .map(|(definition, function)| {
+            let mut emitter = FunctionEmitter {
+                program,
+                function,
+                formats: &mut formats,
+                body: String::new(),
+                next_temp: 0,
+                next_label: 0,
+                block: "entry".to_string(),
+                terminated: false,
+            };
+            for local in &function.locals {
+                emitter.instruction(format!(
+                    "%{}.addr = alloca {}",
+                    local.name,
+                    llvm_type(local.ty)
+                ));
+            }
+            for statement in definition.body().statements() {
+                emitter.statement(statement);
+            }
+            if !emitter.terminated {
+                emitter.terminate(return_zero(function));
+            }
+            format!(
+                "define {} @{}() {{\nentry:\n{}}}\n",
+                llvm_type(return_type(function)),
+                function.name,
+                emitter.body
+            )
+        })
+        .collect();
+
+    let mut module = String::new();
+    if formats.int {
+        module.push_str("@.fmt.int = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\"\n");
+    }
+    if formats.float {
+        module.push_str("@.fmt.float = private unnamed_addr constant [4 x i8] c\"%f\\0A\\00\"\n");
+    }
+    if formats.bool {
+        module.push_str("@.fmt.bool = private unnamed_addr constant [4 x i8] c\"%s\\0A\\00\"\n");
+        module.push_str("@.str.true = private unnamed_addr constant [5 x i8] c\"true\\00\"\n");
+        module.push_str("@.str.false = private unnamed_addr constant [6 x i8] c\"false\\00\"\n");
+    }
+    if formats.int || formats.float || formats.bool {
+        module.push_str("\ndeclare i32 @printf(ptr, ...)\n\n");
+    }
+    module.push_str(&functions.join("\n"));
+    module
+}
+
+/// The format strings that are used by the program
+#[derive(Default)]
+struct Formats {
+    int: bool,
+    float: bool,
+    bool: bool,
+}
+
+fn llvm_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Bool => "i1",
+        Type::Float => "double",
+        Type::Int => "i32",
+        Type::Void => "void",
+    }
+}
+
+fn is_void_main(function: &FunctionInfo) -> bool {
+    function.name == "main" && function.return_type == Type::Void
+}
+
+/// The return type of the function in LLVM, which is `int` for `void main()`
+fn return_type(function: &FunctionInfo) -> Type {
+    if is_void_main(function) {
+        Type::Int
+    } else {
+        function.return_type
+    }
+}
+
+/// The return of a function that reaches its end or returns without a value
+fn return_zero(function: &FunctionInfo) -> String {
+    match return_type(function) {
+        Type::Void => "ret void".to_string(),
+        ty => format!("ret {} {}", llvm_type(ty), zero(ty)),
+    }
+}
+
+fn zero(ty: Type) -> &'static str {
+    match ty {
+        Type::Bool => "false",
+        Type::Float => "0.0",
+        Type::Int | Type::Void => "0",
+    }
+}
+
+/// Format a double constant. LLVM only accepts decimal constants that are exactly representable,
+/// so everything except small integral values is written as the hexadecimal bit pattern.
+fn float_constant(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:?}", value)
+    } else {
+        format!("0x{:016X}", value.to_bits())
+    }
+}
+
+struct FunctionEmitter<'p, 'f> {
+    program: &'p CheckedProgram<'p>,
+    function: &'p FunctionInfo,
+    formats: &'f mut Formats,
+    body: String,
+    next_temp: usize,
+    next_label: usize,
+    /// Label of the current basic block
+    block: String,
+    /// Whether the current block already ends with a terminator
+    terminated: bool,
+}
+
+impl<'p, 'f> FunctionEmitter<'p, 'f> {
+    fn temp(&mut self) -> String {
+        self.next_temp += 1;
+        format!("%t{}", self.next_temp - 1)
+    }
+
+    /// Number for the labels of the blocks of the next statement or expression
+    fn next_label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label - 1
+    }
+
+    fn instruction(&mut self, text: String) {
+        if self.terminated {
+            // Code after a return is unreachable but still needs a block
+            let label = format!("dead{}", self.next_label());
+            self.start_block(&label);
+        }
+        writeln!(self.body, "  {}", text).unwrap();
+    }
+
+    /// Emit an instruction that defines a new temporary and return the temporary
+    fn value(&mut self, text: String) -> String {
+        let temp = self.temp();
+        self.instruction(format!("{} = {}", temp, text));
+        temp
+    }
+
+    fn terminate(&mut self, text: String) {
+        self.instruction(text);
+        self.terminated = true;
+    }
+
+    /// Start a new basic block, falling through from the current one
+    fn start_block(&mut self, label: &str) {
+        if !self.terminated {
+            writeln!(self.body, "  br label %{}", label).unwrap();
+        }
+        writeln!(self.body, "{}:", label).unwrap();
+        self.block = label.to_string();
+        self.terminated = false;
+    }
+
+    fn expr_type(&self, expr: Expr) -> Type {
+        self.program.expr_type(self.function, expr)
+    }
+
+    fn convert(&mut self, value: String, from: Type, to: Type) -> String {
+        match (from, to) {
+            (Type::Int, Type::Float) => match value.parse::<i32>() {
+                // Integer constants are converted right away
+                Ok(constant) => float_constant(constant as f64),
+                Err(_) => self.value(format!("sitofp i32 {} to double", value)),
+            },
+            (Type::Float, Type::Int) => self.value(format!("fptosi double {} to i32", value)),
+            _ => value,
+        }
+    }
+
+    fn store(&mut self, name: &str, value: String, ty: Type) {
+        let local_type = self
+            .function
+            .local(name)
+            .expect("variables were checked")
+            .ty;
+        let value = self.convert(value, ty, local_type);
+        self.instruction(format!(
+            "store {} {}, ptr %{}.addr",
+            llvm_type(local_type),
+            value,
+            name
+        ));
+    }
+
+    fn statement(&mut self, statement: Statement) {
+        match statement {
+            Statement::Block(block) => {
+                for statement in block.statements() {
+                    self.statement(statement);
+                }
+            }
+            Statement::If(if_statement) => {
+                let condition = self.expr(if_statement.condition());
+                let number = self.next_label();
+                let then_label = format!("if.then{}", number);
+                let end_label = format!("if.end{}", number);
+                self.terminate(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, then_label, end_label
+                ));
+                self.start_block(&then_label);
+                self.statement(if_statement.then_branch());
+                self.start_block(&end_label);
+            }
+            Statement::Return(ret) => match ret.value() {
+                None => self.terminate(return_zero(self.function)),
+                Some(value) => {
+                    let ty = self.expr_type(value);
+                    let value = self.expr(value);
+                    let return_type = self.function.return_type;
+                    let value = self.convert(value, ty, return_type);
+                    self.terminate(format!("ret {} {}", llvm_type(return_type), value));
+                }
+            },
+            Statement::Printf(printf) => {
+                let ty = self.expr_type(printf.argument());
+                let value = self.expr(printf.argument());
+                let (format, argument) = match ty {
+                    Type::Int => {
+                        self.formats.int = true;
+                        ("@.fmt.int", format!("i32 {}", value))
+                    }
+                    Type::Float => {
+                        self.formats.float = true;
+                        ("@.fmt.float", format!("double {}", value))
+                    }
+                    _ => {
+                        self.formats.bool = true;
+                        let text = self.value(format!(
+                            "select i1 {}, ptr @.str.true, ptr @.str.false",
+                            value
+                        ));
+                        ("@.fmt.bool", format!("ptr {}", text))
+                    }
+                };
+                self.value(format!(
+                    "call i32 (ptr, ...) @printf(ptr {}, {})",
+                    format, argument
+                ));
+            }
+            Statement::Assignment(assignment) => {
+                let ty = self.expr_type(assignment.value());
+                let value = self.expr(assignment.value());
+                self.store(assignment.target(), value, ty);
+            }
+            Statement::Call(call) => {
+                let function = self
+                    .program
+                    .function(call.name())
+                    .expect("called functions were checked");
+                self.instruction(format!(
+                    "call {} @{}()",
+                    llvm_type(return_type(function)),
+                    call.name()
+                ));
+            }
+        }
+    }
+
+    /// Emit the code for an expression and return the operand that holds its value
+    fn expr(&mut self, expr: Expr) -> String {
+        match expr {
+            Expr::Assignment(assignment) => {
+                let ty = self.expr_type(assignment.value());
+                let value = self.expr(assignment.value());
+                let local_type = self.expr_type(expr);
+                let value = self.convert(value, ty, local_type);
+                self.store(assignment.target(), value.clone(), local_type);
+                value
+            }
+            Expr::Binary(binary) => match binary.operator() {
+                operator @ (BinaryOp::And | BinaryOp::Or) => {
+                    // The right operand is only evaluated if the left one does not decide
+                    let lhs = self.expr(binary.lhs());
+                    let lhs_block = self.block.clone();
+                    let name = if operator == BinaryOp::And {
+                        "and"
+                    } else {
+                        "or"
+                    };
+                    let number = self.next_label();
+                    let rhs_label = format!("{}.rhs{}", name, number);
+                    let end_label = format!("{}.end{}", name, number);
+                    let (on_true, on_false, short_value) = if operator == BinaryOp::And {
+                        (&rhs_label, &end_label, "false")
+                    } else {
+                        (&end_label, &rhs_label, "true")
+                    };
+                    self.terminate(format!(
+                        "br i1 {}, label %{}, label %{}",
+                        lhs, on_true, on_false
+                    ));
+                    self.start_block(&rhs_label);
+                    let rhs = self.expr(binary.rhs());
+                    let rhs_block = self.block.clone();
+                    self.start_block(&end_label);
+                    self.value(format!(
+                        "phi i1 [ {}, %{} ], [ {}, %{} ]",
+                        short_value, lhs_block, rhs, rhs_block
+                    ))
+                }
+                operator => {
+                    let lhs_type = self.expr_type(binary.lhs());
+                    let rhs_type = self.expr_type(binary.rhs());
+                    let lhs = self.expr(binary.lhs());
+                    let rhs = self.expr(binary.rhs());
+                    let operand_type = match (lhs_type, rhs_type) {
+                        (Type::Int, Type::Int) => Type::Int,
+                        (Type::Bool, Type::Bool) => Type::Bool,
+                        _ => Type::Float,
+                    };
+                    let lhs = self.convert(lhs, lhs_type, operand_type);
+                    let rhs = self.convert(rhs, rhs_type, operand_type);
+                    let opcode = match operand_type {
+                        Type::Float => float_opcode(operator),
+                        _ => int_opcode(operator),
+                    };
+                    self.value(format!(
+                        "{} {} {}, {}",
+                        opcode,
+                        llvm_type(operand_type),
+                        lhs,
+                        rhs
+                    ))
+                }
+            },
+            Expr::Unary(unary) => {
+                let operand = self.expr(unary.operand());
+                match self.expr_type(unary.operand()) {
+                    Type::Float => self.value(format!("fneg double {}", operand)),
+                    _ => self.value(format!("sub i32 0, {}", operand)),
+                }
+            }
+            Expr::Literal(literal) => match constant(literal) {
+                Constant::Int(value) => value.to_string(),
+                Constant::Float(value) => float_constant(value),
+                Constant::Bool(value) => value.to_string(),
+            },
+            Expr::Name(name) => {
+                let ty = self.expr_type(expr);
+                self.value(format!("load {}, ptr %{}.addr", llvm_type(ty), name.name()))
+            }
+            Expr::Call(call) => {
+                let ty = self.expr_type(expr);
+                self.value(format!("call {} @{}()", llvm_type(ty), call.name()))
+            }
+            Expr::Paren(paren) => self.expr(paren.inner()),
+        }
+    }
+}
+
+fn int_opcode(operator: BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "add",
+        BinaryOp::Sub => "sub",
+        BinaryOp::Mul => "mul",
+        BinaryOp::Div => "sdiv",
+        BinaryOp::Equal => "icmp eq",
+        BinaryOp::NotEqual => "icmp ne",
+        BinaryOp::Less => "icmp slt",
+        BinaryOp::LessEqual => "icmp sle",
+        BinaryOp::Greater => "icmp sgt",
+        BinaryOp::GreaterEqual => "icmp sge",
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+    }
+}
+
+fn float_opcode(operator: BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "fadd",
+        BinaryOp::Sub => "fsub",
+        BinaryOp::Mul => "fmul",
+        BinaryOp::Div => "fdiv",
+        BinaryOp::Equal => "fcmp oeq",
+        BinaryOp::NotEqual => "fcmp une",
+        BinaryOp::Less => "fcmp olt",
+        BinaryOp::LessEqual => "fcmp ole",
+        BinaryOp::Greater => "fcmp ogt",
+        BinaryOp::GreaterEqual => "fcmp oge",
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::codegen::llvm::{emit, float_constant};
+    use crate::typeck::check;
+    use crate::C1Parser;
+
+    #[test]
+    fn float_constants() {
+        assert_eq!(float_constant(2.0), "2.0");
+        assert_eq!(float_constant(-0.0), "-0.0");
+        assert_eq!(float_constant(0.1), "0x3FB999999999999A");
+    }
+
+    #[test]
+    fn code_after_return() {
+        let tree = C1Parser::parse_tree("int f() { return 1; x = 2; }").unwrap();
+        let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+        assert_eq!(
+            emit(&checked),
+            "define i32 @f() {\n\
+             entry:\n\
+             \x20 %x.addr = alloca i32\n\
+             \x20 ret i32 1\n\
+             dead0:\n\
+             \x20 store i32 2, ptr %x.addr\n\
+             \x20 ret i32 0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn void_main() {
+        let text = "void f() { main(); } void main() { if (true) return; f(); }";
+        let tree = C1Parser::parse_tree(text).unwrap();
+        let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+        assert_eq!(
+            emit(&checked),
+            "define void @f() {\n\
+             entry:\n\
+             \x20 call i32 @main()\n\
+             \x20 ret void\n\
+             }\n\n\
+             define i32 @main() {\n\
+             entry:\n\
+             \x20 br i1 true, label %if.then0, label %if.end0\n\
+             if.then0:\n\
+             \x20 ret i32 0\n\
+             if.end0:\n\
+             \x20 call void @f()\n\
+             \x20 ret i32 0\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,16 @@
+//! # Overview
+//! Code generators that translate a [type-checked](crate::typeck) program into other languages.
+//! Integers are 32 bits wide, floats are doubles and `printf` prints its argument on a line of its
+//! own, as in the [`vm`](crate::vm).
+//!
+//! The generated code follows the types the checker inferred, which the vm does not know about, as
+//! it runs programs without checking them. A value stored into a variable is converted to the type
+//! of the variable, so `c = 2.5; c = 1; printf(c);` prints `1.000000` here but `1` in the vm. A
+//! function that reaches its end without a `return` returns zero instead of failing.
+
+pub mod c;
+pub mod llvm;
+pub mod wasm;
+pub mod x86_64;
+
+pub(crate) use crate::ir::{constant, Constant};
@@ -0,0 +1,312 @@
+//! # Overview
+//! Translation of a type-checked program to the WebAssembly text format (WAT).
+//!
+//! Every function definition becomes an exported wasm function without parameters. `int` and `bool`
+//! become `i32`, `float` becomes `f64`, and local variables are wasm locals. `if` maps to the
+//! structured `if ... end`, as do the short-circuit operators `&&` and `||`; C(-1) has no loops.
+//! WebAssembly has no output of its own, so `printf` calls functions the host has to provide in the
+//! `env` module: `print_i32`, which also receives `bool` values as 0 or 1, and `print_f64`. Only
+//! the imports that are used are declared. Their names start with `env.`, which no C(-1) function
+//! can, to keep them apart from the functions of the program. A function that reaches its end
+//! without a `return` returns zero.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::codegen::wasm;
+//! use cb_3::typeck::check;
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("void main() { printf(6 * 7); }").unwrap();
+//! let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+//!
+//! assert_eq!(
+//!     wasm::emit(&checked),
+//!     "(module\n\
+//!     \x20 (import \"env\" \"print_i32\" (func $env.print_i32 (param i32)))\n\
+//!     \x20 (func $main (export \"main\")\n\
+//!     \x20   i32.const 6\n\
+//!     \x20   i32.const 7\n\
+//!     \x20   i32.mul\n\
+//!     \x20   call $env.print_i32\n\
+//!     \x20 )\n\
+//!     )\n"
+//! );
+//! ```
+
+use crate::ast::{BinaryOp, Expr, Statement, Type};
+use crate::codegen::{constant, Constant};
+use crate::typeck::{CheckedProgram, FunctionInfo};
+use std::fmt::Write;
+
+/// Translate the program into a wasm module
+pub fn emit(program: &CheckedProgram) -> String {
+    let mut imports = Imports::default();
+    let mut functions = String::new();
+    for (definition, function) in program.definitions() {
+        let mut emitter = FunctionEmitter {
+            program,
+            function,
+            imports: &mut imports,
+            body: String::new(),
+            indent: 2,
+        };
+        let mut returns = false;
+        for statement in definition.body().statements() {
+            returns = matches!(statement, Statement::Return(_));
+            emitter.statement(statement);
+        }
+        if !returns && function.return_type != Type::Void {
+            emitter.instruction(zero(function.return_type));
+        }
+
+        write!(functions, "  (func ${0} (export \"{0}\")", function.name).unwrap();
+        if function.return_type != Type::Void {
+            write!(functions, " (result {})", wasm_type(function.return_type)).unwrap();
+        }
+        functions.push('\n');
+        for local in &function.locals {
+            writeln!(
+                functions,
+                "    (local ${} {})",
+                local.name,
+                wasm_type(local.ty)
+            )
+            .unwrap();
+        }
+        functions.push_str(&emitter.body);
+        functions.push_str("  )\n");
+    }
+
+    let mut module = String::from("(module\n");
+    for (used, name, ty) in [
+        (imports.i32, "print_i32", "i32"),
+        (imports.f64, "print_f64", "f64"),
+    ] {
+        if used {
+            writeln!(
+                module,
+                "  (import \"env\" \"{0}\" (func $env.{0} (param {1})))",
+                name, ty
+            )
+            .unwrap();
+        }
+    }
+    module.push_str(&functions);
+    module.push_str(")\n");
+    module
+}
+
+/// The host functions that are called by the program
+#[derive(Default)]
+struct Imports {
+    i32: bool,
+    f64: bool,
+}
+
+fn wasm_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Float => "f64",
+        _ => "i32",
+    }
+}
+
+fn zero(ty: Type) -> &'static str {
+    match ty {
+        Type::Float => "f64.const 0",
+        _ => "i32.const 0",
+    }
+}
+
+struct FunctionEmitter<'p, 'i> {
+    program: &'p CheckedProgram<'p>,
+    function: &'p FunctionInfo,
+    imports: &'i mut Imports,
+    body: String,
+    /// Indentation of the next instruction, in levels of two spaces
+    indent: usize,
+}
+
+impl<'p, 'i> FunctionEmitter<'p, 'i> {
+    fn instruction(&mut self, text: &str) {
+        writeln!(self.body, "{:width$}{}", "", text, width = self.indent * 2).unwrap();
+    }
+
+    fn expr_type(&self, expr: Expr) -> Type {
+        self.program.expr_type(self.function, expr)
+    }
+
+    fn convert(&mut self, from: Type, to: Type) {
+        match (from, to) {
+            (Type::Int, Type::Float) => self.instruction("f64.convert_i32_s"),
+            (Type::Float, Type::Int) => self.instruction("i32.trunc_sat_f64_s"),
+            _ => {}
+        }
+    }
+
+    /// Emit the value converted to the type of the variable
+    fn assigned_value(&mut self, name: &str, value: Expr) {
+        let ty = self.expr_type(value);
+        let local_type = self
+            .function
+            .local(name)
+            .expect("variables were checked")
+            .ty;
+        self.expr(value);
+        self.convert(ty, local_type);
+    }
+
+    fn statement(&mut self, statement: Statement) {
+        match statement {
+            Statement::Block(block) => {
+                for statement in block.statements() {
+                    self.statement(statement);
+                }
+            }
+            Statement::If(if_statement) => {
+                self.expr(if_statement.condition());
+                self.instruction("if");
+                self.indent += 1;
+                self.statement(if_statement.then_branch());
+                self.indent -= 1;
+                self.instruction("end");
+            }
+            Statement::Return(ret) => {
+                if let Some(value) = ret.value() {
+                    let ty = self.expr_type(value);
+                    self.expr(value);
+                    self.convert(ty, self.function.return_type);
+                }
+                self.instruction("return");
+            }
+            Statement::Printf(printf) => {
+                let ty = self.expr_type(printf.argument());
+                self.expr(printf.argument());
+                let function = if ty == Type::Float {
+                    self.imports.f64 = true;
+                    "print_f64"
+                } else {
+                    self.imports.i32 = true;
+                    "print_i32"
+                };
+                self.instruction(&format!("call $env.{}", function));
+            }
+            Statement::Assignment(assignment) => {
+                self.assigned_value(assignment.target(), assignment.value());
+                self.instruction(&format!("local.set ${}", assignment.target()));
+            }
+            Statement::Call(call) => {
+                self.instruction(&format!("call ${}", call.name()));
+                let return_type = self
+                    .program
+                    .function(call.name())
+                    .expect("called functions were checked")
+                    .return_type;
+                if return_type != Type::Void {
+                    self.instruction("drop");
+                }
+            }
+        }
+    }
+
+    /// Emit the code for an expression, which leaves its value on the operand stack
+    fn expr(&mut self, expr: Expr) {
+        match expr {
+            Expr::Assignment(assignment) => {
+                self.assigned_value(assignment.target(), assignment.value());
+                self.instruction(&format!("local.tee ${}", assignment.target()));
+            }
+            Expr::Binary(binary) => match binary.operator() {
+                operator @ (BinaryOp::And | BinaryOp::Or) => {
+                    // The right operand is only evaluated if the left one does not decide
+                    self.expr(binary.lhs());
+                    self.instruction("if (result i32)");
+                    self.indent += 1;
+                    if operator == BinaryOp::And {
+                        self.expr(binary.rhs());
+                    } else {
+                        self.instruction("i32.const 1");
+                    }
+                    self.indent -= 1;
+                    self.instruction("else");
+                    self.indent += 1;
+                    if operator == BinaryOp::And {
+                        self.instruction("i32.const 0");
+                    } else {
+                        self.expr(binary.rhs());
+                    }
+                    self.indent -= 1;
+                    self.instruction("end");
+                }
+                operator => {
+                    let lhs_type = self.expr_type(binary.lhs());
+                    let rhs_type = self.expr_type(binary.rhs());
+                    let operand_type = match (lhs_type, rhs_type) {
+                        (Type::Int, Type::Int) | (Type::Bool, Type::Bool) => Type::Int,
+                        _ => Type::Float,
+                    };
+                    self.expr(binary.lhs());
+                    self.convert(lhs_type, operand_type);
+                    self.expr(binary.rhs());
+                    self.convert(rhs_type, operand_type);
+                    let instruction = match operand_type {
+                        Type::Float => float_instruction(operator),
+                        _ => int_instruction(operator),
+                    };
+                    self.instruction(instruction);
+                }
+            },
+            Expr::Unary(unary) => match self.expr_type(unary.operand()) {
+                Type::Float => {
+                    self.expr(unary.operand());
+                    self.instruction("f64.neg");
+                }
+                _ => {
+                    self.instruction("i32.const 0");
+                    self.expr(unary.operand());
+                    self.instruction("i32.sub");
+                }
+            },
+            Expr::Literal(literal) => match constant(literal) {
+                Constant::Int(value) => self.instruction(&format!("i32.const {}", value)),
+                Constant::Float(value) => self.instruction(&format!("f64.const {:?}", value)),
+                Constant::Bool(value) => self.instruction(&format!("i32.const {}", value as i32)),
+            },
+            Expr::Name(name) => self.instruction(&format!("local.get ${}", name.name())),
+            Expr::Call(call) => self.instruction(&format!("call ${}", call.name())),
+            Expr::Paren(paren) => self.expr(paren.inner()),
+        }
+    }
+}
+
+fn int_instruction(operator: BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "i32.add",
+        BinaryOp::Sub => "i32.sub",
+        BinaryOp::Mul => "i32.mul",
+        BinaryOp::Div => "i32.div_s",
+        BinaryOp::Equal => "i32.eq",
+        BinaryOp::NotEqual => "i32.ne",
+        BinaryOp::Less => "i32.lt_s",
+        BinaryOp::LessEqual => "i32.le_s",
+        BinaryOp::Greater => "i32.gt_s",
+        BinaryOp::GreaterEqual => "i32.ge_s",
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+    }
+}
+
+fn float_instruction(operator: BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "f64.add",
+        BinaryOp::Sub => "f64.sub",
+        BinaryOp::Mul => "f64.mul",
+        BinaryOp::Div => "f64.div",
+        BinaryOp::Equal => "f64.eq",
+        BinaryOp::NotEqual => "f64.ne",
+        BinaryOp::Less => "f64.lt",
+        BinaryOp::LessEqual => "f64.le",
+        BinaryOp::Greater => "f64.gt",
+        BinaryOp::GreaterEqual => "f64.ge",
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+    }
+}
@@ -0,0 +1,481 @@
+//! # Overview
+//! Translation of a type-checked program to x86-64 assembly in AT&T syntax for the GNU assembler.
+//! The output follows the System V ABI and can be assembled and linked with the C library, e.g.
+//! with `gcc program.s -o program`.
+//!
+//! The code is a straightforward tree walk. An expression leaves its value in `%eax` (integers
+//! and booleans as 0 or 1) or in `%xmm0` (floats); intermediate results of binary operators are
+//! pushed on the machine stack. Every local variable lives in an 8 byte slot below `%rbp`.
+//! Comparisons use `setcc`, with the parity flag taking care of unordered floats. `printf` calls
+//! the `printf` of the C library. Integer division checks for a divisor of -1 first, as `idivl`
+//! traps on `INT_MIN / -1`. A function that reaches its end without a `return` returns zero,
+//! so a `void main()` exits with status 0.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::codegen::x86_64;
+//! use cb_3::typeck::check;
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("int one() { return 1; }").unwrap();
+//! let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+//!
+//! let assembly = x86_64::emit(&checked);
+//! assert!(assembly.contains("one:\n\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n\tmovl\t$1, %eax\n"));
+//! ```
+
+use crate::ast::{BinaryOp, Expr, Statement, Type};
+use crate::codegen::{constant, Constant};
+use crate::typeck::{CheckedProgram, FunctionInfo};
+use std::fmt::Write;
+
+/// Translate the program into an assembly file
+pub fn emit(program: &CheckedProgram) -> String {
+    let mut emitter = Emitter {
+        program,
+        function: None,
+        text: String::new(),
+        float_constants: Vec::new(),
+        formats: Formats::default(),
+        next_label: 0,
+        depth: 0,
+    };
+    emitter.text.push_str("\t.text\n");
+    for (definition, function) in program.definitions() {
+        emitter.function = Some(function);
+        emitter.depth = 0;
+        let frame_size = (function.locals.len() * 8).div_ceil(16) * 16;
+        writeln!(
+            emitter.text,
+            "\n\t.globl\t{0}\n\t.type\t{0}, @function\n{0}:",
+            function.name
+        )
+        .unwrap();
+        emitter.op("pushq\t%rbp");
+        emitter.op("movq\t%rsp, %rbp");
+        if frame_size > 0 {
+            emitter.op(&format!("subq\t${}, %rsp", frame_size));
+        }
+        for statement in definition.body().statements() {
+            emitter.statement(statement);
+        }
+        // Falling off the end of the function
+        match function.return_type {
+            Type::Float => emitter.op("pxor\t%xmm0, %xmm0"),
+            _ => emitter.op("movl\t$0, %eax"),
+        }
+        emitter.op("leave");
+        emitter.op("ret");
+        writeln!(emitter.text, "\t.size\t{0}, .-{0}", function.name).unwrap();
+    }
+    emitter.read_only_data();
+    emitter
+        .text
+        .push_str("\n\t.section\t.note.GNU-stack,\"\",@progbits\n");
+    emitter.text
+}
+
+/// The format strings that are used by the program
+#[derive(Default)]
+struct Formats {
+    int: bool,
+    float: bool,
+    bool: bool,
+    /// The mask to flip the sign of a float
+    negate: bool,
+}
+
+struct Emitter<'p> {
+    program: &'p CheckedProgram<'p>,
+    /// The function that is being translated
+    function: Option<&'p FunctionInfo>,
+    text: String,
+    /// Bit patterns of the float constants, labeled by their index
+    float_constants: Vec<u64>,
+    formats: Formats,
+    next_label: usize,
+    /// Number of 8 byte values pushed on the stack since the prologue
+    depth: usize,
+}
+
+impl<'p> Emitter<'p> {
+    fn op(&mut self, instruction: &str) {
+        writeln!(self.text, "\t{}", instruction).unwrap();
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.text, "{}:", label).unwrap();
+    }
+
+    fn next_label(&mut self) -> usize {
+        self.next_label += 1;
+        self.next_label - 1
+    }
+
+    fn function(&self) -> &'p FunctionInfo {
+        self.function.expect("no function is being translated")
+    }
+
+    fn expr_type(&self, expr: Expr) -> Type {
+        self.program.expr_type(self.function(), expr)
+    }
+
+    /// Offset of the slot of a local variable relative to `%rbp`
+    fn slot(&self, name: &str) -> (i64, Type) {
+        let function = self.function();
+        let index = function
+            .locals
+            .iter()
+            .position(|local| local.name == name)
+            .expect("variables were checked");
+        (-8 * (index as i64 + 1), function.locals[index].ty)
+    }
+
+    fn push(&mut self, ty: Type) {
+        match ty {
+            Type::Float => {
+                self.op("subq\t$8, %rsp");
+                self.op("movsd\t%xmm0, (%rsp)");
+            }
+            _ => self.op("pushq\t%rax"),
+        }
+        self.depth += 1;
+    }
+
+    /// Pop a value into `%eax` or `%xmm0`
+    fn pop(&mut self, ty: Type) {
+        match ty {
+            Type::Float => {
+                self.op("movsd\t(%rsp), %xmm0");
+                self.op("addq\t$8, %rsp");
+            }
+            _ => self.op("popq\t%rax"),
+        }
+        self.depth -= 1;
+    }
+
+    /// Call a function with the stack aligned to 16 bytes
+    fn call(&mut self, function: &str) {
+        if self.depth % 2 == 1 {
+            self.op("subq\t$8, %rsp");
+            self.op(&format!("call\t{}", function));
+            self.op("addq\t$8, %rsp");
+        } else {
+            self.op(&format!("call\t{}", function));
+        }
+    }
+
+    fn convert(&mut self, from: Type, to: Type) {
+        match (from, to) {
+            (Type::Int, Type::Float) => self.op("cvtsi2sdl\t%eax, %xmm0"),
+            (Type::Float, Type::Int) => self.op("cvttsd2si\t%xmm0, %eax"),
+            _ => {}
+        }
+    }
+
+    fn store(&mut self, name: &str, ty: Type) {
+        let (offset, local_type) = self.slot(name);
+        self.convert(ty, local_type);
+        match local_type {
+            Type::Float => self.op(&format!("movsd\t%xmm0, {}(%rbp)", offset)),
+            _ => self.op(&format!("movl\t%eax, {}(%rbp)", offset)),
+        }
+    }
+
+    fn statement(&mut self, statement: Statement) {
+        match statement {
+            Statement::Block(block) => {
+                for statement in block.statements() {
+                    self.statement(statement);
+                }
+            }
+            Statement::If(if_statement) => {
+                let end = format!(".Lif_end{}", self.next_label());
+                self.expr(if_statement.condition());
+                self.op("testl\t%eax, %eax");
+                self.op(&format!("je\t{}", end));
+                self.statement(if_statement.then_branch());
+                self.label(&end);
+            }
+            Statement::Return(ret) => {
+                if let Some(value) = ret.value() {
+                    let ty = self.expr_type(value);
+                    self.expr(value);
+                    self.convert(ty, self.function().return_type);
+                } else if self.function().name == "main" {
+                    self.op("movl\t$0, %eax");
+                }
+                self.op("leave");
+                self.op("ret");
+            }
+            Statement::Printf(printf) => {
+                let ty = self.expr_type(printf.argument());
+                self.expr(printf.argument());
+                match ty {
+                    Type::Int => {
+                        self.formats.int = true;
+                        self.op("movl\t%eax, %esi");
+                        self.op("leaq\t.Lformat_int(%rip), %rdi");
+                        self.op("movl\t$0, %eax");
+                    }
+                    Type::Float => {
+                        self.formats.float = true;
+                        self.op("leaq\t.Lformat_float(%rip), %rdi");
+                        self.op("movl\t$1, %eax");
+                    }
+                    _ => {
+                        self.formats.bool = true;
+                        self.op("leaq\t.Lfalse(%rip), %rsi");
+                        self.op("leaq\t.Ltrue(%rip), %rcx");
+                        self.op("testl\t%eax, %eax");
+                        self.op("cmovne\t%rcx, %rsi");
+                        self.op("leaq\t.Lformat_bool(%rip), %rdi");
+                        self.op("movl\t$0, %eax");
+                    }
+                }
+                self.call("printf@PLT");
+            }
+            Statement::Assignment(assignment) => {
+                let ty = self.expr_type(assignment.value());
+                self.expr(assignment.value());
+                self.store(assignment.target(), ty);
+            }
+            Statement::Call(call) => self.call(call.name()),
+        }
+    }
+
+    /// Emit the code for an expression, which leaves its value in `%eax` or `%xmm0`
+    fn expr(&mut self, expr: Expr) {
+        match expr {
+            Expr::Assignment(assignment) => {
+                let ty = self.expr_type(assignment.value());
+                self.expr(assignment.value());
+                self.store(assignment.target(), ty);
+            }
+            Expr::Binary(binary) => match binary.operator() {
+                BinaryOp::And | BinaryOp::Or => {
+                    // The left operand decides if it is false for && and true for ||, and it is
+                    // already the result in that case
+                    let end = format!(".Llogic_end{}", self.next_label());
+                    self.expr(binary.lhs());
+                    self.op("testl\t%eax, %eax");
+                    if binary.operator() == BinaryOp::And {
+                        self.op(&format!("je\t{}", end));
+                    } else {
+                        self.op(&format!("jne\t{}", end));
+                    }
+                    self.expr(binary.rhs());
+                    self.label(&end);
+                }
+                operator => {
+                    let lhs_type = self.expr_type(binary.lhs());
+                    let rhs_type = self.expr_type(binary.rhs());
+                    let operand_type = match (lhs_type, rhs_type) {
+                        (Type::Int, Type::Int) => Type::Int,
+                        (Type::Bool, Type::Bool) => Type::Bool,
+                        _ => Type::Float,
+                    };
+                    self.expr(binary.lhs());
+                    self.convert(lhs_type, operand_type);
+                    self.push(operand_type);
+                    self.expr(binary.rhs());
+                    self.convert(rhs_type, operand_type);
+                    // Move the right operand out of the way and restore the left one
+                    match operand_type {
+                        Type::Float => {
+                            self.op("movapd\t%xmm0, %xmm1");
+                            self.pop(operand_type);
+                            self.float_operator(operator);
+                        }
+                        _ => {
+                            self.op("movl\t%eax, %ecx");
+                            self.pop(operand_type);
+                            self.int_operator(operator);
+                        }
+                    }
+                }
+            },
+            Expr::Unary(unary) => {
+                self.expr(unary.operand());
+                match self.expr_type(unary.operand()) {
+                    Type::Float => {
+                        self.formats.negate = true;
+                        self.op("xorpd\t.Lsign_mask(%rip), %xmm0");
+                    }
+                    _ => self.op("negl\t%eax"),
+                }
+            }
+            Expr::Literal(literal) => match constant(literal) {
+                Constant::Int(value) => self.op(&format!("movl\t${}, %eax", value)),
+                Constant::Float(value) => {
+                    let bits = value.to_bits();
+                    let index = match self.float_constants.iter().position(|&b| b == bits) {
+                        Some(index) => index,
+                        None => {
+                            self.float_constants.push(bits);
+                            self.float_constants.len() - 1
+                        }
+                    };
+                    self.op(&format!("movsd\t.Lfloat{}(%rip), %xmm0", index));
+                }
+                Constant::Bool(value) => self.op(&format!("movl\t${}, %eax", value as i32)),
+            },
+            Expr::Name(name) => {
+                let (offset, ty) = self.slot(name.name());
+                match ty {
+                    Type::Float => self.op(&format!("movsd\t{}(%rbp), %xmm0", offset)),
+                    _ => self.op(&format!("movl\t{}(%rbp), %eax", offset)),
+                }
+            }
+            Expr::Call(call) => self.call(call.name()),
+            Expr::Paren(paren) => self.expr(paren.inner()),
+        }
+    }
+
+    /// Combine `%eax` and `%ecx` into `%eax`
+    fn int_operator(&mut self, operator: BinaryOp) {
+        let condition = match operator {
+            BinaryOp::Add => return self.op("addl\t%ecx, %eax"),
+            BinaryOp::Sub => return self.op("subl\t%ecx, %eax"),
+            BinaryOp::Mul => return self.op("imull\t%ecx, %eax"),
+            BinaryOp::Div => {
+                // `idivl` traps on INT_MIN / -1, which wraps around to INT_MIN everywhere else
+                let label = self.next_label();
+                let divide = format!(".Ldivide{}", label);
+                let end = format!(".Ldivide_end{}", label);
+                self.op("cmpl\t$-1, %ecx");
+                self.op(&format!("jne\t{}", divide));
+                self.op("negl\t%eax");
+                self.op(&format!("jmp\t{}", end));
+                self.label(&divide);
+                self.op("cltd");
+                self.op("idivl\t%ecx");
+                return self.label(&end);
+            }
+            BinaryOp::Equal => "e",
+            BinaryOp::NotEqual => "ne",
+            BinaryOp::Less => "l",
+            BinaryOp::LessEqual => "le",
+            BinaryOp::Greater => "g",
+            BinaryOp::GreaterEqual => "ge",
+            BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+        };
+        self.op("cmpl\t%ecx, %eax");
+        self.op(&format!("set{}\t%al", condition));
+        self.op("movzbl\t%al, %eax");
+    }
+
+    /// Combine `%xmm0` and `%xmm1` into `%xmm0`, or into `%eax` for comparisons
+    fn float_operator(&mut self, operator: BinaryOp) {
+        match operator {
+            BinaryOp::Add => return self.op("addsd\t%xmm1, %xmm0"),
+            BinaryOp::Sub => return self.op("subsd\t%xmm1, %xmm0"),
+            BinaryOp::Mul => return self.op("mulsd\t%xmm1, %xmm0"),
+            BinaryOp::Div => return self.op("divsd\t%xmm1, %xmm0"),
+            // An unordered comparison sets the zero, parity and carry flag, so `a` and `ae` are
+            // false for NaN. Less and less or equal swap the operands to use them.
+            BinaryOp::Less => {
+                self.op("ucomisd\t%xmm0, %xmm1");
+                self.op("seta\t%al");
+            }
+            BinaryOp::LessEqual => {
+                self.op("ucomisd\t%xmm0, %xmm1");
+                self.op("setae\t%al");
+            }
+            BinaryOp::Greater => {
+                self.op("ucomisd\t%xmm1, %xmm0");
+                self.op("seta\t%al");
+            }
+            BinaryOp::GreaterEqual => {
+                self.op("ucomisd\t%xmm1, %xmm0");
+                self.op("setae\t%al");
+            }
+            BinaryOp::Equal => {
+                self.op("ucomisd\t%xmm1, %xmm0");
+                self.op("sete\t%al");
+                self.op("setnp\t%cl");
+                self.op("andb\t%cl, %al");
+            }
+            BinaryOp::NotEqual => {
+                self.op("ucomisd\t%xmm1, %xmm0");
+                self.op("setne\t%al");
+                self.op("setp\t%cl");
+                self.op("orb\t%cl, %al");
+            }
+            BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit"),
+        }
+        self.op("movzbl\t%al, %eax");
+    }
+
+    fn read_only_data(&mut self) {
+        let formats = &self.formats;
+        if !(formats.int || formats.float || formats.bool || formats.negate)
+            && self.float_constants.is_empty()
+        {
+            return;
+        }
+        let mut data = String::from("\n\t.section\t.rodata\n");
+        if formats.int {
+            data.push_str(".Lformat_int:\n\t.string\t\"%d\\n\"\n");
+        }
+        if formats.float {
+            data.push_str(".Lformat_float:\n\t.string\t\"%f\\n\"\n");
+        }
+        if formats.bool {
+            data.push_str(".Lformat_bool:\n\t.string\t\"%s\\n\"\n");
+            data.push_str(".Ltrue:\n\t.string\t\"true\"\n");
+            data.push_str(".Lfalse:\n\t.string\t\"false\"\n");
+        }
+        if !self.float_constants.is_empty() || formats.negate {
+            data.push_str("\t.p2align\t4\n");
+        }
+        if formats.negate {
+            data.push_str(".Lsign_mask:\n\t.quad\t0x8000000000000000\n\t.quad\t0\n");
+        }
+        for (index, bits) in self.float_constants.iter().enumerate() {
+            writeln!(data, ".Lfloat{}:\n\t.quad\t0x{:016X}", index, bits).unwrap();
+        }
+        self.text.push_str(&data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::codegen::x86_64::emit;
+    use crate::typeck::check;
+    use crate::C1Parser;
+
+    #[test]
+    fn calls_keep_the_stack_aligned() {
+        let tree = C1Parser::parse_tree("int f() { return 1 + f(); }").unwrap();
+        let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+        assert!(emit(&checked).contains(
+            "\tmovl\t$1, %eax\n\
+             \tpushq\t%rax\n\
+             \tsubq\t$8, %rsp\n\
+             \tcall\tf\n\
+             \taddq\t$8, %rsp\n\
+             \tmovl\t%eax, %ecx\n\
+             \tpopq\t%rax\n\
+             \taddl\t%ecx, %eax\n"
+        ));
+    }
+
+    #[test]
+    fn division_by_minus_one_negates() {
+        let tree = C1Parser::parse_tree("int f() { return f() / f(); }").unwrap();
+        let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+        assert!(emit(&checked).contains(
+            "\tcmpl\t$-1, %ecx\n\
+             \tjne\t.Ldivide0\n\
+             \tnegl\t%eax\n\
+             \tjmp\t.Ldivide_end0\n\
+             .Ldivide0:\n\
+             \tcltd\n\
+             \tidivl\t%ecx\n\
+             .Ldivide_end0:\n"
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::lexer::{C1Token, Trivia};
+use std::fmt;
+
+/// The kinds of inner nodes of the concrete syntax tree. Most of them correspond directly to a rule
+/// of the C(-1) grammar. Binary and unary expressions, literals, names and parenthesized
+/// expressions get their own kinds, so that the nesting of the tree reflects operator precedence.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SyntaxKind {
+    Program,
+    FunctionDefinition,
+    Type,
+    FunctionCall,
+    StatementList,
+    /// `"{" statementlist "}"`
+    Block,
+    /// A statement together with its terminating ';'
+    Statement,
+    IfStatement,
+    ReturnStatement,
+    Printf,
+    StatAssignment,
+    /// `<ID> "=" assignment` inside an expression
+    Assignment,
+    BinaryExpr,
+    UnaryExpr,
+    Literal,
+    NameRef,
+    ParenExpr,
+}
+
+/// A token of the concrete syntax tree. Every token owns the trivia that precedes it in the source
+/// text.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxToken<'a> {
+    pub kind: C1Token,
+    pub text: &'a str,
+    pub line: usize,
+    /// Byte offset of the token text in the parsed text
+    pub offset: usize,
+    pub leading_trivia: Vec<Trivia<'a>>,
+}
+
+/// An inner node of the concrete syntax tree.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxNode<'a> {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+/// A child of a [`SyntaxNode`], either another node or a token.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(SyntaxToken<'a>),
+}
+
+/// # Overview
+/// Lossless concrete syntax tree of a C(-1) program. Together with the trivia attached to its
+/// tokens and the trivia at the end of the file, the tree contains every byte of the parsed text,
+/// so [`SyntaxTree::text`] reproduces the source exactly.
+///
+/// # Examples
+/// ```
+/// use cb_3::{C1Parser, SyntaxKind};
+///
+/// let source = "// entry point\nvoid main() {\n\tprintf(1 + 2); /* done */\n}\n";
+/// let tree = C1Parser::parse_tree(source).unwrap();
+///
+/// assert_eq!(tree.root.kind, SyntaxKind::Program);
+/// assert_eq!(tree.text(), source);
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxTree<'a> {
+    pub root: SyntaxNode<'a>,
+    /// Trivia that follows the last token of the text
+    pub eof_trivia: Vec<Trivia<'a>>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Reassemble the source text from the tree
+    pub fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<'a> SyntaxNode<'a> {
+    /// Iterate over the child nodes, skipping tokens
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Iterate over the tokens that are direct children of this node
+    pub fn child_tokens(&self) -> impl Iterator<Item = &SyntaxToken<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+
+    /// Return the first direct child token of the given kind
+    pub fn child_token(&self, kind: C1Token) -> Option<&SyntaxToken<'a>> {
+        self.child_tokens().find(|token| token.kind == kind)
+    }
+
+    /// Return the first token of the subtree rooted at this node
+    pub fn first_token(&self) -> Option<&SyntaxToken<'a>> {
+        self.children.iter().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.first_token(),
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+
+    /// Collect all tokens of the subtree rooted at this node in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken<'a>> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'t>(&'t self, tokens: &mut Vec<&'t SyntaxToken<'a>>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Display for SyntaxToken<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading_trivia {
+            f.write_str(trivia.text)?;
+        }
+        f.write_str(self.text)
+    }
+}
+
+impl<'a> fmt::Display for SyntaxNode<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => write!(f, "{}", node)?,
+                SyntaxElement::Token(token) => write!(f, "{}", token)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for SyntaxTree<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root)?;
+        for trivia in &self.eof_trivia {
+            f.write_str(trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Marks a position among the children of the currently open node. A node can later be started at
+/// this position to wrap everything that was added since, which is how left-associative binary
+/// expressions are built.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Checkpoint(usize);
+
+/// Assembles a [`SyntaxTree`] from the start/finish events emitted by the parser.
+pub(crate) struct TreeBuilder<'a> {
+    open_nodes: Vec<SyntaxNode<'a>>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    pub(crate) fn new() -> TreeBuilder<'a> {
+        TreeBuilder {
+            open_nodes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn start_node(&mut self, kind: SyntaxKind) {
+        self.open_nodes.push(SyntaxNode {
+            kind,
+            children: Vec::new(),
+        });
+    }
+
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.open_nodes.last().map_or(0, |node| node.children.len()))
+    }
+
+    pub(crate) fn start_node_at(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        let children = match self.open_nodes.last_mut() {
+            Some(parent) => parent.children.split_off(checkpoint.0),
+            None => Vec::new(),
+        };
+        self.open_nodes.push(SyntaxNode { kind, children });
+    }
+
+    pub(crate) fn finish_node(&mut self) {
+        let node = self.open_nodes.pop().expect("no open node to finish");
+        match self.open_nodes.last_mut() {
+            Some(parent) => parent.children.push(SyntaxElement::Node(node)),
+            // Keep the root on the stack until the tree is finished
+            None => self.open_nodes.push(node),
+        }
+    }
+
+    pub(crate) fn token(&mut self, token: SyntaxToken<'a>) {
+        if let Some(parent) = self.open_nodes.last_mut() {
+            parent.children.push(SyntaxElement::Token(token));
+        }
+    }
+
+    pub(crate) fn finish(mut self, eof_trivia: Vec<Trivia<'a>>) -> SyntaxTree<'a> {
+        let root = self.open_nodes.pop().expect("the tree has no root node");
+        SyntaxTree { root, eof_trivia }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cst::{SyntaxElement, SyntaxKind, SyntaxNode};
+    use crate::{C1Parser, C1Token};
+
+    fn find<'t, 'a>(node: &'t SyntaxNode<'a>, kind: SyntaxKind) -> Option<&'t SyntaxNode<'a>> {
+        if node.kind == kind {
+            return Some(node);
+        }
+        node.child_nodes().find_map(|child| find(child, kind))
+    }
+
+    #[test]
+    fn round_trip_example() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let tree = C1Parser::parse_tree(&text).unwrap();
+        assert_eq!(tree.text(), text);
+    }
+
+    #[test]
+    fn round_trip_trivia() {
+        let sources = [
+            "",
+            "  \n ",
+            "// only a comment",
+            "/* header\n   comment */\nvoid main() {}\n\n",
+            "int f() {\r\n\treturn 1; // one\r\n}\r\n",
+            "void main(){x=1;if(x<2){printf(-x+3*(x=2));}}",
+            "\tvoid  main ( )\n{\n  /**/ foo ( ) ;\n}   // end",
+        ];
+        for source in sources {
+            let tree = C1Parser::parse_tree(source).unwrap();
+            assert_eq!(tree.text(), source);
+        }
+    }
+
+    #[test]
+    fn trivia_is_attached_to_following_token() {
+        let tree = C1Parser::parse_tree("void main() {\n  /* call */\n  foo();\n}").unwrap();
+        let call = find(&tree.root, SyntaxKind::FunctionCall).unwrap();
+        let name = call.first_token().unwrap();
+        assert_eq!(name.text, "foo");
+        assert_eq!(name.line, 3);
+        let trivia: String = name.leading_trivia.iter().map(|t| t.text).collect();
+        assert_eq!(trivia, "\n  /* call */\n  ");
+    }
+
+    #[test]
+    fn binary_expressions_are_left_associative() {
+        let tree = C1Parser::parse_tree("void main() { x = 1 - 2 + 3 * 4; }").unwrap();
+        let outer = find(&tree.root, SyntaxKind::BinaryExpr).unwrap();
+        let operator = outer.child_tokens().next().unwrap();
+        assert_eq!(operator.kind, C1Token::Plus);
+        assert_eq!(outer.to_string(), " 1 - 2 + 3 * 4");
+
+        let kinds: Vec<SyntaxKind> = outer.child_nodes().map(|node| node.kind).collect();
+        assert_eq!(kinds, vec![SyntaxKind::BinaryExpr, SyntaxKind::BinaryExpr]);
+        match &outer.children[0] {
+            SyntaxElement::Node(left) => assert_eq!(left.to_string(), " 1 - 2"),
+            SyntaxElement::Token(_) => panic!("expected the left operand to be a node"),
+        }
+    }
+
+    #[test]
+    fn invalid_program_has_no_tree() {
+        assert!(C1Parser::parse_tree("void main() { x = ; }").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! # Overview
+//! Syntax highlighting for C(-1). [`highlight`] splits a text into spans and classifies every token
+//! and comment; whitespace stays unclassified. Identifiers directly followed by `(` are function
+//! names, all other identifiers are variables, which is the same lookahead the parser uses in
+//! `factor`. The spans can be rendered as ANSI-colored terminal output or as HTML.
+//!
+//! # Examples
+//! ```
+//! use cb_3::highlight::{highlight, to_html, Highlight};
+//!
+//! let spans = highlight("x = f(); // call");
+//! let classes: Vec<_> = spans.iter().filter_map(|span| span.class).collect();
+//! assert_eq!(
+//!     classes,
+//!     vec![
+//!         Highlight::Variable,
+//!         Highlight::Operator,
+//!         Highlight::Function,
+//!         Highlight::Operator,
+//!         Highlight::Operator,
+//!         Highlight::Operator,
+//!         Highlight::Comment,
+//!     ]
+//! );
+//!
+//! assert_eq!(
+//!     to_html("int a"),
+//!     "<pre class=\"c1\"><span class=\"c1-type\">int</span> <span class=\"c1-variable\">a</span></pre>"
+//! );
+//! ```
+
+use crate::lexer::{C1Lexer, C1Token, Trivia, TriviaKind};
+
+/// The highlighting classes
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Highlight {
+    Keyword,
+    /// The type keywords `bool`, `float`, `int` and `void`
+    Type,
+    Number,
+    String,
+    /// Operators and punctuation
+    Operator,
+    Function,
+    Variable,
+    Comment,
+}
+
+impl Highlight {
+    /// Name of the class, also used for the CSS classes of the HTML output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Highlight::Keyword => "keyword",
+            Highlight::Type => "type",
+            Highlight::Number => "number",
+            Highlight::String => "string",
+            Highlight::Operator => "operator",
+            Highlight::Function => "function",
+            Highlight::Variable => "variable",
+            Highlight::Comment => "comment",
+        }
+    }
+
+    fn ansi_code(&self) -> &'static str {
+        match self {
+            Highlight::Keyword => "1;35",
+            Highlight::Type => "36",
+            Highlight::Number => "33",
+            Highlight::String => "32",
+            Highlight::Operator => "37",
+            Highlight::Function => "34",
+            Highlight::Variable => "39",
+            Highlight::Comment => "90",
+        }
+    }
+}
+
+/// A piece of the highlighted text. Whitespace and tokens the lexer does not recognize have no
+/// class.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct HighlightedSpan<'a> {
+    pub class: Option<Highlight>,
+    pub text: &'a str,
+}
+
+/// Split the text into classified spans. Concatenating the spans yields the original text.
+pub fn highlight(text: &str) -> Vec<HighlightedSpan<'_>> {
+    let mut lexer = C1Lexer::with_trivia(text);
+    let mut spans = Vec::new();
+    while let Some(token) = lexer.current_token() {
+        push_trivia(&mut spans, lexer.current_leading_trivia());
+        let class = classify(token, lexer.peek_token());
+        if let Some(text) = lexer.current_text() {
+            spans.push(HighlightedSpan { class, text });
+        }
+        lexer.eat();
+    }
+    push_trivia(&mut spans, lexer.current_leading_trivia());
+    spans
+}
+
+fn push_trivia<'a>(spans: &mut Vec<HighlightedSpan<'a>>, trivia: &[Trivia<'a>]) {
+    for trivia in trivia {
+        let class = match trivia.kind {
+            TriviaKind::LineComment | TriviaKind::BlockComment => Some(Highlight::Comment),
+            TriviaKind::Whitespace | TriviaKind::Linebreak => None,
+        };
+        spans.push(HighlightedSpan {
+            class,
+            text: trivia.text,
+        });
+    }
+}
+
+fn classify(token: C1Token, next: Option<C1Token>) -> Option<Highlight> {
+    use C1Token::*;
+    let class = match token {
+        KwBoolean | KwFloat | KwInt | KwVoid => Highlight::Type,
+        KwDo | KwElse | KwFor | KwIf | KwPrintf | KwReturn | KwWhile | ConstBoolean => {
+            Highlight::Keyword
+        }
+        ConstInt | ConstFloat => Highlight::Number,
+        ConstString => Highlight::String,
+        Identifier if next == Some(LeftParenthesis) => Highlight::Function,
+        Identifier => Highlight::Variable,
+        Plus | Minus | Asterisk | Slash | Assign | Equal | NotEqual | Less | Greater
+        | LessEqual | GreaterEqual | And | Or | Comma | Semicolon | LeftParenthesis
+        | RightParenthesis | LeftBrace | RightBrace => Highlight::Operator,
+        CComment | CPPComment => Highlight::Comment,
+        Whitespace | Linebreak | Error => return None,
+    };
+    Some(class)
+}
+
+/// Render the text with ANSI escape sequences for a terminal
+pub fn to_ansi(text: &str) -> String {
+    let mut output = String::with_capacity(text.len() * 2);
+    for span in highlight(text) {
+        match span.class {
+            Some(class) => {
+                output.push_str(&format!("\x1b[{}m{}\x1b[0m", class.ansi_code(), span.text))
+            }
+            None => output.push_str(span.text),
+        }
+    }
+    output
+}
+
+/// Render the text as an HTML `pre` element. Every classified span becomes a `span` element with
+/// the CSS class `c1-<class name>`.
+pub fn to_html(text: &str) -> String {
+    let mut output = String::from("<pre class=\"c1\">");
+    for span in highlight(text) {
+        match span.class {
+            Some(class) => {
+                output.push_str(&format!("<span class=\"c1-{}\">", class.name()));
+                escape_html(span.text, &mut output);
+                output.push_str("</span>");
+            }
+            None => escape_html(span.text, &mut output),
+        }
+    }
+    output.push_str("</pre>");
+    output
+}
+
+fn escape_html(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            c => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::{highlight, to_ansi, to_html, Highlight};
+
+    #[test]
+    fn spans_cover_the_text() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let spans = highlight(&text);
+        let joined: String = spans.iter().map(|span| span.text).collect();
+        assert_eq!(joined, text);
+
+        let class_of = |text: &str| {
+            spans
+                .iter()
+                .find(|span| span.text == text)
+                .and_then(|span| span.class)
+        };
+        assert_eq!(class_of("float"), Some(Highlight::Type));
+        assert_eq!(class_of("return"), Some(Highlight::Keyword));
+        assert_eq!(class_of("3.14159"), Some(Highlight::Number));
+        assert_eq!(class_of("blub"), Some(Highlight::Function));
+        assert_eq!(class_of("blub1"), Some(Highlight::Variable));
+        assert_eq!(class_of("<="), Some(Highlight::Operator));
+    }
+
+    #[test]
+    fn comments_and_strings() {
+        let spans = highlight("/* a */ \"text\" // b\n");
+        let classes: Vec<Option<Highlight>> = spans.iter().map(|span| span.class).collect();
+        assert_eq!(
+            classes,
+            vec![
+                Some(Highlight::Comment),
+                None,
+                Some(Highlight::String),
+                None,
+                Some(Highlight::Comment)
+            ]
+        );
+    }
+
+    #[test]
+    fn rendering() {
+        assert_eq!(
+            to_ansi("if (a<b)"),
+            "\x1b[1;35mif\x1b[0m \x1b[37m(\x1b[0m\x1b[39ma\x1b[0m\x1b[37m<\x1b[0m\x1b[39mb\x1b[0m\x1b[37m)\x1b[0m"
+        );
+        assert_eq!(
+            to_html("a<b"),
+            "<pre class=\"c1\"><span class=\"c1-variable\">a</span><span class=\"c1-operator\">&lt;</span><span class=\"c1-variable\">b</span></pre>"
+        );
+    }
+}
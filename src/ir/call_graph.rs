@@ -0,0 +1,252 @@
+//! Call graphs
+//!
+//! The call graph has an edge from every function to each function it calls. Recursion shows up
+//! as cycles: a function that calls itself, or a group of functions that call each other. These
+//! groups are the strongly connected components of the graph, found with Tarjan's algorithm.
+
+use crate::ir::{InstrKind, Module};
+use std::fmt::Write;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CallGraph {
+    /// The names of the functions, in the order of the module
+    names: Vec<String>,
+    /// The functions each function calls, by index, in the order of their first call
+    callees: Vec<Vec<usize>>,
+}
+
+impl CallGraph {
+    pub fn new(module: &Module) -> Self {
+        let names: Vec<String> = module
+            .functions
+            .iter()
+            .map(|function| function.name.clone())
+            .collect();
+        let callees = module
+            .functions
+            .iter()
+            .map(|function| {
+                let mut callees = Vec::new();
+                for block in &function.blocks {
+                    for instr in &block.instrs {
+                        if let InstrKind::Call { function, .. } = &instr.kind {
+                            let callee = names
+                                .iter()
+                                .position(|name| name == function)
+                                .expect("called functions were checked");
+                            if !callees.contains(&callee) {
+                                callees.push(callee);
+                            }
+                        }
+                    }
+                }
+                callees
+            })
+            .collect();
+        CallGraph { names, callees }
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|other| other == name)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// The functions that the function calls directly
+    pub fn callees(&self, name: &str) -> Vec<&str> {
+        self.index(name).map_or_else(Vec::new, |index| {
+            self.callees[index]
+                .iter()
+                .map(|&callee| self.names[callee].as_str())
+                .collect()
+        })
+    }
+
+    /// The groups of functions that call each other, each in the order of the module. A single
+    /// function is only a group if it calls itself.
+    pub fn recursive_groups(&self) -> Vec<Vec<&str>> {
+        let mut groups: Vec<Vec<&str>> = self
+            .components()
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || self.callees[component[0]].contains(&component[0])
+            })
+            .map(|mut component| {
+                component.sort();
+                component
+                    .into_iter()
+                    .map(|index| self.names[index].as_str())
+                    .collect()
+            })
+            .collect();
+        groups.sort_by_key(|group| self.index(group[0]));
+        groups
+    }
+
+    /// Whether the function can call itself, directly or through other functions
+    pub fn is_recursive(&self, name: &str) -> bool {
+        self.recursive_groups()
+            .iter()
+            .any(|group| group.contains(&name))
+    }
+
+    /// The functions that can be called, directly or indirectly, starting from the function.
+    /// The function itself is included.
+    pub fn reachable_from(&self, name: &str) -> Vec<&str> {
+        let mut reached = vec![false; self.names.len()];
+        let mut stack: Vec<usize> = self.index(name).into_iter().collect();
+        while let Some(index) = stack.pop() {
+            if reached[index] {
+                continue;
+            }
+            reached[index] = true;
+            stack.extend(&self.callees[index]);
+        }
+        self.functions()
+            .zip(reached)
+            .filter_map(|(name, reached)| reached.then_some(name))
+            .collect()
+    }
+
+    /// The functions that are never called when the program runs from `main`
+    pub fn unreachable_from_main(&self) -> Vec<&str> {
+        let reachable = self.reachable_from("main");
+        self.functions()
+            .filter(|name| !reachable.contains(name))
+            .collect()
+    }
+
+    /// The strongly connected components, callees before their callers
+    pub(crate) fn components(&self) -> Vec<Vec<usize>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: vec![None; self.names.len()],
+            low_link: vec![0; self.names.len()],
+            on_stack: vec![false; self.names.len()],
+            stack: Vec::new(),
+            next_index: 0,
+            components: Vec::new(),
+        };
+        for function in 0..self.names.len() {
+            if tarjan.index[function].is_none() {
+                tarjan.visit(function);
+            }
+        }
+        tarjan.components
+    }
+
+    /// The call graph in the DOT language of Graphviz; recursive calls are drawn in red
+    pub fn to_dot(&self) -> String {
+        let recursive = self.recursive_groups();
+        let mut dot = String::from("digraph calls {\n  node [shape=box];\n");
+        for name in &self.names {
+            writeln!(dot, "  \"{}\";", name).unwrap();
+        }
+        for (caller, callees) in self.callees.iter().enumerate() {
+            let caller = self.names[caller].as_str();
+            for &callee in callees {
+                let callee = self.names[callee].as_str();
+                let in_cycle = recursive
+                    .iter()
+                    .any(|group| group.contains(&caller) && group.contains(&callee));
+                let attributes = if in_cycle { " [color=red]" } else { "" };
+                writeln!(dot, "  \"{}\" -> \"{}\"{};", caller, callee, attributes).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+struct Tarjan<'g> {
+    graph: &'g CallGraph,
+    index: Vec<Option<usize>>,
+    low_link: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, function: usize) {
+        self.index[function] = Some(self.next_index);
+        self.low_link[function] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(function);
+        self.on_stack[function] = true;
+        for &callee in &self.graph.callees[function] {
+            match self.index[callee] {
+                None => {
+                    self.visit(callee);
+                    self.low_link[function] = self.low_link[function].min(self.low_link[callee]);
+                }
+                Some(index) if self.on_stack[callee] => {
+                    self.low_link[function] = self.low_link[function].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+        if Some(self.low_link[function]) == self.index[function] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().expect("the function is on the stack");
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == function {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{lower_text, CallGraph};
+
+    fn call_graph(text: &str) -> CallGraph {
+        CallGraph::new(&lower_text(text))
+    }
+
+    #[test]
+    fn example() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let graph = call_graph(&text);
+        assert_eq!(graph.callees("main"), vec!["blub", "blah"]);
+        assert_eq!(graph.callees("blah"), vec!["blub"]);
+        assert!(graph.recursive_groups().is_empty());
+        assert!(graph.unreachable_from_main().is_empty());
+        assert_eq!(
+            graph.to_dot(),
+            "digraph calls {\n\
+             \x20 node [shape=box];\n\
+             \x20 \"blub\";\n\
+             \x20 \"blah\";\n\
+             \x20 \"main\";\n\
+             \x20 \"blah\" -> \"blub\";\n\
+             \x20 \"main\" -> \"blub\";\n\
+             \x20 \"main\" -> \"blah\";\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let graph = call_graph(
+            "int even() { return odd(); } int odd() { return even(); } int self() { return self(); }
+             int unused() { return 1; } void main() { printf(even()); }",
+        );
+        assert_eq!(
+            graph.recursive_groups(),
+            vec![vec!["even", "odd"], vec!["self"]]
+        );
+        assert!(graph.is_recursive("odd"));
+        assert!(!graph.is_recursive("main"));
+        assert_eq!(graph.unreachable_from_main(), vec!["self", "unused"]);
+        assert!(graph.to_dot().contains("\"even\" -> \"odd\" [color=red];"));
+    }
+}
@@ -0,0 +1,422 @@
+//! Dataflow analyses
+//!
+//! An [`Analysis`] describes the facts it tracks, how they combine where control flow merges and
+//! how every instruction transforms them. [`solve`] iterates the transfer functions over the
+//! control-flow graph until the facts no longer change, in the direction of the analysis.
+//!
+//! Three classic analyses are provided:
+//! - [`Liveness`] (backward): the variables whose current value may still be read.
+//! - [`ReachingDefinitions`] (forward): the assignments whose value may still be in a variable.
+//! - [`AvailableExpressions`] (forward): the expressions that are computed on every path and whose
+//!   operands have not been assigned since.
+
+use crate::ir::{BinaryOp, BlockId, Function, InstrKind, Operand, UnaryOp, Var};
+use std::collections::BTreeSet;
+
+/// A point in a function: the instruction with the given index in a block, or the terminator if
+/// the index is the number of instructions
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct Location {
+    pub block: BlockId,
+    pub index: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+pub trait Analysis {
+    type Fact: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// The fact at the entry of the function, or at its returns for a backward analysis
+    fn boundary(&self, function: &Function) -> Self::Fact;
+
+    /// The starting fact of every other block, which the meet must not lose information against
+    fn initial(&self, function: &Function) -> Self::Fact;
+
+    /// Combine the fact from another path into `fact`
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+    /// Apply the effect of the instruction or terminator at the location to the fact
+    fn transfer(&self, function: &Function, location: Location, fact: &mut Self::Fact);
+}
+
+/// The facts at the start and at the end of every block, indexed by block number
+#[derive(Debug, PartialEq, Clone)]
+pub struct Results<F> {
+    pub block_entry: Vec<F>,
+    pub block_exit: Vec<F>,
+}
+
+impl<F: Clone> Results<F> {
+    /// The facts in front of every instruction of a block and, as the last element, after its
+    /// terminator
+    pub fn block_facts<A: Analysis<Fact = F>>(
+        &self,
+        analysis: &A,
+        function: &Function,
+        block: BlockId,
+    ) -> Vec<F> {
+        let count = function.block(block).instrs.len() + 1;
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut fact = self.block_entry[block.0 as usize].clone();
+                let mut facts = vec![fact.clone()];
+                for index in 0..count {
+                    analysis.transfer(function, Location { block, index }, &mut fact);
+                    facts.push(fact.clone());
+                }
+                facts
+            }
+            Direction::Backward => {
+                let mut fact = self.block_exit[block.0 as usize].clone();
+                let mut facts = vec![fact.clone()];
+                for index in (0..count).rev() {
+                    analysis.transfer(function, Location { block, index }, &mut fact);
+                    facts.push(fact.clone());
+                }
+                facts.reverse();
+                facts
+            }
+        }
+    }
+}
+
+/// Compute the fixed point of the analysis
+pub fn solve<A: Analysis>(analysis: &A, function: &Function) -> Results<A::Fact> {
+    let predecessors = function.predecessors();
+    // Visiting the blocks in reverse postorder, or in postorder for backward analyses, makes most
+    // facts final in the first round. Unreachable blocks are visited last.
+    let mut order = function.reverse_postorder();
+    for block in function.block_ids() {
+        if !order.contains(&block) {
+            order.push(block);
+        }
+    }
+    if A::DIRECTION == Direction::Backward {
+        order.reverse();
+    }
+
+    let initial = analysis.initial(function);
+    let mut results = Results {
+        block_entry: vec![initial.clone(); function.blocks.len()],
+        block_exit: vec![initial; function.blocks.len()],
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order {
+            let index = block.0 as usize;
+            let count = function.block(block).instrs.len() + 1;
+            match A::DIRECTION {
+                Direction::Forward => {
+                    let mut fact = if block == BlockId(0) {
+                        analysis.boundary(function)
+                    } else {
+                        let mut sources = predecessors[index].iter();
+                        match sources.next() {
+                            Some(first) => {
+                                let mut fact = results.block_exit[first.0 as usize].clone();
+                                for other in sources {
+                                    analysis.meet(&mut fact, &results.block_exit[other.0 as usize]);
+                                }
+                                fact
+                            }
+                            None => analysis.boundary(function),
+                        }
+                    };
+                    results.block_entry[index] = fact.clone();
+                    for position in 0..count {
+                        analysis.transfer(
+                            function,
+                            Location {
+                                block,
+                                index: position,
+                            },
+                            &mut fact,
+                        );
+                    }
+                    if results.block_exit[index] != fact {
+                        results.block_exit[index] = fact;
+                        changed = true;
+                    }
+                }
+                Direction::Backward => {
+                    let successors = function.successors(block);
+                    let mut sources = successors.iter();
+                    let mut fact = match sources.next() {
+                        Some(first) => {
+                            let mut fact = results.block_entry[first.0 as usize].clone();
+                            for other in sources {
+                                analysis.meet(&mut fact, &results.block_entry[other.0 as usize]);
+                            }
+                            fact
+                        }
+                        None => analysis.boundary(function),
+                    };
+                    results.block_exit[index] = fact.clone();
+                    for position in (0..count).rev() {
+                        analysis.transfer(
+                            function,
+                            Location {
+                                block,
+                                index: position,
+                            },
+                            &mut fact,
+                        );
+                    }
+                    if results.block_entry[index] != fact {
+                        results.block_entry[index] = fact;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+    results
+}
+
+/// The variables that are read at the location
+fn uses(function: &Function, location: Location) -> Vec<Var> {
+    let block = function.block(location.block);
+    match block.instrs.get(location.index) {
+        Some(instr) => instr.uses(),
+        None => block.terminator.uses(),
+    }
+}
+
+/// The variable that is assigned at the location
+fn definition(function: &Function, location: Location) -> Option<Var> {
+    function
+        .block(location.block)
+        .instrs
+        .get(location.index)
+        .and_then(|instr| instr.dest())
+}
+
+/// Live variables: a variable is live at a point if some path from there reads it before it is
+/// assigned. In SSA form, the operands of a phi count as read at the phi.
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Fact = BTreeSet<Var>;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self, _: &Function) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn initial(&self, _: &Function) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other);
+    }
+
+    fn transfer(&self, function: &Function, location: Location, fact: &mut Self::Fact) {
+        if let Some(var) = definition(function, location) {
+            fact.remove(&var);
+        }
+        fact.extend(uses(function, location));
+    }
+}
+
+/// Reaching definitions: the locations of the assignments that may have given the variables
+/// their current values
+pub struct ReachingDefinitions;
+
+impl Analysis for ReachingDefinitions {
+    type Fact = BTreeSet<Location>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self, _: &Function) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn initial(&self, _: &Function) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other);
+    }
+
+    fn transfer(&self, function: &Function, location: Location, fact: &mut Self::Fact) {
+        if let Some(var) = definition(function, location) {
+            fact.retain(|&other| definition(function, other) != Some(var));
+            fact.insert(location);
+        }
+    }
+}
+
+/// An expression that is computed by an instruction
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Expression {
+    Unary(UnaryOp, Operand),
+    Binary(BinaryOp, Operand, Operand),
+}
+
+impl Expression {
+    fn of(function: &Function, location: Location) -> Option<Expression> {
+        match &function
+            .block(location.block)
+            .instrs
+            .get(location.index)?
+            .kind
+        {
+            InstrKind::Unary { op, operand, .. } => Some(Expression::Unary(*op, *operand)),
+            InstrKind::Binary { op, lhs, rhs, .. } => Some(Expression::Binary(*op, *lhs, *rhs)),
+            _ => None,
+        }
+    }
+
+    fn reads(&self, var: Var) -> bool {
+        let operands = match self {
+            Expression::Unary(_, operand) => vec![operand],
+            Expression::Binary(_, lhs, rhs) => vec![lhs, rhs],
+        };
+        operands.contains(&&Operand::Var(var))
+    }
+}
+
+/// Available expressions: the expressions that every path computes, without assigning to their
+/// operands afterwards. Facts are indices into [`AvailableExpressions::expressions`].
+pub struct AvailableExpressions {
+    /// Every distinct expression of the function
+    pub expressions: Vec<Expression>,
+}
+
+impl AvailableExpressions {
+    pub fn new(function: &Function) -> Self {
+        let mut expressions = Vec::new();
+        for block in function.block_ids() {
+            for index in 0..function.block(block).instrs.len() {
+                if let Some(expression) = Expression::of(function, Location { block, index }) {
+                    if !expressions.contains(&expression) {
+                        expressions.push(expression);
+                    }
+                }
+            }
+        }
+        AvailableExpressions { expressions }
+    }
+
+    fn index(&self, expression: Expression) -> usize {
+        self.expressions
+            .iter()
+            .position(|&other| other == expression)
+            .expect("all expressions were collected")
+    }
+}
+
+impl Analysis for AvailableExpressions {
+    type Fact = BTreeSet<usize>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self, _: &Function) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn initial(&self, _: &Function) -> Self::Fact {
+        (0..self.expressions.len()).collect()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.retain(|index| other.contains(index));
+    }
+
+    fn transfer(&self, function: &Function, location: Location, fact: &mut Self::Fact) {
+        if let Some(expression) = Expression::of(function, location) {
+            fact.insert(self.index(expression));
+        }
+        if let Some(var) = definition(function, location) {
+            fact.retain(|&index| !self.expressions[index].reads(var));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::dataflow::{
+        solve, AvailableExpressions, Expression, Liveness, Location, ReachingDefinitions,
+    };
+    use crate::ir::{lower_text, BinaryOp, BlockId, Function, Operand, Var};
+    use std::collections::BTreeSet;
+
+    fn function(text: &str) -> Function {
+        lower_text(text).functions.remove(0)
+    }
+
+    fn names(function: &Function, vars: &BTreeSet<Var>) -> Vec<String> {
+        vars.iter().map(|&var| function.var_text(var)).collect()
+    }
+
+    #[test]
+    fn liveness() {
+        // bb0: a = 1, b = 2, branch; bb1: a = b; bb2: return a
+        let function = function("int f() { a = 1; b = 2; if (b > 1) a = b; return a; }");
+        let results = solve(&Liveness, &function);
+        assert_eq!(
+            names(&function, &results.block_entry[0]),
+            Vec::<String>::new()
+        );
+        assert_eq!(names(&function, &results.block_exit[0]), vec!["a", "b"]);
+        assert_eq!(names(&function, &results.block_entry[1]), vec!["b"]);
+        assert_eq!(names(&function, &results.block_entry[2]), vec!["a"]);
+        let facts = results.block_facts(&Liveness, &function, BlockId(0));
+        let facts: Vec<_> = facts.iter().map(|fact| names(&function, fact)).collect();
+        assert_eq!(
+            facts,
+            vec![
+                vec![],
+                vec!["a"],
+                vec!["a", "b"],
+                vec!["a", "b", "%0"],
+                vec!["a", "b"]
+            ]
+        );
+    }
+
+    #[test]
+    fn reaching_definitions() {
+        let function = function("int f() { a = 1; b = 2; if (b > 1) a = b; return a; }");
+        let results = solve(&ReachingDefinitions, &function);
+        let at = |block, index| Location {
+            block: BlockId(block),
+            index,
+        };
+        assert_eq!(
+            results.block_entry[2],
+            BTreeSet::from([at(0, 0), at(0, 1), at(0, 2), at(1, 0)])
+        );
+        assert_eq!(
+            results.block_exit[1],
+            BTreeSet::from([at(0, 1), at(0, 2), at(1, 0)])
+        );
+    }
+
+    #[test]
+    fn available_expressions() {
+        let function = function(
+            "int f() { a = 1; b = a + 1; if (b > 1) { c = a + 1; a = 2; } return a + 1; }",
+        );
+        let analysis = AvailableExpressions::new(&function);
+        let results = solve(&analysis, &function);
+        let sum = analysis.index(Expression::Binary(
+            BinaryOp::Add,
+            Operand::Var(Var(0)),
+            Operand::Const(crate::ir::Constant::Int(1)),
+        ));
+        assert!(results.block_entry[1].contains(&sum));
+        // `a` is assigned on one of the paths
+        assert!(!results.block_entry[2].contains(&sum));
+    }
+}
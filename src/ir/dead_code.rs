@@ -0,0 +1,233 @@
+//! Unreachable code and unused assignments
+//!
+//! Statements after a `return` can never run; lowering puts them into blocks without a path from
+//! the entry. An assignment to a local variable is unused if no path from it reads the variable
+//! before it is assigned again, which [liveness](crate::ir::dataflow::Liveness) tells. Both are
+//! reported as [`Warning`]s and can be removed with [`remove_dead_code`].
+
+use crate::ir::dataflow::{solve, Liveness};
+use crate::ir::{BinaryOp, Constant, Function, Instr, InstrKind, Operand, VarKind};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum WarningKind {
+    UnreachableCode,
+    UnusedAssignment,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}", self.message, self.line)
+    }
+}
+
+/// Find the unreachable code and the unused assignments of a function that was just lowered,
+/// ordered by line
+pub fn dead_code_warnings(function: &Function) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let reachable = reachable_blocks(function);
+    let predecessors = function.predecessors();
+    for block in function.block_ids() {
+        // Only the first block of unreachable code is reported
+        if reachable[block.0 as usize] || !predecessors[block.0 as usize].is_empty() {
+            continue;
+        }
+        let block = function.block(block);
+        let line = block
+            .instrs
+            .first()
+            .map_or(block.terminator_line, |instr| instr.line);
+        warnings.push(Warning {
+            kind: WarningKind::UnreachableCode,
+            message: "unreachable code".to_string(),
+            line,
+        });
+    }
+
+    let liveness = solve(&Liveness, function);
+    for block in function.block_ids() {
+        if !reachable[block.0 as usize] {
+            continue;
+        }
+        let facts = liveness.block_facts(&Liveness, function, block);
+        for (instr, live) in function.block(block).instrs.iter().zip(&facts[1..]) {
+            let Some(dest) = instr.dest() else {
+                continue;
+            };
+            let var = function.var(dest);
+            if var.kind == VarKind::Local && !live.contains(&dest) {
+                warnings.push(Warning {
+                    kind: WarningKind::UnusedAssignment,
+                    message: format!("value assigned to '{}' is never read", var.name),
+                    line: instr.line,
+                });
+            }
+        }
+    }
+    warnings.sort_by_key(|warning| warning.line);
+    warnings
+}
+
+fn reachable_blocks(function: &Function) -> Vec<bool> {
+    let mut reachable = vec![false; function.blocks.len()];
+    for block in function.reverse_postorder() {
+        reachable[block.0 as usize] = true;
+    }
+    reachable
+}
+
+/// Remove unreachable blocks and assignments whose value is never read, including those of
+/// temporaries. Calls are kept for their effects, but lose an unused result. Returns whether
+/// anything changed.
+pub fn remove_dead_code(function: &mut Function) -> bool {
+    let blocks = function.blocks.len();
+    function.remove_unreachable_blocks();
+    let mut changed = function.blocks.len() != blocks;
+    // Removing an assignment can make the assignments of its operands unused
+    loop {
+        let liveness = solve(&Liveness, function);
+        let mut removed = false;
+        for block in function.block_ids() {
+            let facts = liveness.block_facts(&Liveness, function, block);
+            let mut index = 0;
+            let instrs = &mut function.blocks[block.0 as usize].instrs;
+            instrs.retain_mut(|instr| {
+                let live = &facts[index + 1];
+                index += 1;
+                let Some(dest) = instr.dest() else {
+                    return true;
+                };
+                if live.contains(&dest) || may_fail(instr) {
+                    return true;
+                }
+                removed = true;
+                if let InstrKind::Call { dest, .. } = &mut instr.kind {
+                    *dest = None;
+                    return true;
+                }
+                false
+            });
+        }
+        if !removed {
+            break;
+        }
+        changed = true;
+    }
+    changed
+}
+
+/// Integer division by zero fails at run time, so it must stay
+fn may_fail(instr: &Instr) -> bool {
+    match instr.kind {
+        InstrKind::Binary {
+            op: BinaryOp::Div,
+            rhs,
+            ..
+        } => match rhs {
+            Operand::Const(Constant::Int(divisor)) => divisor == 0,
+            Operand::Const(_) => false,
+            Operand::Var(_) => true,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{dead_code_warnings, lower_text, remove_dead_code, WarningKind};
+
+    fn warnings(text: &str) -> Vec<String> {
+        lower_text(text)
+            .functions
+            .iter()
+            .flat_map(dead_code_warnings)
+            .map(|warning| warning.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn unreachable_statements() {
+        assert_eq!(
+            warnings(
+                "int f() {\n\
+                 \tif (true) {\n\
+                 \t\treturn 1;\n\
+                 \t\tprintf(2);\n\
+                 \t}\n\
+                 \treturn 3;\n\
+                 \tif (false) printf(4);\n\
+                 \tprintf(5);\n\
+                 }"
+            ),
+            vec!["unreachable code at line 4", "unreachable code at line 7"]
+        );
+        assert_eq!(
+            warnings("void main() { return; return; }"),
+            vec!["unreachable code at line 1"]
+        );
+    }
+
+    #[test]
+    fn unused_assignments() {
+        let text = "int f() {\n\
+                    \tx = 5;\n\
+                    \tif (x > 3) {\n\
+                    \t\tz = x * 2;\n\
+                    \t\tx = x + 1;\n\
+                    \t}\n\
+                    \treturn x;\n\
+                    }";
+        let found: Vec<_> = lower_text(text)
+            .functions
+            .iter()
+            .flat_map(dead_code_warnings)
+            .collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, WarningKind::UnusedAssignment);
+        assert_eq!(
+            found[0].to_string(),
+            "value assigned to 'z' is never read at line 4"
+        );
+
+        assert_eq!(
+            warnings("void main() { a = 1; a = 2; if (a > 1) a = 3; b = a = 4; printf(b); }"),
+            vec![
+                "value assigned to 'a' is never read at line 1",
+                "value assigned to 'a' is never read at line 1"
+            ]
+        );
+        assert!(warnings("void main() { a = 1; if (a > 0) a = 2; printf(a); }").is_empty());
+    }
+
+    #[test]
+    fn removal() {
+        let mut module = lower_text(
+            "int f() { a = 1; b = a * 2; c = a / b; d = g(); return a; printf(a); } int g() { return 1; }",
+        );
+        assert!(remove_dead_code(&mut module.functions[0]));
+        assert_eq!(
+            module.functions[0].to_string(),
+            "function int f() {\n\
+             \x20 var a: int\n\
+             \x20 var b: int\n\
+             \x20 var c: int\n\
+             \x20 var d: int\n\
+             bb0:\n\
+             \x20 a = 1\n\
+             \x20 %0 = a * 2\n\
+             \x20 b = %0\n\
+             \x20 %1 = a / b\n\
+             \x20 call g()\n\
+             \x20 return a\n\
+             }\n"
+        );
+        assert!(!remove_dead_code(&mut module.functions[0]));
+    }
+}
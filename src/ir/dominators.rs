@@ -0,0 +1,187 @@
+//! Dominator trees and dominance frontiers
+//!
+//! A block `a` dominates a block `b` if every path from the entry to `b` passes through `a`. The
+//! immediate dominators are computed with the iterative algorithm of Cooper, Harvey and Kennedy,
+//! "A Simple, Fast Dominance Algorithm". Blocks that cannot be reached from the entry are not part
+//! of the tree.
+
+use crate::ir::{BlockId, Function};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Dominators {
+    /// The immediate dominator of every reachable block; the entry is its own
+    idom: Vec<Option<BlockId>>,
+    /// The position of every reachable block in reverse postorder
+    order: Vec<Option<usize>>,
+}
+
+impl Dominators {
+    pub fn compute(function: &Function) -> Self {
+        let rpo = function.reverse_postorder();
+        let predecessors = function.predecessors();
+        let mut order = vec![None; function.blocks.len()];
+        for (position, block) in rpo.iter().enumerate() {
+            order[block.0 as usize] = Some(position);
+        }
+        let mut dominators = Dominators {
+            idom: vec![None; function.blocks.len()],
+            order,
+        };
+        dominators.idom[0] = Some(BlockId(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &rpo[1..] {
+                let mut new_idom = None;
+                for &predecessor in &predecessors[block.0 as usize] {
+                    if dominators.idom[predecessor.0 as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(other) => dominators.intersect(predecessor, other),
+                    });
+                }
+                if dominators.idom[block.0 as usize] != new_idom {
+                    dominators.idom[block.0 as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    /// The closest common dominator of two blocks
+    fn intersect(&self, mut a: BlockId, mut b: BlockId) -> BlockId {
+        while a != b {
+            while self.position(a) > self.position(b) {
+                a = self.idom[a.0 as usize].expect("processed blocks have a dominator");
+            }
+            while self.position(b) > self.position(a) {
+                b = self.idom[b.0 as usize].expect("processed blocks have a dominator");
+            }
+        }
+        a
+    }
+
+    fn position(&self, block: BlockId) -> usize {
+        self.order[block.0 as usize].expect("only reachable blocks are compared")
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.order[block.0 as usize].is_some()
+    }
+
+    /// The immediate dominator of a block, `None` for the entry and unreachable blocks
+    pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block.0 as usize].filter(|&idom| idom != block)
+    }
+
+    /// Whether `a` dominates `b`; every reachable block dominates itself
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.immediate_dominator(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// The blocks immediately dominated by a block, in increasing order
+    pub fn children(&self, block: BlockId) -> Vec<BlockId> {
+        (0..self.idom.len() as u32)
+            .map(BlockId)
+            .filter(|&child| self.immediate_dominator(child) == Some(block))
+            .collect()
+    }
+
+    /// The dominance frontier of every block, indexed by block number: the blocks where the
+    /// dominance of a block ends because they also have predecessors it does not dominate
+    pub fn frontiers(&self, function: &Function) -> Vec<Vec<BlockId>> {
+        let mut frontiers = vec![Vec::new(); function.blocks.len()];
+        for (block, predecessors) in function.predecessors().into_iter().enumerate() {
+            let block = BlockId(block as u32);
+            let Some(idom) = self.idom[block.0 as usize] else {
+                continue;
+            };
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors {
+                let mut runner = predecessor;
+                while self.is_reachable(runner) && runner != idom {
+                    let frontier: &mut Vec<BlockId> = &mut frontiers[runner.0 as usize];
+                    if !frontier.contains(&block) {
+                        frontier.push(block);
+                    }
+                    runner =
+                        self.idom[runner.0 as usize].expect("reachable blocks have a dominator");
+                }
+            }
+        }
+        for frontier in &mut frontiers {
+            frontier.sort();
+        }
+        frontiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{lower_text, BlockId, Dominators};
+
+    #[test]
+    fn nested_ifs() {
+        let text = "void main() { a = 1; if (a > 0) { if (a > 1) { a = 2; } a = 3; } printf(a); }";
+        let module = lower_text(text);
+        let function = &module.functions[0];
+        let dominators = Dominators::compute(function);
+        // bb0 -> bb1 (outer then), bb2 (outer end); bb1 -> bb3 (inner then), bb4 (inner end)
+        let idoms: Vec<_> = function
+            .block_ids()
+            .map(|block| dominators.immediate_dominator(block))
+            .collect();
+        assert_eq!(
+            idoms,
+            vec![
+                None,
+                Some(BlockId(0)),
+                Some(BlockId(0)),
+                Some(BlockId(1)),
+                Some(BlockId(1))
+            ]
+        );
+        assert_eq!(
+            dominators.frontiers(function),
+            vec![
+                vec![],
+                vec![BlockId(2)],
+                vec![],
+                vec![BlockId(4)],
+                vec![BlockId(2)]
+            ]
+        );
+        assert_eq!(
+            dominators.children(BlockId(1)),
+            vec![BlockId(3), BlockId(4)]
+        );
+        assert!(dominators.dominates(BlockId(1), BlockId(3)));
+        assert!(!dominators.dominates(BlockId(3), BlockId(4)));
+    }
+
+    #[test]
+    fn unreachable_blocks() {
+        let text = "int f() { return 1; x = 2; return x; }";
+        let module = lower_text(text);
+        let dominators = Dominators::compute(&module.functions[0]);
+        assert!(!dominators.is_reachable(BlockId(1)));
+        assert_eq!(dominators.immediate_dominator(BlockId(1)), None);
+        assert!(!dominators.dominates(BlockId(0), BlockId(1)));
+    }
+}
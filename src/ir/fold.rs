@@ -0,0 +1,438 @@
+//! Constant folding and propagation
+//!
+//! A forward dataflow analysis finds the variables that hold the same constant on every path.
+//! Their reads are replaced by the constant, and operators whose operands are all constant are
+//! evaluated the way the generated code evaluates them: integers wrap around at 32 bits and floats
+//! are 64 bits wide. A branch on a constant condition becomes a jump, the blocks that can no longer
+//! be reached are removed and a block that is only entered from the block before it is merged into
+//! that block. This repeats until nothing changes.
+//!
+//! Integer division by zero is left alone so it still fails at run time, as is every operation
+//! whose result would be NaN. Variables that may be read before they are assigned are not
+//! constant.
+
+use crate::ast::Type;
+use crate::ir::dataflow::{solve, Analysis, Direction, Location};
+use crate::ir::{
+    BinaryOp, Constant, Function, Instr, InstrKind, Operand, Terminator, UnaryOp, Var,
+};
+
+/// What is known about the value of a variable
+#[derive(Debug, Copy, Clone)]
+enum Value {
+    /// No assignment has been seen yet
+    Undefined,
+    Constant(Constant),
+    /// Different values or a value that is only known at run time
+    Varying,
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Undefined, Value::Undefined) | (Value::Varying, Value::Varying) => true,
+            (Value::Constant(a), Value::Constant(b)) => same_constant(*a, *b),
+            _ => false,
+        }
+    }
+}
+
+/// Floats are compared by their bits, so `0.0` and `-0.0` are different constants
+fn same_constant(a: Constant, b: Constant) -> bool {
+    match (a, b) {
+        (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+        (a, b) => a == b,
+    }
+}
+
+fn meet(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Undefined, value) | (value, Value::Undefined) => value,
+        (Value::Constant(a), Value::Constant(b)) if same_constant(a, b) => Value::Constant(a),
+        _ => Value::Varying,
+    }
+}
+
+/// The facts are the values of all variables, indexed by variable number
+struct ConstantPropagation;
+
+impl ConstantPropagation {
+    fn operand(fact: &[Value], operand: Operand) -> Value {
+        match operand {
+            Operand::Var(var) => fact[var.0 as usize],
+            Operand::Const(constant) => Value::Constant(constant),
+        }
+    }
+}
+
+impl Analysis for ConstantPropagation {
+    type Fact = Vec<Value>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self, function: &Function) -> Self::Fact {
+        vec![Value::Varying; function.vars.len()]
+    }
+
+    fn initial(&self, function: &Function) -> Self::Fact {
+        vec![Value::Undefined; function.vars.len()]
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        for (value, &other) in fact.iter_mut().zip(other) {
+            *value = meet(*value, other);
+        }
+    }
+
+    fn transfer(&self, function: &Function, location: Location, fact: &mut Self::Fact) {
+        let Some(instr) = function.block(location.block).instrs.get(location.index) else {
+            return;
+        };
+        let Some(dest) = instr.dest() else {
+            return;
+        };
+        let operands: Vec<Value> = instr
+            .operands()
+            .into_iter()
+            .map(|operand| Self::operand(fact, operand))
+            .collect();
+        let value = match &instr.kind {
+            InstrKind::Call { .. } => Value::Varying,
+            // The operands of a phi come from different predecessors
+            InstrKind::Phi { .. } => operands.into_iter().fold(Value::Undefined, meet),
+            kind => {
+                if operands.contains(&Value::Varying) {
+                    Value::Varying
+                } else if operands.contains(&Value::Undefined) {
+                    Value::Undefined
+                } else {
+                    let constants: Vec<Operand> = operands
+                        .iter()
+                        .map(|value| match value {
+                            Value::Constant(constant) => Operand::Const(*constant),
+                            _ => unreachable!("all operands are constant"),
+                        })
+                        .collect();
+                    match evaluate(function, kind, dest, &constants) {
+                        Some(constant) => Value::Constant(constant),
+                        None => Value::Varying,
+                    }
+                }
+            }
+        };
+        fact[dest.0 as usize] = value;
+    }
+}
+
+/// Evaluate an instruction whose operands are all constants
+fn evaluate(
+    function: &Function,
+    kind: &InstrKind,
+    dest: Var,
+    operands: &[Operand],
+) -> Option<Constant> {
+    let constant = |index: usize| match operands[index] {
+        Operand::Const(constant) => constant,
+        Operand::Var(_) => unreachable!("only constants are evaluated"),
+    };
+    let result = match kind {
+        InstrKind::Copy { .. } => constant(0),
+        InstrKind::Unary { op, .. } => unary(*op, constant(0), function.var(dest).ty)?,
+        InstrKind::Binary { op, .. } => binary(*op, constant(0), constant(1))?,
+        InstrKind::Call { .. } | InstrKind::Print { .. } | InstrKind::Phi { .. } => return None,
+    };
+    match result {
+        Constant::Float(value) if value.is_nan() => None,
+        result => Some(result),
+    }
+}
+
+fn unary(op: UnaryOp, operand: Constant, ty: Type) -> Option<Constant> {
+    match (op, operand) {
+        (UnaryOp::Neg, Constant::Int(value)) => Some(Constant::Int(value.wrapping_neg())),
+        (UnaryOp::Neg, Constant::Float(value)) => Some(Constant::Float(-value)),
+        (UnaryOp::Convert, Constant::Int(value)) if ty == Type::Float => {
+            Some(Constant::Float(value as f64))
+        }
+        (UnaryOp::Convert, Constant::Float(value)) if ty == Type::Int => {
+            Some(Constant::Int(value as i32))
+        }
+        (UnaryOp::Convert, constant) if constant.ty() == ty => Some(constant),
+        _ => None,
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Constant, rhs: Constant) -> Option<Constant> {
+    use std::cmp::Ordering;
+    let compare = |ordering: Option<Ordering>| {
+        let result = match ordering {
+            Some(ordering) => match op {
+                BinaryOp::Equal => ordering == Ordering::Equal,
+                BinaryOp::NotEqual => ordering != Ordering::Equal,
+                BinaryOp::Less => ordering == Ordering::Less,
+                BinaryOp::LessEqual => ordering != Ordering::Greater,
+                BinaryOp::Greater => ordering == Ordering::Greater,
+                BinaryOp::GreaterEqual => ordering != Ordering::Less,
+                _ => unreachable!("{:?} is not a comparison", op),
+            },
+            // Comparisons with NaN are false, except for !=
+            None => op == BinaryOp::NotEqual,
+        };
+        Some(Constant::Bool(result))
+    };
+    match (lhs, rhs) {
+        (Constant::Int(lhs), Constant::Int(rhs)) => match op {
+            BinaryOp::Add => Some(Constant::Int(lhs.wrapping_add(rhs))),
+            BinaryOp::Sub => Some(Constant::Int(lhs.wrapping_sub(rhs))),
+            BinaryOp::Mul => Some(Constant::Int(lhs.wrapping_mul(rhs))),
+            BinaryOp::Div if rhs == 0 => None,
+            BinaryOp::Div => Some(Constant::Int(lhs.wrapping_div(rhs))),
+            _ => compare(Some(lhs.cmp(&rhs))),
+        },
+        (Constant::Float(lhs), Constant::Float(rhs)) => match op {
+            BinaryOp::Add => Some(Constant::Float(lhs + rhs)),
+            BinaryOp::Sub => Some(Constant::Float(lhs - rhs)),
+            BinaryOp::Mul => Some(Constant::Float(lhs * rhs)),
+            BinaryOp::Div => Some(Constant::Float(lhs / rhs)),
+            _ => compare(lhs.partial_cmp(&rhs)),
+        },
+        (Constant::Bool(lhs), Constant::Bool(rhs)) => match op {
+            BinaryOp::Equal => Some(Constant::Bool(lhs == rhs)),
+            BinaryOp::NotEqual => Some(Constant::Bool(lhs != rhs)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Fold the constants of the function and simplify its control flow. Returns whether anything
+/// changed.
+pub fn fold_constants(function: &mut Function) -> bool {
+    let mut changed = false;
+    while fold_once(function) {
+        changed = true;
+    }
+    changed
+}
+
+fn fold_once(function: &mut Function) -> bool {
+    let results = solve(&ConstantPropagation, function);
+    let mut changed = false;
+    for block in function.block_ids() {
+        let facts = results.block_facts(&ConstantPropagation, function, block);
+        let index = block.0 as usize;
+        let mut instrs = std::mem::take(&mut function.blocks[index].instrs);
+        for (instr, fact) in instrs.iter_mut().zip(&facts) {
+            if let InstrKind::Phi { args, .. } = &mut instr.kind {
+                for (predecessor, operand) in args {
+                    let exit = &results.block_exit[predecessor.0 as usize];
+                    changed |= propagate(exit, operand);
+                }
+            } else {
+                for operand in instr.operands_mut() {
+                    changed |= propagate(fact, operand);
+                }
+            }
+            changed |= fold(function, instr);
+        }
+        function.blocks[index].instrs = instrs;
+
+        let fact = &facts[facts.len() - 2];
+        let block = &mut function.blocks[index];
+        for operand in block.terminator.operands_mut() {
+            changed |= propagate(fact, operand);
+        }
+        if let Terminator::Branch {
+            condition: Operand::Const(Constant::Bool(condition)),
+            then_block,
+            else_block,
+        } = block.terminator
+        {
+            block.terminator = Terminator::Jump(if condition { then_block } else { else_block });
+            changed = true;
+        }
+    }
+    if changed {
+        remove_stale_phi_args(function);
+        function.remove_unreachable_blocks();
+        function.merge_blocks();
+    }
+    changed
+}
+
+/// Replace a variable that holds a constant with the constant
+fn propagate(fact: &[Value], operand: &mut Operand) -> bool {
+    if let Operand::Var(var) = *operand {
+        if let Value::Constant(constant) = fact[var.0 as usize] {
+            *operand = Operand::Const(constant);
+            return true;
+        }
+    }
+    false
+}
+
+/// Replace an instruction whose operands are constants with a copy of its result
+fn fold(function: &Function, instr: &mut Instr) -> bool {
+    let Some(dest) = instr.dest() else {
+        return false;
+    };
+    let operands = instr.operands();
+    let constant = match &instr.kind {
+        InstrKind::Copy { .. } | InstrKind::Call { .. } | InstrKind::Print { .. } => return false,
+        InstrKind::Phi { .. } => match operands.first() {
+            Some(&Operand::Const(first))
+                if operands.iter().all(|operand| match operand {
+                    Operand::Const(constant) => same_constant(*constant, first),
+                    Operand::Var(_) => false,
+                }) =>
+            {
+                first
+            }
+            _ => return false,
+        },
+        kind => {
+            if operands
+                .iter()
+                .any(|operand| matches!(operand, Operand::Var(_)))
+            {
+                return false;
+            }
+            match evaluate(function, kind, dest, &operands) {
+                Some(constant) => constant,
+                None => return false,
+            }
+        }
+    };
+    instr.kind = InstrKind::Copy {
+        dest,
+        src: Operand::Const(constant),
+    };
+    true
+}
+
+/// Drop the operands of phis for edges that no longer exist
+fn remove_stale_phi_args(function: &mut Function) {
+    let predecessors = function.predecessors();
+    for (block, predecessors) in function.blocks.iter_mut().zip(predecessors) {
+        for instr in &mut block.instrs {
+            if let InstrKind::Phi { args, .. } = &mut instr.kind {
+                args.retain(|(predecessor, _)| predecessors.contains(predecessor));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{fold_constants, lower_text, to_ssa};
+
+    fn folded(text: &str) -> String {
+        let mut module = lower_text(text);
+        for function in &mut module.functions {
+            fold_constants(function);
+        }
+        module.to_string()
+    }
+
+    #[test]
+    fn example_folds_to_constant_return() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let output = folded(&text);
+        assert!(
+            output.starts_with(
+                "function int blub() {\n\
+                 \x20 var blub1: int\n\
+                 \x20 var blub2: int\n\
+                 \x20 var blub3: int\n\
+                 \x20 var blub4: int\n\
+                 bb0:\n\
+                 \x20 blub1 = 23\n\
+                 \x20 blub2 = 17\n\
+                 \x20 blub3 = 42\n\
+                 \x20 %0 = 59\n\
+                 \x20 %1 = 1357\n\
+                 \x20 blub4 = 1357\n\
+                 \x20 %2 = true\n\
+                 \x20 return 17\n\
+                 }\n"
+            ),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn numeric_semantics() {
+        assert_eq!(
+            folded(
+                "void main() { a = 2147483647 + 1; b = 7 / 2; c = 1 / 0; d = 7.0 / 2; e = -2.5 * 2; f = 0.0 / 0.0; printf(a); printf(b); printf(c); printf(d); printf(e); printf(f); }"
+            ),
+            "function void main() {\n\
+             \x20 var a: int\n\
+             \x20 var b: int\n\
+             \x20 var c: int\n\
+             \x20 var d: float\n\
+             \x20 var e: float\n\
+             \x20 var f: float\n\
+             bb0:\n\
+             \x20 %0 = -2147483648\n\
+             \x20 a = -2147483648\n\
+             \x20 %1 = 3\n\
+             \x20 b = 3\n\
+             \x20 %2 = 1 / 0\n\
+             \x20 c = %2\n\
+             \x20 %3 = 2.0\n\
+             \x20 %4 = 3.5\n\
+             \x20 d = 3.5\n\
+             \x20 %5 = 2.0\n\
+             \x20 %6 = 5.0\n\
+             \x20 %7 = -5.0\n\
+             \x20 e = -5.0\n\
+             \x20 %8 = 0.0 / 0.0\n\
+             \x20 f = %8\n\
+             \x20 print -2147483648\n\
+             \x20 print 3\n\
+             \x20 print c\n\
+             \x20 print 3.5\n\
+             \x20 print -5.0\n\
+             \x20 print f\n\
+             \x20 return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn constant_branches() {
+        assert_eq!(
+            folded("int f() { x = 1; if (false) x = 2; if (true) { if (x == 1) return x + 1; } return 0; }"),
+            "function int f() {\n\
+             \x20 var x: int\n\
+             bb0:\n\
+             \x20 x = 1\n\
+             \x20 %0 = true\n\
+             \x20 %1 = 2\n\
+             \x20 return 2\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn values_from_different_paths() {
+        // `x` is 1 on both paths, `y` is not
+        let output = folded(
+            "int f() { x = 1; y = 1; if (g()) { x = 1; y = 2; } return x + y; } bool g() { return true; }",
+        );
+        assert!(output.contains("%1 = 1 + y\n  return %1\n"), "{}", output);
+    }
+
+    #[test]
+    fn phis_in_ssa_form() {
+        let mut module =
+            lower_text("int f() { x = 1; if (g()) x = 1; return x; } bool g() { return true; }");
+        let mut function = to_ssa(&module.functions[0]);
+        fold_constants(&mut function);
+        assert!(function.to_string().contains("return 1\n"), "{}", function);
+        fold_constants(&mut module.functions[0]);
+        assert!(module.functions[0].to_string().contains("return 1\n"));
+    }
+}
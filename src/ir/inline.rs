@@ -0,0 +1,244 @@
+//! Inlining of calls
+//!
+//! A call is replaced by a copy of the body of the called function if that function is small
+//! enough and not recursive. The size of a function is the number of its instructions and
+//! terminators. Functions are processed in the order of the [call graph](CallGraph), callees
+//! first, so a function that is inlined already contains the inlined bodies of its own callees.
+//!
+//! The copied variables get the name of the inlined function as prefix, like `blub.blub1`. The
+//! returns of the copy assign the result of the call and jump to the code after the call. As a
+//! result the destination of the call may be assigned more than once, so inlining works on
+//! functions before they are converted into [SSA form](crate::ir::to_ssa).
+
+use crate::ir::{
+    BlockId, CallGraph, Function, Instr, InstrKind, Module, Operand, Terminator, Var, VarInfo,
+    VarKind,
+};
+
+/// The size up to which functions are inlined unless another limit is given
+pub const DEFAULT_INLINE_SIZE: usize = 16;
+
+/// The number of instructions and terminators of the function
+fn size(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .map(|block| block.instrs.len() + 1)
+        .sum()
+}
+
+/// Inline all calls of non-recursive functions with at most `max_size` instructions. Returns the
+/// number of inlined calls.
+pub fn inline_calls(module: &mut Module, max_size: usize) -> usize {
+    let graph = CallGraph::new(module);
+    let mut inlined = 0;
+    for component in graph.components() {
+        for caller in component {
+            loop {
+                let candidate = module.functions[caller].block_ids().find_map(|block| {
+                    let instrs = &module.functions[caller].block(block).instrs;
+                    instrs.iter().enumerate().find_map(|(index, instr)| {
+                        let InstrKind::Call { function, .. } = &instr.kind else {
+                            return None;
+                        };
+                        let callee = module.function(function)?;
+                        (!graph.is_recursive(function) && size(callee) <= max_size)
+                            .then(|| (block, index, callee.clone()))
+                    })
+                });
+                let Some((block, index, callee)) = candidate else {
+                    break;
+                };
+                inline_call(&mut module.functions[caller], block, index, &callee);
+                inlined += 1;
+            }
+            module.functions[caller].merge_blocks();
+        }
+    }
+    inlined
+}
+
+/// Replace the call at the given instruction by a copy of the callee
+fn inline_call(caller: &mut Function, block: BlockId, index: usize, callee: &Function) {
+    let call = &caller.block(block).instrs[index];
+    let (InstrKind::Call { dest, .. }, line) = (&call.kind, call.line) else {
+        unreachable!("only calls are inlined");
+    };
+    let dest = *dest;
+
+    // The code after the call continues in a new block
+    let continuation = BlockId(caller.blocks.len() as u32);
+    let rest = caller.blocks[block.0 as usize].instrs.split_off(index + 1);
+    caller.blocks[block.0 as usize].instrs.pop();
+    let mut after = caller.blocks[block.0 as usize].clone();
+    after.instrs = rest;
+    for successor in after.terminator.successors() {
+        rename_predecessor(caller, successor, block, continuation);
+    }
+    let offset = continuation.0 + 1;
+    caller.blocks[block.0 as usize].terminator = Terminator::Jump(BlockId(offset));
+    caller.blocks[block.0 as usize].terminator_line = line;
+    caller.blocks.push(after);
+
+    let vars = copy_vars(caller, callee);
+    let map_block = |block: BlockId| BlockId(block.0 + offset);
+    let map_operand = |operand: &mut Operand| {
+        if let Operand::Var(var) = operand {
+            *var = vars[var.0 as usize];
+        }
+    };
+    for original in &callee.blocks {
+        let mut copy = original.clone();
+        for instr in &mut copy.instrs {
+            for operand in instr.operands_mut() {
+                map_operand(operand);
+            }
+            if let Some(dest) = instr.dest_mut() {
+                *dest = vars[dest.0 as usize];
+            }
+            if let InstrKind::Phi { args, .. } = &mut instr.kind {
+                for (predecessor, _) in args {
+                    *predecessor = map_block(*predecessor);
+                }
+            }
+        }
+        for operand in copy.terminator.operands_mut() {
+            map_operand(operand);
+        }
+        copy.terminator = match copy.terminator {
+            Terminator::Jump(target) => Terminator::Jump(map_block(target)),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => Terminator::Branch {
+                condition,
+                then_block: map_block(then_block),
+                else_block: map_block(else_block),
+            },
+            Terminator::Return(value) => {
+                if let (Some(dest), Some(value)) = (dest, value) {
+                    copy.instrs.push(Instr {
+                        kind: InstrKind::Copy { dest, src: value },
+                        line: copy.terminator_line,
+                    });
+                }
+                Terminator::Jump(continuation)
+            }
+        };
+        caller.blocks.push(copy);
+    }
+}
+
+/// Add the variables of the callee to the caller and return where each of them ended up
+fn copy_vars(caller: &mut Function, callee: &Function) -> Vec<Var> {
+    let mut next_temp = caller
+        .vars
+        .iter()
+        .filter(|var| var.kind == VarKind::Temp)
+        .filter_map(|var| var.name.parse::<usize>().ok())
+        .max()
+        .map_or(0, |last| last + 1);
+    // A function that is inlined several times gets a number from the second copy on
+    let mut prefix = callee.name.clone();
+    let mut copies = 1;
+    while callee.vars.iter().any(|var| {
+        var.kind == VarKind::Local
+            && caller
+                .vars
+                .iter()
+                .any(|other| other.name == format!("{}.{}", prefix, var.name))
+    }) {
+        copies += 1;
+        prefix = format!("{}#{}", callee.name, copies);
+    }
+    callee
+        .vars
+        .iter()
+        .map(|var| {
+            let name = match var.kind {
+                VarKind::Local => format!("{}.{}", prefix, var.name),
+                VarKind::Temp => {
+                    next_temp += 1;
+                    (next_temp - 1).to_string()
+                }
+            };
+            caller.vars.push(VarInfo {
+                name,
+                ty: var.ty,
+                kind: var.kind,
+            });
+            Var(caller.vars.len() as u32 - 1)
+        })
+        .collect()
+}
+
+fn rename_predecessor(function: &mut Function, block: BlockId, from: BlockId, to: BlockId) {
+    for instr in &mut function.blocks[block.0 as usize].instrs {
+        if let InstrKind::Phi { args, .. } = &mut instr.kind {
+            for (predecessor, _) in args {
+                if *predecessor == from {
+                    *predecessor = to;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{fold_constants, inline_calls, lower_text, DEFAULT_INLINE_SIZE};
+
+    #[test]
+    fn small_functions() {
+        let mut module =
+            lower_text("int one() { return 1; } int two() { x = one(); return x + one(); } void main() { printf(two()); }");
+        assert_eq!(inline_calls(&mut module, DEFAULT_INLINE_SIZE), 3);
+        assert_eq!(
+            module.function("main").unwrap().to_string(),
+            "function void main() {\n\
+             \x20 var two.x: int\n\
+             bb0:\n\
+             \x20 %1 = 1\n\
+             \x20 two.x = %1\n\
+             \x20 %2 = 1\n\
+             \x20 %3 = two.x + %2\n\
+             \x20 %0 = %3\n\
+             \x20 print %0\n\
+             \x20 return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn branches_and_several_copies() {
+        let mut module = lower_text(
+            "int sign() { x = 3; if (x < 0) return 0 - 1; return 1; } void main() { a = sign(); b = sign(); printf(a + b); }",
+        );
+        inline_calls(&mut module, DEFAULT_INLINE_SIZE);
+        let main = module.function("main").unwrap();
+        let text = main.to_string();
+        assert!(
+            text.contains("var sign.x: int\n  var sign#2.x: int\n"),
+            "{}",
+            text
+        );
+        assert!(!text.contains("call"), "{}", text);
+        fold_constants(&mut module.functions[1]);
+        assert!(module.functions[1].to_string().contains("print 2\n"));
+    }
+
+    #[test]
+    fn recursion_and_size_limit() {
+        let mut module = lower_text(
+            "int f() { return f(); } int big() { a = 1; b = a + a; c = b * b; return c; } void main() { printf(f()); printf(big()); }",
+        );
+        assert_eq!(inline_calls(&mut module, 3), 0);
+        assert_eq!(inline_calls(&mut module, 10), 1);
+        assert!(module
+            .function("main")
+            .unwrap()
+            .to_string()
+            .contains("call f()"));
+    }
+}
@@ -0,0 +1,472 @@
+//! Lowering of the typed AST into the IR
+
+use crate::ast::{self, Expr, Literal, Statement, Type};
+use crate::cst::SyntaxKind;
+use crate::ir::{
+    BinaryOp, Block, BlockId, Constant, Function, Instr, InstrKind, Module, Operand, Terminator,
+    UnaryOp, Var, VarInfo, VarKind,
+};
+use crate::typeck::{CheckedProgram, FunctionInfo};
+use crate::TokenValue;
+
+/// Convert the value of a literal, whose range the type check has validated
+pub(crate) fn constant(literal: Literal) -> Constant {
+    match literal.value() {
+        TokenValue::Int(value) => {
+            Constant::Int(i32::try_from(value).expect("literals were checked"))
+        }
+        TokenValue::Float(value) => Constant::Float(value),
+        TokenValue::Bool(value) => Constant::Bool(value),
+    }
+}
+
+/// Lower all functions of the program
+pub fn lower(program: &CheckedProgram) -> Module {
+    let functions = program
+        .definitions()
+        .map(|(definition, info)| {
+            let mut lowering = Lowering {
+                program,
+                info,
+                function: Function {
+                    name: info.name.clone(),
+                    return_type: info.return_type,
+                    vars: info
+                        .locals
+                        .iter()
+                        .map(|local| VarInfo {
+                            name: local.name.clone(),
+                            ty: local.ty,
+                            kind: VarKind::Local,
+                        })
+                        .collect(),
+                    blocks: Vec::new(),
+                },
+                current: None,
+                instrs: Vec::new(),
+                next_temp: 0,
+                line: definition.name_token().line,
+            };
+            let entry = lowering.new_block();
+            lowering.start_block(entry);
+            for statement in definition.body().statements() {
+                lowering.statement(statement);
+            }
+            // Falling off the end returns zero, like in the code generators
+            if let Some(last) = definition.syntax().tokens().last() {
+                lowering.line = last.line;
+            }
+            let value = match info.return_type {
+                Type::Bool => Some(Operand::Const(Constant::Bool(false))),
+                Type::Float => Some(Operand::Const(Constant::Float(0.0))),
+                Type::Int => Some(Operand::Const(Constant::Int(0))),
+                Type::Void => None,
+            };
+            if lowering.current.is_some() {
+                lowering.terminate(Terminator::Return(value));
+            }
+            lowering.function
+        })
+        .collect();
+    Module { functions }
+}
+
+struct Lowering<'p> {
+    program: &'p CheckedProgram<'p>,
+    info: &'p FunctionInfo,
+    function: Function,
+    /// The block that receives the next instructions, if it is not terminated yet
+    current: Option<BlockId>,
+    instrs: Vec<Instr>,
+    next_temp: usize,
+    line: usize,
+}
+
+impl<'p> Lowering<'p> {
+    /// Create a block that is filled in later
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block {
+            instrs: Vec::new(),
+            terminator: Terminator::Return(None),
+            terminator_line: 0,
+        });
+        BlockId(self.function.blocks.len() as u32 - 1)
+    }
+
+    fn start_block(&mut self, block: BlockId) {
+        self.current = Some(block);
+    }
+
+    /// The block that receives instructions. Code after a `return` gets a block of its own,
+    /// which is unreachable.
+    fn current_block(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.start_block(block);
+                block
+            }
+        }
+    }
+
+    fn emit(&mut self, kind: InstrKind) {
+        self.current_block();
+        self.instrs.push(Instr {
+            kind,
+            line: self.line,
+        });
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current_block();
+        let block = &mut self.function.blocks[block.0 as usize];
+        block.instrs = std::mem::take(&mut self.instrs);
+        block.terminator = terminator;
+        block.terminator_line = self.line;
+        self.current = None;
+    }
+
+    /// Continue with the given block, falling through from the current one
+    fn continue_with(&mut self, block: BlockId) {
+        if self.current.is_some() {
+            self.terminate(Terminator::Jump(block));
+        }
+        self.start_block(block);
+    }
+
+    fn temp(&mut self, ty: Type) -> Var {
+        self.function.vars.push(VarInfo {
+            name: self.next_temp.to_string(),
+            ty,
+            kind: VarKind::Temp,
+        });
+        self.next_temp += 1;
+        Var(self.function.vars.len() as u32 - 1)
+    }
+
+    fn local(&self, name: &str) -> Var {
+        let index = self
+            .info
+            .locals
+            .iter()
+            .position(|local| local.name == name)
+            .expect("variables were checked");
+        Var(index as u32)
+    }
+
+    fn set_line(&mut self, node: &crate::cst::SyntaxNode) {
+        if let Some(token) = node.first_token() {
+            self.line = token.line;
+        }
+    }
+
+    fn expr_type(&self, expr: Expr) -> Type {
+        self.program.expr_type(self.info, expr)
+    }
+
+    /// Convert the operand to the given type if necessary
+    fn convert(&mut self, operand: Operand, to: Type) -> Operand {
+        let from = self.function.operand_type(operand);
+        if from == to || !matches!(to, Type::Int | Type::Float) {
+            return operand;
+        }
+        let dest = self.temp(to);
+        self.emit(InstrKind::Unary {
+            dest,
+            op: UnaryOp::Convert,
+            operand,
+        });
+        Operand::Var(dest)
+    }
+
+    fn assign(&mut self, name: &str, value: Expr) -> Var {
+        let dest = self.local(name);
+        let value = self.expr(value);
+        let value = self.convert(value, self.function.var(dest).ty);
+        self.emit(InstrKind::Copy { dest, src: value });
+        dest
+    }
+
+    fn statement(&mut self, statement: Statement) {
+        self.set_line(statement.syntax());
+        match statement {
+            Statement::Block(block) => {
+                for statement in block.statements() {
+                    self.statement(statement);
+                }
+            }
+            Statement::If(if_statement) => {
+                let condition = self.expr(if_statement.condition());
+                let then_block = self.new_block();
+                let end_block = self.new_block();
+                self.terminate(Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block: end_block,
+                });
+                self.start_block(then_block);
+                self.statement(if_statement.then_branch());
+                self.continue_with(end_block);
+            }
+            Statement::Return(ret) => {
+                let value = ret.value().map(|value| {
+                    let operand = self.expr(value);
+                    self.convert(operand, self.info.return_type)
+                });
+                self.set_line(statement.syntax());
+                self.terminate(Terminator::Return(value));
+            }
+            Statement::Printf(printf) => {
+                let value = self.expr(printf.argument());
+                self.emit(InstrKind::Print { value });
+            }
+            Statement::Assignment(assignment) => {
+                self.assign(assignment.target(), assignment.value());
+            }
+            Statement::Call(call) => {
+                let return_type = self
+                    .program
+                    .function(call.name())
+                    .expect("called functions were checked")
+                    .return_type;
+                // The result is assigned even though it is unused, so calls look the same
+                // everywhere
+                let dest = (return_type != Type::Void).then(|| self.temp(return_type));
+                self.emit(InstrKind::Call {
+                    dest,
+                    function: call.name().to_string(),
+                });
+            }
+        }
+    }
+
+    /// Lower an expression and return the operand that holds its value
+    fn expr(&mut self, expr: Expr) -> Operand {
+        self.set_line(expr.syntax());
+        match expr {
+            Expr::Assignment(assignment) => {
+                Operand::Var(self.assign(assignment.target(), assignment.value()))
+            }
+            Expr::Binary(binary) => {
+                let operator = binary.operator();
+                if matches!(operator, ast::BinaryOp::And | ast::BinaryOp::Or) {
+                    return self.short_circuit(binary);
+                }
+                let ty = self.expr_type(expr);
+                let lhs_type = self.expr_type(binary.lhs());
+                let rhs_type = self.expr_type(binary.rhs());
+                let operand_type = match (lhs_type, rhs_type) {
+                    (Type::Int, Type::Int) => Type::Int,
+                    (Type::Bool, Type::Bool) => Type::Bool,
+                    _ => Type::Float,
+                };
+                let mut lhs = self.expr(binary.lhs());
+                // An assignment in the right operand must not change the left one
+                if let Operand::Var(var) = lhs {
+                    if contains_assignment(binary.rhs()) {
+                        let copy = self.temp(self.function.var(var).ty);
+                        self.emit(InstrKind::Copy {
+                            dest: copy,
+                            src: lhs,
+                        });
+                        lhs = Operand::Var(copy);
+                    }
+                }
+                let lhs = self.convert(lhs, operand_type);
+                let rhs = self.expr(binary.rhs());
+                let rhs = self.convert(rhs, operand_type);
+                let dest = self.temp(ty);
+                self.set_line(binary.syntax());
+                self.emit(InstrKind::Binary {
+                    dest,
+                    op: binary_op(operator),
+                    lhs,
+                    rhs,
+                });
+                Operand::Var(dest)
+            }
+            Expr::Unary(unary) => {
+                let operand = self.expr(unary.operand());
+                let dest = self.temp(self.expr_type(expr));
+                self.emit(InstrKind::Unary {
+                    dest,
+                    op: UnaryOp::Neg,
+                    operand,
+                });
+                Operand::Var(dest)
+            }
+            Expr::Literal(literal) => Operand::Const(constant(literal)),
+            Expr::Name(name) => Operand::Var(self.local(name.name())),
+            Expr::Call(call) => {
+                let dest = self.temp(self.expr_type(expr));
+                self.emit(InstrKind::Call {
+                    dest: Some(dest),
+                    function: call.name().to_string(),
+                });
+                Operand::Var(dest)
+            }
+            Expr::Paren(paren) => self.expr(paren.inner()),
+        }
+    }
+
+    /// `a && b` and `a || b` only evaluate `b` if `a` does not decide the result
+    fn short_circuit(&mut self, binary: ast::BinaryExpr) -> Operand {
+        let is_and = binary.operator() == ast::BinaryOp::And;
+        let result = self.temp(Type::Bool);
+        let lhs = self.expr(binary.lhs());
+        self.emit(InstrKind::Copy {
+            dest: result,
+            src: lhs,
+        });
+        let rhs_block = self.new_block();
+        let end_block = self.new_block();
+        let (then_block, else_block) = if is_and {
+            (rhs_block, end_block)
+        } else {
+            (end_block, rhs_block)
+        };
+        self.terminate(Terminator::Branch {
+            condition: Operand::Var(result),
+            then_block,
+            else_block,
+        });
+        self.start_block(rhs_block);
+        let rhs = self.expr(binary.rhs());
+        self.emit(InstrKind::Copy {
+            dest: result,
+            src: rhs,
+        });
+        self.continue_with(end_block);
+        Operand::Var(result)
+    }
+}
+
+fn binary_op(operator: ast::BinaryOp) -> BinaryOp {
+    match operator {
+        ast::BinaryOp::Add => BinaryOp::Add,
+        ast::BinaryOp::Sub => BinaryOp::Sub,
+        ast::BinaryOp::Mul => BinaryOp::Mul,
+        ast::BinaryOp::Div => BinaryOp::Div,
+        ast::BinaryOp::Equal => BinaryOp::Equal,
+        ast::BinaryOp::NotEqual => BinaryOp::NotEqual,
+        ast::BinaryOp::Less => BinaryOp::Less,
+        ast::BinaryOp::LessEqual => BinaryOp::LessEqual,
+        ast::BinaryOp::Greater => BinaryOp::Greater,
+        ast::BinaryOp::GreaterEqual => BinaryOp::GreaterEqual,
+        ast::BinaryOp::And | ast::BinaryOp::Or => unreachable!("logical operators short-circuit"),
+    }
+}
+
+fn contains_assignment(expr: Expr) -> bool {
+    fn visit(node: &crate::cst::SyntaxNode) -> bool {
+        node.kind == SyntaxKind::Assignment || node.child_nodes().any(visit)
+    }
+    visit(expr.syntax())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{lower_text, BlockId};
+
+    #[test]
+    fn nested_ifs_in_example() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let module = lower_text(&text);
+        let blah = module.function("blah").unwrap();
+        assert_eq!(
+            blah.to_string(),
+            "function float blah() {\n\
+             \x20 var a: int\n\
+             \x20 var b: int\n\
+             bb0:\n\
+             \x20 a = 1\n\
+             \x20 b = 2\n\
+             \x20 %0 = call blub()\n\
+             \x20 %1 = a < %0\n\
+             \x20 branch %1, bb1, bb2\n\
+             bb1:\n\
+             \x20 %2 = call blub()\n\
+             \x20 %3 = b > %2\n\
+             \x20 branch %3, bb3, bb4\n\
+             bb2:\n\
+             \x20 return 3.14159\n\
+             bb3:\n\
+             \x20 %4 = call blub()\n\
+             \x20 %5 = call blub()\n\
+             \x20 %6 = %4 + %5\n\
+             \x20 print %6\n\
+             \x20 jump bb4\n\
+             bb4:\n\
+             \x20 jump bb2\n\
+             }\n"
+        );
+        assert_eq!(
+            blah.predecessors(),
+            vec![
+                vec![],
+                vec![BlockId(0)],
+                vec![BlockId(0), BlockId(4)],
+                vec![BlockId(1)],
+                vec![BlockId(1), BlockId(3)]
+            ]
+        );
+        assert_eq!(
+            blah.reverse_postorder(),
+            vec![BlockId(0), BlockId(1), BlockId(3), BlockId(4), BlockId(2)]
+        );
+    }
+
+    #[test]
+    fn conversions_and_short_circuits() {
+        assert_eq!(
+            lower_text("bool f() { x = 1.5; y = (x < 2) || false; return y; }").to_string(),
+            "function bool f() {\n\
+             \x20 var x: float\n\
+             \x20 var y: bool\n\
+             bb0:\n\
+             \x20 x = 1.5\n\
+             \x20 %1 = float(2)\n\
+             \x20 %2 = x < %1\n\
+             \x20 %0 = %2\n\
+             \x20 branch %0, bb2, bb1\n\
+             bb1:\n\
+             \x20 %0 = false\n\
+             \x20 jump bb2\n\
+             bb2:\n\
+             \x20 y = %0\n\
+             \x20 return y\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn assignments_in_operands() {
+        assert_eq!(
+            lower_text("int f() { a = 1; return a + (a = 2); }").to_string(),
+            "function int f() {\n\
+             \x20 var a: int\n\
+             bb0:\n\
+             \x20 a = 1\n\
+             \x20 %0 = a\n\
+             \x20 a = 2\n\
+             \x20 %1 = %0 + a\n\
+             \x20 return %1\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn code_after_return() {
+        assert_eq!(
+            lower_text("void f() { return; printf(1); }").to_string(),
+            "function void f() {\n\
+             bb0:\n\
+             \x20 return\n\
+             bb1:\n\
+             \x20 print 1\n\
+             \x20 return\n\
+             }\n"
+        );
+    }
+}
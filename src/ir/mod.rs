@@ -0,0 +1,713 @@
+//! # Overview
+//! Intermediate representation for analyses and optimizations. A type-checked program is
+//! [lowered](lower) into three-address code: every [`Instr`] applies at most one operator to
+//! [`Operand`]s, which are constants or variables, and assigns the result to a variable.
+//! Intermediate results get fresh temporary variables, written `%0`, `%1`, ..., while the local
+//! variables of the source keep their names.
+//!
+//! The instructions of a function are grouped into basic blocks. Only the [`Terminator`] at the
+//! end of a block transfers control, so the terminators span the control-flow graph of the
+//! function. Blocks are numbered in the order they were created; `bb0` is the entry. Code after a
+//! `return` ends up in blocks without predecessors.
+//!
+//! Conversions between integers and floats are explicit, so the operands of a binary operator
+//! always have the same type. `&&` and `||` are lowered to branches that assign the result to a
+//! temporary on both paths.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::ir;
+//! use cb_3::typeck::check;
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("int f() { x = 2; if (x < 3) return x * 4; return 0; }").unwrap();
+//! let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+//! let module = ir::lower(&checked);
+//!
+//! assert_eq!(
+//!     module.to_string(),
+//!     "function int f() {\n\
+//!     \x20 var x: int\n\
+//!     bb0:\n\
+//!     \x20 x = 2\n\
+//!     \x20 %0 = x < 3\n\
+//!     \x20 branch %0, bb1, bb2\n\
+//!     bb1:\n\
+//!     \x20 %1 = x * 4\n\
+//!     \x20 return %1\n\
+//!     bb2:\n\
+//!     \x20 return 0\n\
+//!     }\n"
+//! );
+//! assert_eq!(module.functions[0].successors(ir::BlockId(0)), vec![ir::BlockId(1), ir::BlockId(2)]);
+//! ```
+
+mod call_graph;
+pub mod dataflow;
+mod dead_code;
+mod dominators;
+mod fold;
+mod inline;
+mod lower;
+pub mod regalloc;
+mod ssa;
+
+pub use call_graph::CallGraph;
+pub use dead_code::{dead_code_warnings, remove_dead_code, Warning, WarningKind};
+pub use dominators::Dominators;
+pub use fold::fold_constants;
+pub use inline::{inline_calls, DEFAULT_INLINE_SIZE};
+pub(crate) use lower::constant;
+pub use lower::lower;
+pub use regalloc::{allocate_registers, verify, Allocation, RegisterFile};
+pub use ssa::to_ssa;
+
+use crate::ast::Type;
+use std::fmt::{self, Write};
+
+/// A constant value
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Constant {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Constant {
+    pub fn ty(&self) -> Type {
+        match self {
+            Constant::Int(_) => Type::Int,
+            Constant::Float(_) => Type::Float,
+            Constant::Bool(_) => Type::Bool,
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(value) => write!(f, "{}", value),
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A variable of a function, identified by its index in [`Function::vars`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct Var(pub u32);
+
+/// A basic block, identified by its index in [`Function::blocks`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct BlockId(pub u32);
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum VarKind {
+    /// A local variable of the source program
+    Local,
+    /// An intermediate result
+    Temp,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VarInfo {
+    /// The name of a local, or the number of a temporary
+    pub name: String,
+    pub ty: Type,
+    pub kind: VarKind,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Operand {
+    Var(Var),
+    Const(Constant),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum UnaryOp {
+    Neg,
+    /// Conversion between integers and floats, to the type of the destination
+    Convert,
+}
+
+/// The operators of C(-1) apart from the short-circuiting `&&` and `||`
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+        }
+    }
+
+    /// Whether the operator compares its operands and yields a boolean
+    pub fn is_comparison(&self) -> bool {
+        !matches!(
+            self,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum InstrKind {
+    Copy {
+        dest: Var,
+        src: Operand,
+    },
+    Unary {
+        dest: Var,
+        op: UnaryOp,
+        operand: Operand,
+    },
+    Binary {
+        dest: Var,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// Call of a function; the result of a `void` function has no destination
+    Call {
+        dest: Option<Var>,
+        function: String,
+    },
+    Print {
+        value: Operand,
+    },
+    /// The value of the operand for the predecessor control came from; only in [SSA form](to_ssa)
+    Phi {
+        dest: Var,
+        args: Vec<(BlockId, Operand)>,
+    },
+}
+
+/// An instruction together with the source line it was lowered from
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instr {
+    pub kind: InstrKind,
+    pub line: usize,
+}
+
+impl Instr {
+    /// The variable the instruction assigns to
+    pub fn dest(&self) -> Option<Var> {
+        match &self.kind {
+            InstrKind::Copy { dest, .. }
+            | InstrKind::Unary { dest, .. }
+            | InstrKind::Binary { dest, .. }
+            | InstrKind::Phi { dest, .. } => Some(*dest),
+            InstrKind::Call { dest, .. } => *dest,
+            InstrKind::Print { .. } => None,
+        }
+    }
+
+    /// The operands the instruction reads
+    pub fn operands(&self) -> Vec<Operand> {
+        match &self.kind {
+            InstrKind::Copy { src, .. } => vec![*src],
+            InstrKind::Unary { operand, .. } => vec![*operand],
+            InstrKind::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            InstrKind::Call { .. } => Vec::new(),
+            InstrKind::Print { value } => vec![*value],
+            InstrKind::Phi { args, .. } => args.iter().map(|(_, operand)| *operand).collect(),
+        }
+    }
+
+    pub fn dest_mut(&mut self) -> Option<&mut Var> {
+        match &mut self.kind {
+            InstrKind::Copy { dest, .. }
+            | InstrKind::Unary { dest, .. }
+            | InstrKind::Binary { dest, .. }
+            | InstrKind::Phi { dest, .. } => Some(dest),
+            InstrKind::Call { dest, .. } => dest.as_mut(),
+            InstrKind::Print { .. } => None,
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match &mut self.kind {
+            InstrKind::Copy { src, .. } => vec![src],
+            InstrKind::Unary { operand, .. } => vec![operand],
+            InstrKind::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            InstrKind::Call { .. } => Vec::new(),
+            InstrKind::Print { value } => vec![value],
+            InstrKind::Phi { args, .. } => args.iter_mut().map(|(_, operand)| operand).collect(),
+        }
+    }
+
+    /// The variables the instruction reads
+    pub fn uses(&self) -> Vec<Var> {
+        vars(self.operands())
+    }
+}
+
+fn vars(operands: Vec<Operand>) -> Vec<Var> {
+    operands
+        .into_iter()
+        .filter_map(|operand| match operand {
+            Operand::Var(var) => Some(var),
+            Operand::Const(_) => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        condition: Operand,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    Return(Option<Operand>),
+}
+
+impl Terminator {
+    /// The blocks control can continue with
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    /// The operands the terminator reads
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+
+    /// The variables the terminator reads
+    pub fn uses(&self) -> Vec<Var> {
+        vars(self.operands())
+    }
+
+    fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![then_block, else_block],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub instrs: Vec<Instr>,
+    pub terminator: Terminator,
+    /// The source line of the terminator
+    pub terminator_line: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub return_type: Type,
+    /// The locals followed by the temporaries
+    pub vars: Vec<VarInfo>,
+    /// The basic blocks; the first one is the entry
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn var(&self, var: Var) -> &VarInfo {
+        &self.vars[var.0 as usize]
+    }
+
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len() as u32).map(BlockId)
+    }
+
+    pub fn operand_type(&self, operand: Operand) -> Type {
+        match operand {
+            Operand::Var(var) => self.var(var).ty,
+            Operand::Const(constant) => constant.ty(),
+        }
+    }
+
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.block(block).terminator.successors()
+    }
+
+    /// The predecessors of every block, indexed by block number
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for block in self.block_ids() {
+            for successor in self.successors(block) {
+                if !predecessors[successor.0 as usize].contains(&block) {
+                    predecessors[successor.0 as usize].push(block);
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// The blocks that can be reached from the entry, in reverse postorder
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::new();
+        // Iterative depth-first search; the flag marks blocks whose successors are done
+        let mut stack = vec![(BlockId(0), false)];
+        while let Some((block, done)) = stack.pop() {
+            if done {
+                postorder.push(block);
+                continue;
+            }
+            if visited[block.0 as usize] {
+                continue;
+            }
+            visited[block.0 as usize] = true;
+            stack.push((block, true));
+            for successor in self.successors(block).into_iter().rev() {
+                if !visited[successor.0 as usize] {
+                    stack.push((successor, false));
+                }
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Remove the blocks that cannot be reached from the entry. The remaining blocks keep their
+    /// order but are renumbered.
+    pub fn remove_unreachable_blocks(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        for block in self.reverse_postorder() {
+            reachable[block.0 as usize] = true;
+        }
+        let mut numbers = Vec::with_capacity(self.blocks.len());
+        let mut next = 0;
+        for &reachable in &reachable {
+            numbers.push(BlockId(next));
+            next += u32::from(reachable);
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (mut block, &keep) in blocks.into_iter().zip(&reachable) {
+            if !keep {
+                continue;
+            }
+            for target in block.terminator.successors_mut() {
+                *target = numbers[target.0 as usize];
+            }
+            for instr in &mut block.instrs {
+                if let InstrKind::Phi { args, .. } = &mut instr.kind {
+                    args.retain(|(predecessor, _)| reachable[predecessor.0 as usize]);
+                    for (predecessor, _) in args {
+                        *predecessor = numbers[predecessor.0 as usize];
+                    }
+                }
+            }
+            self.blocks.push(block);
+        }
+    }
+
+    /// Append every block that is only entered by a jump from one other block to that block.
+    /// Blocks that cannot be reached are removed as well.
+    pub fn merge_blocks(&mut self) {
+        loop {
+            let predecessors = self.predecessors();
+            let merge = self
+                .block_ids()
+                .find_map(|block| match self.block(block).terminator {
+                    Terminator::Jump(target)
+                        if target != block
+                            && target != BlockId(0)
+                            && predecessors[target.0 as usize] == [block] =>
+                    {
+                        Some((block, target))
+                    }
+                    _ => None,
+                });
+            let Some((block, target)) = merge else {
+                break;
+            };
+            let mut merged = std::mem::take(&mut self.blocks[target.0 as usize].instrs);
+            // Phis with a single predecessor are copies
+            for instr in &mut merged {
+                if let InstrKind::Phi { dest, args } = &instr.kind {
+                    instr.kind = InstrKind::Copy {
+                        dest: *dest,
+                        src: args[0].1,
+                    };
+                }
+            }
+            let target_block = &self.blocks[target.0 as usize];
+            let terminator = target_block.terminator.clone();
+            let terminator_line = target_block.terminator_line;
+            // The target becomes unreachable and the block takes its place in the phis of the
+            // successors
+            self.blocks[target.0 as usize].terminator = Terminator::Return(None);
+            for successor in terminator.successors() {
+                for instr in &mut self.blocks[successor.0 as usize].instrs {
+                    if let InstrKind::Phi { args, .. } = &mut instr.kind {
+                        for (predecessor, _) in args {
+                            if *predecessor == target {
+                                *predecessor = block;
+                            }
+                        }
+                    }
+                }
+            }
+            let block = &mut self.blocks[block.0 as usize];
+            block.instrs.append(&mut merged);
+            block.terminator = terminator;
+            block.terminator_line = terminator_line;
+            self.remove_unreachable_blocks();
+        }
+    }
+
+    pub fn operand_text(&self, operand: Operand) -> String {
+        match operand {
+            Operand::Var(var) => self.var_text(var),
+            Operand::Const(constant) => constant.to_string(),
+        }
+    }
+
+    pub fn var_text(&self, var: Var) -> String {
+        let info = self.var(var);
+        match info.kind {
+            VarKind::Local => info.name.clone(),
+            VarKind::Temp => format!("%{}", info.name),
+        }
+    }
+
+    /// The text of an instruction, as used in the dump
+    pub fn instr_text(&self, instr: &Instr) -> String {
+        let operand = |operand: &Operand| self.operand_text(*operand);
+        match &instr.kind {
+            InstrKind::Copy { dest, src } => format!("{} = {}", self.var_text(*dest), operand(src)),
+            InstrKind::Unary {
+                dest,
+                op: UnaryOp::Neg,
+                operand: value,
+            } => format!("{} = -{}", self.var_text(*dest), operand(value)),
+            InstrKind::Unary {
+                dest,
+                op: UnaryOp::Convert,
+                operand: value,
+            } => format!(
+                "{} = {}({})",
+                self.var_text(*dest),
+                self.var(*dest).ty,
+                operand(value)
+            ),
+            InstrKind::Binary { dest, op, lhs, rhs } => format!(
+                "{} = {} {} {}",
+                self.var_text(*dest),
+                operand(lhs),
+                op.symbol(),
+                operand(rhs)
+            ),
+            InstrKind::Call {
+                dest: Some(dest),
+                function,
+            } => format!("{} = call {}()", self.var_text(*dest), function),
+            InstrKind::Call {
+                dest: None,
+                function,
+            } => format!("call {}()", function),
+            InstrKind::Print { value } => format!("print {}", operand(value)),
+            InstrKind::Phi { dest, args } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|(block, value)| format!("{}: {}", block, operand(value)))
+                    .collect();
+                format!("{} = phi({})", self.var_text(*dest), args.join(", "))
+            }
+        }
+    }
+
+    /// The text of a terminator, as used in the dump
+    pub fn terminator_text(&self, terminator: &Terminator) -> String {
+        match terminator {
+            Terminator::Jump(target) => format!("jump {}", target),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => format!(
+                "branch {}, {}, {}",
+                self.operand_text(*condition),
+                then_block,
+                else_block
+            ),
+            Terminator::Return(Some(value)) => format!("return {}", self.operand_text(*value)),
+            Terminator::Return(None) => "return".to_string(),
+        }
+    }
+
+    /// The control-flow graph in the DOT language of Graphviz
+    pub fn to_dot(&self) -> String {
+        let mut dot = format!("digraph \"{}\" {{\n", self.name);
+        self.write_dot_body(&mut dot, "", "  ");
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Write the nodes and edges of the graph. Node names get the given prefix so several
+    /// functions can share one graph.
+    fn write_dot_body(&self, dot: &mut String, prefix: &str, indent: &str) {
+        writeln!(dot, "{}node [shape=box, fontname=\"monospace\"];", indent).unwrap();
+        for block in self.block_ids() {
+            let mut label = format!("{}:\\l", block);
+            for instr in &self.block(block).instrs {
+                label.push_str(&dot_escape(&self.instr_text(instr)));
+                label.push_str("\\l");
+            }
+            label.push_str(&dot_escape(
+                &self.terminator_text(&self.block(block).terminator),
+            ));
+            label.push_str("\\l");
+            writeln!(dot, "{}{}{} [label=\"{}\"];", indent, prefix, block, label).unwrap();
+        }
+        for block in self.block_ids() {
+            match &self.block(block).terminator {
+                Terminator::Jump(target) => {
+                    writeln!(dot, "{0}{1}{2} -> {1}{3};", indent, prefix, block, target).unwrap()
+                }
+                Terminator::Branch {
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    writeln!(
+                        dot,
+                        "{0}{1}{2} -> {1}{3} [label=\"true\"];",
+                        indent, prefix, block, then_block
+                    )
+                    .unwrap();
+                    writeln!(
+                        dot,
+                        "{0}{1}{2} -> {1}{3} [label=\"false\"];",
+                        indent, prefix, block, else_block
+                    )
+                    .unwrap();
+                }
+                Terminator::Return(_) => {}
+            }
+        }
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function {} {}() {{", self.return_type, self.name)?;
+        for var in &self.vars {
+            if var.kind == VarKind::Local {
+                writeln!(f, "  var {}: {}", var.name, var.ty)?;
+            }
+        }
+        for block in self.block_ids() {
+            writeln!(f, "{}:", block)?;
+            for instr in &self.block(block).instrs {
+                writeln!(f, "  {}", self.instr_text(instr))?;
+            }
+            writeln!(
+                f,
+                "  {}",
+                self.terminator_text(&self.block(block).terminator)
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// The control-flow graphs of all functions as clusters of one DOT graph
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph program {\n");
+        for function in &self.functions {
+            writeln!(dot, "  subgraph \"cluster_{}\" {{", function.name).unwrap();
+            writeln!(dot, "    label=\"{}\";", function.name).unwrap();
+            function.write_dot_body(&mut dot, &format!("{}_", function.name), "    ");
+            dot.push_str("  }\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse, check and lower the text of a valid program, for the tests of the passes
+#[cfg(test)]
+pub(crate) fn lower_text(text: &str) -> Module {
+    let tree = crate::C1Parser::parse_tree(text).unwrap();
+    let checked = crate::typeck::check(crate::ast::Program::cast(&tree.root).unwrap()).unwrap();
+    lower(&checked)
+}
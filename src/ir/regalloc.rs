@@ -0,0 +1,471 @@
+//! Register allocation by linear scan
+//!
+//! The blocks of a function are laid out in reverse postorder and their instructions numbered
+//! consecutively. Every variable gets a live interval from its first to its last position where
+//! it is [live](crate::ir::dataflow::Liveness) or assigned. The intervals are scanned in the
+//! order of their start (Poletto and Sarkar, "Linear Scan Register Allocation"): a variable gets
+//! a free register of its class, and when there is none, the interval that ends last is spilled
+//! to a stack slot of its own.
+//!
+//! Integers and booleans live in the integer registers, floats in the float registers. The
+//! registers are given by a [`RegisterFile`]. [`verify`] checks an allocation independently of
+//! the intervals: no two variables that are live at the same point may share a location.
+
+use crate::ast::Type;
+use crate::ir::dataflow::{solve, Liveness};
+use crate::ir::{BlockId, Function, Operand, Var, VarKind};
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum RegisterClass {
+    Int,
+    Float,
+}
+
+impl RegisterClass {
+    pub fn of(ty: Type) -> RegisterClass {
+        match ty {
+            Type::Float => RegisterClass::Float,
+            _ => RegisterClass::Int,
+        }
+    }
+}
+
+/// The registers available to the allocator, by class
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RegisterFile {
+    pub int: Vec<String>,
+    pub float: Vec<String>,
+}
+
+impl RegisterFile {
+    pub fn new(int: &[&str], float: &[&str]) -> Self {
+        RegisterFile {
+            int: int.iter().map(|name| name.to_string()).collect(),
+            float: float.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// The callee-saved general purpose registers and the upper half of the SSE registers of
+    /// x86-64
+    pub fn x86_64() -> Self {
+        RegisterFile::new(
+            &["rbx", "r12", "r13", "r14", "r15"],
+            &[
+                "xmm8", "xmm9", "xmm10", "xmm11", "xmm12", "xmm13", "xmm14", "xmm15",
+            ],
+        )
+    }
+
+    pub fn registers(&self, class: RegisterClass) -> &[String] {
+        match class {
+            RegisterClass::Int => &self.int,
+            RegisterClass::Float => &self.float,
+        }
+    }
+}
+
+/// A register of the register file
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct Register {
+    pub class: RegisterClass,
+    pub index: usize,
+}
+
+/// Where a variable is kept
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Place {
+    Register(Register),
+    /// A stack slot, numbered from 0
+    Stack(usize),
+}
+
+/// The positions from the definition to the last use of a variable, inclusive
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Interval {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The result of register allocation for one function
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Allocation {
+    /// The place of every variable, `None` for variables that are never used
+    places: Vec<Option<Place>>,
+    intervals: Vec<Option<Interval>>,
+    stack_slots: usize,
+}
+
+impl Allocation {
+    pub fn place(&self, var: Var) -> Option<Place> {
+        self.places[var.0 as usize]
+    }
+
+    pub fn interval(&self, var: Var) -> Option<Interval> {
+        self.intervals[var.0 as usize]
+    }
+
+    /// The number of stack slots used for spilled variables
+    pub fn stack_slots(&self) -> usize {
+        self.stack_slots
+    }
+
+    /// The function with the variables replaced by their places, `[N]` being stack slot N
+    pub fn listing(&self, function: &Function, registers: &RegisterFile) -> String {
+        let mut allocated = function.clone();
+        let place_text = |place: Option<Place>| match place {
+            Some(Place::Register(register)) => {
+                registers.registers(register.class)[register.index].clone()
+            }
+            Some(Place::Stack(slot)) => format!("[{}]", slot),
+            None => "_".to_string(),
+        };
+        let mut listing = String::new();
+        writeln!(
+            listing,
+            "function {} {}() {{",
+            function.return_type, function.name
+        )
+        .unwrap();
+        for var in (0..function.vars.len() as u32).map(Var) {
+            if let Some(interval) = self.interval(var) {
+                writeln!(
+                    listing,
+                    "  {} -> {} [{}, {}]",
+                    function.var_text(var),
+                    place_text(self.place(var)),
+                    interval.start,
+                    interval.end
+                )
+                .unwrap();
+            }
+        }
+        // The places stand in for the variables in the text of the instructions
+        for (index, var) in allocated.vars.iter_mut().enumerate() {
+            var.name = place_text(self.places[index]);
+            var.kind = VarKind::Local;
+        }
+        for block in allocated.block_ids() {
+            writeln!(listing, "{}:", block).unwrap();
+            for instr in &allocated.block(block).instrs {
+                writeln!(listing, "  {}", allocated.instr_text(instr)).unwrap();
+            }
+            writeln!(
+                listing,
+                "  {}",
+                allocated.terminator_text(&allocated.block(block).terminator)
+            )
+            .unwrap();
+        }
+        listing.push_str("}\n");
+        listing
+    }
+}
+
+/// The order in which the blocks are laid out: reverse postorder, then the unreachable blocks
+fn layout(function: &Function) -> Vec<BlockId> {
+    let mut order = function.reverse_postorder();
+    for block in function.block_ids() {
+        if !order.contains(&block) {
+            order.push(block);
+        }
+    }
+    order
+}
+
+/// Compute the live interval of every variable
+pub fn live_intervals(function: &Function) -> Vec<Option<Interval>> {
+    let liveness = solve(&Liveness, function);
+    let mut intervals: Vec<Option<Interval>> = vec![None; function.vars.len()];
+    let mut extend = |var: Var, position: usize| {
+        let interval = intervals[var.0 as usize].get_or_insert(Interval {
+            start: position,
+            end: position,
+        });
+        interval.start = interval.start.min(position);
+        interval.end = interval.end.max(position);
+    };
+    let mut position = 0;
+    for block in layout(function) {
+        let index = block.0 as usize;
+        let first = position;
+        for &var in &liveness.block_entry[index] {
+            extend(var, first);
+        }
+        for instr in &function.block(block).instrs {
+            for var in instr.uses() {
+                extend(var, position);
+            }
+            if let Some(dest) = instr.dest() {
+                extend(dest, position);
+            }
+            position += 1;
+        }
+        for var in function.block(block).terminator.uses() {
+            extend(var, position);
+        }
+        for &var in &liveness.block_exit[index] {
+            extend(var, position);
+        }
+        position += 1;
+    }
+    intervals
+}
+
+/// Allocate registers for the variables of the function
+pub fn allocate_registers(function: &Function, registers: &RegisterFile) -> Allocation {
+    let intervals = live_intervals(function);
+    let mut places = vec![None; function.vars.len()];
+    let mut stack_slots = 0;
+    for class in [RegisterClass::Int, RegisterClass::Float] {
+        let mut order: Vec<Var> = (0..function.vars.len() as u32)
+            .map(Var)
+            .filter(|&var| {
+                intervals[var.0 as usize].is_some()
+                    && RegisterClass::of(function.var(var).ty) == class
+            })
+            .collect();
+        let interval = |var: Var| intervals[var.0 as usize].expect("only live variables");
+        order.sort_by_key(|&var| interval(var).start);
+
+        let mut free: Vec<usize> = (0..registers.registers(class).len()).rev().collect();
+        // The variables in registers, ordered by the end of their interval
+        let mut active: Vec<Var> = Vec::new();
+        for var in order {
+            let current = interval(var);
+            active.retain(|&other| {
+                if interval(other).end < current.start {
+                    if let Some(Place::Register(register)) = places[other.0 as usize] {
+                        free.push(register.index);
+                    }
+                    false
+                } else {
+                    true
+                }
+            });
+            let register = match free.pop() {
+                Some(index) => Register { class, index },
+                None => {
+                    // Spill the variable that is needed for the longest time
+                    match active.last() {
+                        Some(&last) if interval(last).end > current.end => {
+                            let Some(Place::Register(register)) = places[last.0 as usize] else {
+                                unreachable!("active variables are in registers");
+                            };
+                            places[last.0 as usize] = Some(Place::Stack(stack_slots));
+                            stack_slots += 1;
+                            active.pop();
+                            register
+                        }
+                        _ => {
+                            places[var.0 as usize] = Some(Place::Stack(stack_slots));
+                            stack_slots += 1;
+                            continue;
+                        }
+                    }
+                }
+            };
+            places[var.0 as usize] = Some(Place::Register(register));
+            let position = active
+                .iter()
+                .position(|&other| interval(other).end > current.end)
+                .unwrap_or(active.len());
+            active.insert(position, var);
+        }
+    }
+    Allocation {
+        places,
+        intervals,
+        stack_slots,
+    }
+}
+
+/// An allocation that is not valid for the function
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AllocationError {
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}", self.message, self.line)
+    }
+}
+
+/// Check that every used variable has a place that fits its class and that variables that are
+/// live at the same time, or assigned while another one is live, have different places
+pub fn verify(
+    function: &Function,
+    allocation: &Allocation,
+    registers: &RegisterFile,
+) -> Result<(), AllocationError> {
+    let liveness = solve(&Liveness, function);
+    let place = |var: Var, line: usize| -> Result<Place, AllocationError> {
+        let error = |message: String| AllocationError { message, line };
+        let name = function.var_text(var);
+        match allocation.place(var) {
+            None => Err(error(format!("'{}' has no place", name))),
+            Some(Place::Register(register)) => {
+                let class = RegisterClass::of(function.var(var).ty);
+                if register.class != class {
+                    Err(error(format!(
+                        "'{}' is in a register of the wrong class",
+                        name
+                    )))
+                } else if register.index >= registers.registers(class).len() {
+                    Err(error(format!(
+                        "'{}' is in a register that does not exist",
+                        name
+                    )))
+                } else {
+                    Ok(Place::Register(register))
+                }
+            }
+            Some(Place::Stack(slot)) if slot >= allocation.stack_slots() => Err(error(format!(
+                "'{}' is in a stack slot that does not exist",
+                name
+            ))),
+            Some(place) => Ok(place),
+        }
+    };
+    let distinct = |vars: &BTreeSet<Var>, line: usize| -> Result<(), AllocationError> {
+        let mut used: Vec<(Place, Var)> = Vec::new();
+        for &var in vars {
+            let place = place(var, line)?;
+            if let Some((_, other)) = used.iter().find(|(other, _)| *other == place) {
+                return Err(AllocationError {
+                    message: format!(
+                        "'{}' and '{}' are live at the same time in the same place",
+                        function.var_text(*other),
+                        function.var_text(var)
+                    ),
+                    line,
+                });
+            }
+            used.push((place, var));
+        }
+        Ok(())
+    };
+    for block in function.block_ids() {
+        let facts = liveness.block_facts(&Liveness, function, block);
+        let instrs = &function.block(block).instrs;
+        for (index, instr) in instrs.iter().enumerate() {
+            distinct(&facts[index], instr.line)?;
+            let mut written = facts[index + 1].clone();
+            if let Some(dest) = instr.dest() {
+                written.insert(dest);
+            }
+            distinct(&written, instr.line)?;
+        }
+        let line = function.block(block).terminator_line;
+        distinct(&facts[instrs.len()], line)?;
+        for operand in function.block(block).terminator.operands() {
+            if let Operand::Var(var) = operand {
+                place(var, line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::regalloc::{Place, Register, RegisterClass};
+    use crate::ir::{allocate_registers, lower_text, verify, RegisterFile, Var};
+
+    #[test]
+    fn enough_registers() {
+        let module =
+            lower_text("int f() { a = 1; b = a + 2; c = 1.5; if (b > a) return b; return a; }");
+        let function = &module.functions[0];
+        let registers = RegisterFile::new(&["r0", "r1", "r2"], &["f0"]);
+        let allocation = allocate_registers(function, &registers);
+        assert_eq!(allocation.stack_slots(), 0);
+        assert_eq!(
+            allocation.listing(function, &registers),
+            "function int f() {\n\
+             \x20 a -> r0 [0, 6]\n\
+             \x20 b -> r2 [2, 7]\n\
+             \x20 c -> f0 [3, 3]\n\
+             \x20 %0 -> r1 [1, 2]\n\
+             \x20 %1 -> r1 [4, 5]\n\
+             bb0:\n\
+             \x20 r0 = 1\n\
+             \x20 r1 = r0 + 2\n\
+             \x20 r2 = r1\n\
+             \x20 f0 = 1.5\n\
+             \x20 r1 = r2 > r0\n\
+             \x20 branch r1, bb1, bb2\n\
+             bb1:\n\
+             \x20 return r2\n\
+             bb2:\n\
+             \x20 return r0\n\
+             }\n"
+        );
+        verify(function, &allocation, &registers).unwrap();
+    }
+
+    #[test]
+    fn spilling() {
+        let module = lower_text(
+            "int f() { a = 1; b = 2; c = 3; d = 4; x = 1.0; y = 2.0; return a + b + c + d; }",
+        );
+        let function = &module.functions[0];
+        let registers = RegisterFile::new(&["r0", "r1"], &["f0"]);
+        let allocation = allocate_registers(function, &registers);
+        verify(function, &allocation, &registers).unwrap();
+        assert!(allocation.stack_slots() >= 2);
+        // `d` is needed last and goes to the stack
+        assert!(matches!(allocation.place(Var(3)), Some(Place::Stack(_))));
+        assert!(matches!(
+            allocation.place(Var(4)),
+            Some(Place::Register(Register {
+                class: RegisterClass::Float,
+                ..
+            }))
+        ));
+
+        let none = RegisterFile::new(&[], &[]);
+        let allocation = allocate_registers(function, &none);
+        verify(function, &allocation, &none).unwrap();
+    }
+
+    #[test]
+    fn verifier_finds_conflicts() {
+        let module = lower_text("int f() { a = 1; b = 2; return a + b; }");
+        let function = &module.functions[0];
+        let registers = RegisterFile::new(&["r0", "r1", "r2"], &[]);
+        let mut allocation = allocate_registers(function, &registers);
+        allocation.places[1] = allocation.places[0];
+        assert_eq!(
+            verify(function, &allocation, &registers)
+                .unwrap_err()
+                .to_string(),
+            "'a' and 'b' are live at the same time in the same place at line 1"
+        );
+        allocation.places[1] = Some(Place::Register(Register {
+            class: RegisterClass::Float,
+            index: 0,
+        }));
+        assert_eq!(
+            verify(function, &allocation, &registers)
+                .unwrap_err()
+                .to_string(),
+            "'b' is in a register of the wrong class at line 1"
+        );
+    }
+
+    #[test]
+    fn example_is_allocated() {
+        let text = std::fs::read_to_string("tests/data/features.c-1").unwrap();
+        let module = lower_text(&text);
+        for registers in [RegisterFile::x86_64(), RegisterFile::new(&["r0"], &["f0"])] {
+            for function in &module.functions {
+                let allocation = allocate_registers(function, &registers);
+                verify(function, &allocation, &registers).unwrap();
+            }
+        }
+    }
+}
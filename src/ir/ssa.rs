@@ -0,0 +1,248 @@
+//! Conversion into static single assignment form
+//!
+//! In SSA form, every variable is assigned exactly once. Each assignment of a variable of the
+//! original function gets a new version, written with the version number after a dot: `x.1`,
+//! `x.2`, `%3.1`. Where the definitions of different paths meet, a phi instruction at the start
+//! of the block selects the version of the predecessor control came from.
+//!
+//! The construction follows Cytron et al.: phis are inserted in the iterated dominance frontier of
+//! the blocks that assign a variable, and the variables are renamed in a walk over the dominator
+//! tree. Phis are only inserted where the variable is live (pruned SSA). A read that no
+//! assignment reaches uses version 0, the undefined value on entry, which has no definition.
+//! Blocks that cannot be reached from the entry are removed first.
+
+use crate::ir::dataflow::{solve, Liveness};
+use crate::ir::{BlockId, Dominators, Function, Instr, InstrKind, Operand, Var, VarInfo};
+
+/// Convert the function into SSA form
+pub fn to_ssa(function: &Function) -> Function {
+    let mut function = function.clone();
+    function.remove_unreachable_blocks();
+    let dominators = Dominators::compute(&function);
+    let frontiers = dominators.frontiers(&function);
+    let liveness = solve(&Liveness, &function);
+    let predecessors = function.predecessors();
+
+    // The variables that get a phi in every block, in the order of the variables
+    let mut phis: Vec<Vec<Var>> = vec![Vec::new(); function.blocks.len()];
+    for var in 0..function.vars.len() as u32 {
+        let var = Var(var);
+        let mut worklist: Vec<BlockId> = function
+            .block_ids()
+            .filter(|&block| {
+                function
+                    .block(block)
+                    .instrs
+                    .iter()
+                    .any(|instr| instr.dest() == Some(var))
+            })
+            .collect();
+        while let Some(block) = worklist.pop() {
+            for &frontier in &frontiers[block.0 as usize] {
+                let index = frontier.0 as usize;
+                if phis[index].contains(&var) || !liveness.block_entry[index].contains(&var) {
+                    continue;
+                }
+                phis[index].push(var);
+                worklist.push(frontier);
+            }
+        }
+    }
+    for (index, vars) in phis.iter_mut().enumerate() {
+        vars.sort();
+        let line = function.blocks[index]
+            .instrs
+            .first()
+            .map_or(function.blocks[index].terminator_line, |instr| instr.line);
+        let instrs = vars.iter().map(|&var| Instr {
+            kind: InstrKind::Phi {
+                dest: var,
+                args: predecessors[index]
+                    .iter()
+                    .map(|&predecessor| (predecessor, Operand::Var(var)))
+                    .collect(),
+            },
+            line,
+        });
+        function.blocks[index].instrs.splice(0..0, instrs);
+    }
+
+    let count = function.vars.len();
+    let mut renamer = Renamer {
+        original: std::mem::take(&mut function.vars),
+        versions: vec![0; count],
+        stacks: vec![Vec::new(); count],
+        undefined: vec![None; count],
+        vars: Vec::new(),
+    };
+    renamer.rename(&mut function, &dominators, &phis, BlockId(0));
+    function.vars = renamer.vars;
+    function
+}
+
+struct Renamer {
+    /// The variables before the conversion
+    original: Vec<VarInfo>,
+    /// The last version of every original variable
+    versions: Vec<u32>,
+    /// The current version of every original variable on the way down the dominator tree
+    stacks: Vec<Vec<Var>>,
+    /// Version 0 of the original variables, once it is needed
+    undefined: Vec<Option<Var>>,
+    /// The variables in SSA form
+    vars: Vec<VarInfo>,
+}
+
+impl Renamer {
+    fn new_var(&mut self, original: Var, version: u32) -> Var {
+        let info = &self.original[original.0 as usize];
+        self.vars.push(VarInfo {
+            name: format!("{}.{}", info.name, version),
+            ty: info.ty,
+            kind: info.kind,
+        });
+        Var(self.vars.len() as u32 - 1)
+    }
+
+    fn define(&mut self, original: Var) -> Var {
+        self.versions[original.0 as usize] += 1;
+        let var = self.new_var(original, self.versions[original.0 as usize]);
+        self.stacks[original.0 as usize].push(var);
+        var
+    }
+
+    fn current(&mut self, original: Var) -> Var {
+        if let Some(&var) = self.stacks[original.0 as usize].last() {
+            return var;
+        }
+        match self.undefined[original.0 as usize] {
+            Some(var) => var,
+            None => {
+                let var = self.new_var(original, 0);
+                self.undefined[original.0 as usize] = Some(var);
+                var
+            }
+        }
+    }
+
+    fn rename_use(&mut self, operand: &mut Operand) {
+        if let Operand::Var(var) = operand {
+            *var = self.current(*var);
+        }
+    }
+
+    fn rename(
+        &mut self,
+        function: &mut Function,
+        dominators: &Dominators,
+        phis: &[Vec<Var>],
+        block: BlockId,
+    ) {
+        let mut defined = Vec::new();
+        let mut instrs = std::mem::take(&mut function.blocks[block.0 as usize].instrs);
+        for instr in &mut instrs {
+            if !matches!(instr.kind, InstrKind::Phi { .. }) {
+                for operand in instr.operands_mut() {
+                    self.rename_use(operand);
+                }
+            }
+            if let Some(dest) = instr.dest_mut() {
+                defined.push(*dest);
+                *dest = self.define(*dest);
+            }
+        }
+        function.blocks[block.0 as usize].instrs = instrs;
+        let mut terminator = function.block(block).terminator.clone();
+        for operand in terminator.operands_mut() {
+            self.rename_use(operand);
+        }
+        function.blocks[block.0 as usize].terminator = terminator;
+
+        for successor in function.successors(block) {
+            let index = successor.0 as usize;
+            for (position, &original) in phis[index].iter().enumerate() {
+                let current = self.current(original);
+                if let InstrKind::Phi { args, .. } =
+                    &mut function.blocks[index].instrs[position].kind
+                {
+                    for (predecessor, operand) in args {
+                        if *predecessor == block {
+                            *operand = Operand::Var(current);
+                        }
+                    }
+                }
+            }
+        }
+
+        for child in dominators.children(block) {
+            self.rename(function, dominators, phis, child);
+        }
+        for original in defined {
+            self.stacks[original.0 as usize].pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{lower_text, to_ssa, InstrKind};
+
+    fn ssa(text: &str) -> String {
+        to_ssa(&lower_text(text).functions[0]).to_string()
+    }
+
+    #[test]
+    fn phi_at_join() {
+        assert_eq!(
+            ssa("int f() { a = 1; if (a > 0) a = 2; return a; }"),
+            "function int f() {\n\
+             \x20 var a.1: int\n\
+             \x20 var a.2: int\n\
+             \x20 var a.3: int\n\
+             bb0:\n\
+             \x20 a.1 = 1\n\
+             \x20 %0.1 = a.1 > 0\n\
+             \x20 branch %0.1, bb1, bb2\n\
+             bb1:\n\
+             \x20 a.2 = 2\n\
+             \x20 jump bb2\n\
+             bb2:\n\
+             \x20 a.3 = phi(bb0: a.1, bb1: a.2)\n\
+             \x20 return a.3\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn dead_values_get_no_phi() {
+        // `b` is not read after the `if`, and `c` may be read before it is assigned
+        let output = ssa("int f() { a = 1; if (a > 0) { b = 2; c = b; } return c; }");
+        assert!(
+            output.contains("c.2 = phi(bb0: c.0, bb1: c.1)"),
+            "{}",
+            output
+        );
+        assert!(!output.contains("b.2"), "{}", output);
+    }
+
+    #[test]
+    fn every_variable_is_assigned_once() {
+        let text = std::fs::read_to_string("tests/data/features.c-1").unwrap();
+        for function in &lower_text(&text).functions {
+            let function = to_ssa(function);
+            let predecessors = function.predecessors();
+            let mut assigned = vec![0; function.vars.len()];
+            for block in function.block_ids() {
+                for instr in &function.block(block).instrs {
+                    if let Some(dest) = instr.dest() {
+                        assigned[dest.0 as usize] += 1;
+                    }
+                    if let InstrKind::Phi { args, .. } = &instr.kind {
+                        assert_eq!(args.len(), predecessors[block.0 as usize].len());
+                    }
+                }
+            }
+            assert!(assigned.iter().all(|&count| count <= 1), "{}", function);
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use logos::{Lexer, Logos};
+use crate::{FileId, LineIndex, SourceMap, TokenSource};
+use logos::{FilterResult, Lexer, Logos};
+use std::collections::VecDeque;
+use std::num::IntErrorKind;
+use std::ops::Range;
 
 #[derive(Logos, Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[logos(extras = Identifiers)]
 pub enum C1Token {
     #[token("bool")]
     KwBoolean,
@@ -105,10 +111,15 @@ pub enum C1Token {
     /// }
     RightBrace,
 
-    #[regex("[0-9]+")]
+    /// Decimal, or hexadecimal, octal and binary with the prefixes `0x`, `0o` and `0b`. Digits
+    /// may be separated by `_`.
+    #[regex("[0-9][0-9_]*", valid_int)]
+    #[regex("0[xXoObB][0-9a-zA-Z_]*", valid_int)]
     ConstInt,
 
-    #[regex(r"(\d+\.\d+)|(\.\d+([eE]([-+])?\d+)?)|(\d+[eE]([-+])?\d+)")]
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*([eE][-+]?[0-9_]*)?", valid_float)]
+    #[regex(r"\.[0-9][0-9_]*([eE][-+]?[0-9_]*)?", valid_float)]
+    #[regex(r"[0-9][0-9_]*[eE][-+]?[0-9_]*", valid_float)]
     ConstFloat,
 
     #[regex("true|false")]
@@ -117,10 +128,10 @@ pub enum C1Token {
     #[regex("\"[^\n\"]*\"")]
     ConstString,
 
-    #[regex("[a-zA-Z]+[0-9a-zA-Z]*")]
+    #[regex(r"[\p{XID_Start}_]\p{XID_Continue}*", identifier)]
     Identifier,
 
-    #[regex(r"/\*[^\*/]*\*/", logos::skip)]
+    #[token("/*", block_comment)]
     CComment,
 
     #[regex("//[^\n]*(\n)?", logos::skip)]
@@ -140,10 +151,172 @@ pub enum C1Token {
     Error,
 }
 
+/// Skip a block comment, which ends at the first `*/`. A comment without end is a lexical error
+/// that spans the rest of the text.
+fn block_comment(lexer: &mut Lexer<C1Token>) -> FilterResult<()> {
+    let rest = lexer.remainder();
+    match rest.find("*/") {
+        Some(end) => {
+            lexer.bump(end + 2);
+            FilterResult::Skip
+        }
+        None => {
+            lexer.bump(rest.len());
+            FilterResult::Error
+        }
+    }
+}
+
+/// The value of a literal token
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TokenValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl TokenValue {
+    /// Decode the text of a literal token. Returns `None` for other tokens and for malformed
+    /// literals, which the lexer turns into [`C1Token::Error`].
+    /// ```
+    /// use cb_3::{C1Token, TokenValue};
+    ///
+    /// assert_eq!(TokenValue::decode(C1Token::ConstInt, "0x1F"), Some(TokenValue::Int(31)));
+    /// assert_eq!(TokenValue::decode(C1Token::ConstInt, "1_000"), Some(TokenValue::Int(1000)));
+    /// assert_eq!(TokenValue::decode(C1Token::ConstFloat, ".5e1"), Some(TokenValue::Float(5.0)));
+    /// assert_eq!(TokenValue::decode(C1Token::ConstFloat, "1.5e"), None);
+    /// ```
+    pub fn decode(kind: C1Token, text: &str) -> Option<TokenValue> {
+        match kind {
+            C1Token::ConstInt => decode_int(text).ok(),
+            C1Token::ConstFloat => decode_float(text).ok(),
+            C1Token::ConstBoolean => Some(TokenValue::Bool(text == "true")),
+            _ => None,
+        }
+    }
+}
+
+fn valid_int(lexer: &mut Lexer<C1Token>) -> bool {
+    decode_int(lexer.slice()).is_ok()
+}
+
+fn valid_float(lexer: &mut Lexer<C1Token>) -> bool {
+    decode_float(lexer.slice()).is_ok()
+}
+
+/// Whether the text of a number is an integer, given that the lexer matched it as a number
+fn is_int(text: &str) -> bool {
+    let prefixed = text.starts_with('0') && text[1..].starts_with(['x', 'X', 'o', 'O', 'b', 'B']);
+    prefixed || !text.contains(['.', 'e', 'E'])
+}
+
+/// Remove the `_` separators from the digits, which are only allowed between two digits of the
+/// radix
+fn remove_separators(digits: &str, radix: u32) -> Result<String, String> {
+    let chars: Vec<char> = digits.chars().collect();
+    let is_digit = |index: Option<usize>| {
+        index
+            .and_then(|index| chars.get(index))
+            .is_some_and(|c| c.is_digit(radix))
+    };
+    for (index, &c) in chars.iter().enumerate() {
+        if c == '_' && !(is_digit(index.checked_sub(1)) && is_digit(Some(index + 1))) {
+            return Err("'_' is only allowed between digits".to_string());
+        }
+    }
+    Ok(chars.into_iter().filter(|&c| c != '_').collect())
+}
+
+fn decode_int(text: &str) -> Result<TokenValue, String> {
+    let (radix, digits) = match text.get(..2) {
+        Some("0x" | "0X") => (16, &text[2..]),
+        Some("0o" | "0O") => (8, &text[2..]),
+        Some("0b" | "0B") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    if digits.is_empty() {
+        return Err("the integer literal has no digits".to_string());
+    }
+    let digits = remove_separators(digits, radix)?;
+    i64::from_str_radix(&digits, radix)
+        .map(TokenValue::Int)
+        .map_err(|error| match error.kind() {
+            IntErrorKind::PosOverflow => {
+                "the integer literal does not fit into 64 bits".to_string()
+            }
+            _ => format!("invalid digit for base {}", radix),
+        })
+}
+
+fn decode_float(text: &str) -> Result<TokenValue, String> {
+    let digits = remove_separators(text, 10)?;
+    if let Some(exponent) = digits.find(['e', 'E']).map(|index| &digits[index + 1..]) {
+        let exponent = exponent.trim_start_matches(['+', '-']);
+        if exponent.is_empty() {
+            return Err("the exponent has no digits".to_string());
+        }
+    }
+    match digits.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(TokenValue::Float(value)),
+        Ok(_) => Err("the float literal is out of range".to_string()),
+        Err(_) => Err("malformed float literal".to_string()),
+    }
+}
+
+/// Which characters identifiers may consist of. Keywords are only recognized if they are not
+/// the start of a longer identifier, so `integer` and `doit` are identifiers in both modes.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum Identifiers {
+    /// Like in C: a letter or `_`, followed by letters, digits and `_`, all ASCII
+    #[default]
+    Ascii,
+    /// Following UAX #31: a character with the property XID_Start or `_`, followed by
+    /// characters with the property XID_Continue
+    Unicode,
+}
+
+/// Accept an identifier outside of ASCII only in Unicode mode. A rejected identifier becomes a
+/// single error token.
+fn identifier(lexer: &mut Lexer<C1Token>) -> bool {
+    lexer.extras == Identifiers::Unicode || lexer.slice().is_ascii()
+}
+
+/// Describe what is wrong with the text of a [`C1Token::Error`] token
+pub(crate) fn lexical_error(text: &str) -> String {
+    let first = text.chars().next().unwrap_or_default();
+    if text.starts_with("/*") {
+        "unterminated comment".to_string()
+    } else if first.is_ascii_digit() || first == '.' && text.len() > 1 {
+        let error = if is_int(text) {
+            decode_int(text).err()
+        } else {
+            decode_float(text).err()
+        };
+        format!(
+            "{} in '{}'",
+            error.unwrap_or_else(|| "malformed number".to_string()),
+            text
+        )
+    } else if !text.is_ascii() && (first == '_' || first.is_alphabetic()) {
+        format!(
+            "identifier '{}' contains characters outside of ASCII, which are only allowed \
+             with Unicode identifiers",
+            text
+        )
+    } else {
+        format!(
+            "unexpected character '{}'",
+            text.chars().next().unwrap_or_default()
+        )
+    }
+}
+
 /// # Overview
 /// Extended lexer based on the logos crate. The lexer keeps track of the current token and the next token
-/// in the lexed text. Furthermore, the lexer keeps track of the line number in which each token is
-/// located, and of the text associated with each token.
+/// in the lexed text. Furthermore, the lexer keeps track of the line and column in which each token
+/// is located, computed from its byte offset with a [`LineIndex`], and of the text associated with
+/// each token.
 ///
 /// # Examples
 /// ```
@@ -176,22 +349,128 @@ pub enum C1Token {
 /// ```
 pub struct C1Lexer<'a> {
     logos_lexer: Lexer<'a, C1Token>,
-    logos_line_number: usize,
-    current_token: Option<TokenData<'a>>,
-    peek_token: Option<TokenData<'a>>,
+    line_index: LineIndex<'a>,
+    /// The file of a source map that is lexed, and its global offset that spans start from
+    file: Option<FileId>,
+    start: usize,
+    keep_trivia: bool,
+    trivia_start: usize,
+    eof_trivia: Vec<Trivia<'a>>,
+    /// Ring buffer of the lexed tokens from the oldest mark or the current token on
+    tokens: VecDeque<TokenData<'a>>,
+    /// The index in the token stream of the first buffered token and of the current token
+    first: usize,
+    cursor: usize,
+    /// The positions of the marks that were neither reset nor released, oldest first
+    marks: Vec<usize>,
+}
+
+/// A position in the token stream to return to with [`C1Lexer::reset`]. Marks are nested: resetting
+/// to or releasing a mark also drops all marks that were set after it.
+#[must_use = "a mark keeps the lexer from dropping tokens until it is reset or released"]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mark {
+    position: usize,
+    depth: usize,
 }
 
 impl<'a> C1Lexer<'a> {
     /// Initialize a new C1Lexer for the given string slice
-    pub fn new(text: &'a str) -> C1Lexer {
+    pub fn new(text: &'a str) -> C1Lexer<'a> {
+        Self::with_options(text, false, Identifiers::Ascii)
+    }
+
+    /// Initialize a new C1Lexer in trivia-preserving mode. Whitespace, line breaks and comments are
+    /// no longer thrown away but attached as leading trivia to the token that follows them. Trivia
+    /// after the last token is returned by [`current_leading_trivia`](C1Lexer::current_leading_trivia)
+    /// once the end of the text has been reached.
+    /// ```
+    /// use cb_3::{C1Lexer, TriviaKind};
+    /// let mut lexer = C1Lexer::with_trivia("// main\nvoid main");
+    ///
+    /// let trivia = lexer.current_leading_trivia();
+    /// assert_eq!(trivia.len(), 1);
+    /// assert_eq!(trivia[0].kind, TriviaKind::LineComment);
+    /// assert_eq!(trivia[0].text, "// main\n");
+    ///
+    /// lexer.eat();
+    /// assert_eq!(lexer.current_leading_trivia()[0].text, " ");
+    /// ```
+    pub fn with_trivia(text: &'a str) -> C1Lexer<'a> {
+        Self::with_options(text, true, Identifiers::Ascii)
+    }
+
+    /// Initialize a new C1Lexer that accepts the given identifiers, optionally preserving trivia.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token, Identifiers};
+    ///
+    /// let lexer = C1Lexer::with_options("größe = 1;", false, Identifiers::Unicode);
+    /// assert_eq!(lexer.current_token(), Some(C1Token::Identifier));
+    /// assert_eq!(lexer.current_text(), Some("größe"));
+    ///
+    /// let lexer = C1Lexer::new("größe = 1;");
+    /// assert_eq!(lexer.current_token(), Some(C1Token::Error));
+    /// ```
+    pub fn with_options(text: &'a str, keep_trivia: bool, identifiers: Identifiers) -> C1Lexer<'a> {
+        Self::build(text, keep_trivia, identifiers, None, 0)
+    }
+
+    /// Initialize a new C1Lexer for a file of the source map that preserves trivia. The spans of
+    /// the tokens are global offsets of the source map; lines and columns are those in the file.
+    /// ```
+    /// use cb_3::{C1Lexer, SourceMap};
+    ///
+    /// let mut sources = SourceMap::new();
+    /// sources.add("main.c-1", "void main() {}\n");
+    /// let lib = sources.add("lib.c-1", "int one() {\n  return 1;\n}\n");
+    ///
+    /// let mut lexer = C1Lexer::for_file(&sources, lib);
+    /// assert_eq!(lexer.current_file(), Some(lib));
+    /// for _ in 0..5 {
+    ///     lexer.eat();
+    /// }
+    /// assert_eq!(lexer.current_line_number(), Some(2));
+    /// let start = lexer.current_span().unwrap().start;
+    /// assert_eq!(sources.resolve(start).unwrap().file, lib);
+    /// ```
+    pub fn for_file(sources: &'a SourceMap, file: FileId) -> C1Lexer<'a> {
+        let source = sources.file(file);
+        Self::build(
+            source.text(),
+            true,
+            Identifiers::Ascii,
+            Some(file),
+            source.start(),
+        )
+    }
+
+    /// The file of the source map that is lexed, if the lexer was created with
+    /// [`for_file`](C1Lexer::for_file)
+    pub fn current_file(&self) -> Option<FileId> {
+        self.file
+    }
+
+    fn build(
+        text: &'a str,
+        keep_trivia: bool,
+        identifiers: Identifiers,
+        file: Option<FileId>,
+        start: usize,
+    ) -> C1Lexer<'a> {
         let mut lexer = C1Lexer {
-            logos_lexer: C1Token::lexer(text),
-            logos_line_number: 1,
-            current_token: None,
-            peek_token: None,
+            logos_lexer: C1Token::lexer_with_extras(text, identifiers),
+            line_index: LineIndex::new(text),
+            file,
+            start,
+            keep_trivia,
+            trivia_start: 0,
+            eof_trivia: Vec::new(),
+            tokens: VecDeque::new(),
+            first: 0,
+            cursor: 0,
+            marks: Vec::new(),
         };
-        lexer.current_token = lexer.next_token();
-        lexer.peek_token = lexer.next_token();
+        lexer.fill(1);
         lexer
     }
 
@@ -207,7 +486,7 @@ impl<'a> C1Lexer<'a> {
     /// assert_eq!(lexer.current_text(), Some("current"));
     /// ```
     pub fn current_token(&self) -> Option<C1Token> {
-        self.current_token.token_type()
+        self.current().token_type()
     }
 
     /// Return the C1Token variant of the next token without consuming it.
@@ -222,27 +501,75 @@ impl<'a> C1Lexer<'a> {
     /// assert_eq!(lexer.peek_text(), Some("next"));
     /// ```
     pub fn peek_token(&self) -> Option<C1Token> {
-        self.peek_token.token_type()
+        self.peek().token_type()
     }
 
     /// Return the text of the current token
-    pub fn current_text(&self) -> Option<&str> {
-        self.current_token.text()
+    pub fn current_text(&self) -> Option<&'a str> {
+        self.current().text()
     }
 
     /// Return the text of the next token
-    pub fn peek_text(&self) -> Option<&str> {
-        self.peek_token.text()
+    pub fn peek_text(&self) -> Option<&'a str> {
+        self.peek().text()
+    }
+
+    /// Return the value of the current token if it is a literal
+    /// ```
+    /// use cb_3::{C1Lexer, TokenValue};
+    /// let lexer = C1Lexer::new("0b1010 2.5e-1");
+    ///
+    /// assert_eq!(lexer.current_value(), Some(TokenValue::Int(10)));
+    /// assert_eq!(lexer.peek_value(), Some(TokenValue::Float(0.25)));
+    /// ```
+    pub fn current_value(&self) -> Option<TokenValue> {
+        self.current().value()
+    }
+
+    /// Return the value of the next token if it is a literal
+    pub fn peek_value(&self) -> Option<TokenValue> {
+        self.peek().value()
     }
 
     /// Return the line number where the current token is located
     pub fn current_line_number(&self) -> Option<usize> {
-        self.current_token.line_number()
+        self.current().line_number()
     }
 
     /// Return the line number where the next token is located
     pub fn peek_line_number(&self) -> Option<usize> {
-        self.peek_token.line_number()
+        self.peek().line_number()
+    }
+
+    /// Return the column where the current token starts, counted in characters from 1
+    pub fn current_column(&self) -> Option<usize> {
+        self.current().column()
+    }
+
+    /// Return the column where the next token starts, counted in characters from 1
+    pub fn peek_column(&self) -> Option<usize> {
+        self.peek().column()
+    }
+
+    /// Return the byte range of the current token in the lexed text, or in the source map if the
+    /// lexer was created with [`for_file`](C1Lexer::for_file)
+    pub fn current_span(&self) -> Option<Range<usize>> {
+        self.current().span()
+    }
+
+    /// Return the byte range of the next token in the lexed text
+    pub fn peek_span(&self) -> Option<Range<usize>> {
+        self.peek().span()
+    }
+
+    /// Return the trivia in front of the current token. At the end of the text, this is the trivia
+    /// following the last token. Always empty unless the lexer was created with
+    /// [`with_trivia`](C1Lexer::with_trivia).
+    pub fn current_leading_trivia(&self) -> &[Trivia<'a>] {
+        match self.current() {
+            Some(data) => &data.leading_trivia,
+            None => &self.eof_trivia,
+        }
     }
 
     /// Drop the current token and retrieve the next token in the text.
@@ -266,31 +593,242 @@ impl<'a> C1Lexer<'a> {
     /// assert_eq!(lexer.peek_text(), None);
     /// ```
     pub fn eat(&mut self) {
-        self.current_token = self.peek_token.take();
-        self.peek_token = self.next_token();
+        self.cursor += 1;
+        self.drop_consumed();
+        self.fill(1);
+    }
+
+    /// Return the C1Token variant of the token `n` positions ahead without consuming anything.
+    /// `peek_nth(0)` is the current token and `peek_nth(1)` the next one.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let mut lexer = C1Lexer::new("int x;");
+    ///
+    /// assert_eq!(lexer.peek_nth(2), Some(C1Token::Semicolon));
+    /// assert_eq!(lexer.peek_nth_text(1), Some("x"));
+    /// assert_eq!(lexer.peek_nth(3), None);
+    /// assert_eq!(lexer.current_text(), Some("int"));
+    /// ```
+    pub fn peek_nth(&mut self, n: usize) -> Option<C1Token> {
+        self.fill(n);
+        self.token(n).map(|data| data.token_type)
+    }
+
+    /// Return the text of the token `n` positions ahead without consuming anything
+    pub fn peek_nth_text(&mut self, n: usize) -> Option<&'a str> {
+        self.fill(n);
+        self.token(n).map(|data| data.token_text)
+    }
+
+    /// Remember the current position to return to it with [`reset`](C1Lexer::reset). Until the mark
+    /// is reset or [released](C1Lexer::release), the tokens from the mark on stay buffered.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let mut lexer = C1Lexer::new("a = b;");
+    ///
+    /// let mark = lexer.mark();
+    /// lexer.eat();
+    /// lexer.eat();
+    /// assert_eq!(lexer.current_text(), Some("b"));
+    ///
+    /// lexer.reset(mark);
+    /// assert_eq!(lexer.current_text(), Some("a"));
+    /// assert_eq!(lexer.peek_token(), Some(C1Token::Assign));
+    /// ```
+    pub fn mark(&mut self) -> Mark {
+        self.marks.push(self.cursor);
+        Mark {
+            position: self.cursor,
+            depth: self.marks.len(),
+        }
+    }
+
+    /// Return to the position of the mark, dropping it and all later marks.
+    ///
+    /// Panics if the mark was already dropped by resetting or releasing it or an earlier mark,
+    /// since the tokens it points to may be gone.
+    pub fn reset(&mut self, mark: Mark) {
+        self.check_mark(&mark);
+        self.cursor = mark.position;
+        self.release(mark);
+    }
+
+    /// Drop the mark and all later marks without moving. Panics like [`reset`](C1Lexer::reset) if
+    /// the mark was already dropped.
+    pub fn release(&mut self, mark: Mark) {
+        self.check_mark(&mark);
+        self.marks.truncate(mark.depth - 1);
+        self.drop_consumed();
+    }
+
+    fn check_mark(&self, mark: &Mark) {
+        assert!(
+            mark.depth <= self.marks.len() && self.marks[mark.depth - 1] == mark.position,
+            "mark at token {} was already reset or released",
+            mark.position
+        );
+    }
+
+    /// Drop the tokens in front of the current token that no mark needs anymore
+    fn drop_consumed(&mut self) {
+        let keep = self
+            .marks
+            .first()
+            .map_or(self.cursor, |&mark| mark.min(self.cursor));
+        while self.first < keep && self.tokens.pop_front().is_some() {
+            self.first += 1;
+        }
+    }
+
+    /// The buffered token `n` positions after the current one
+    fn token(&self, n: usize) -> Option<&TokenData<'a>> {
+        let index = self.cursor.checked_add(n)?.checked_sub(self.first)?;
+        self.tokens.get(index)
+    }
+
+    fn current(&self) -> Option<&TokenData<'a>> {
+        self.token(0)
+    }
+
+    fn peek(&self) -> Option<&TokenData<'a>> {
+        self.token(1)
+    }
+
+    /// Lex tokens until the token `n` positions after the current one is buffered or the text ends
+    fn fill(&mut self, n: usize) {
+        let Some(end) = self.cursor.checked_add(n) else {
+            return;
+        };
+        while self.first + self.tokens.len() <= end {
+            match self.next_token() {
+                Some(data) => self.tokens.push_back(data),
+                None => break,
+            }
+        }
     }
 
     /// Private method for reading the next token from the logos::Lexer and extracting the required data
     /// from it
     fn next_token(&mut self) -> Option<TokenData<'a>> {
+        let mut leading_trivia = Vec::new();
         // Retrieve the next token from the internal lexer
-        if let Some(c1_token) = self.logos_lexer.next() {
+        while let Some(c1_token) = self.logos_lexer.next() {
+            let span = self.logos_lexer.span();
+            if self.keep_trivia {
+                // Everything between the previous token and this one was skipped by logos
+                let skipped = &self.logos_lexer.source()[self.trivia_start..span.start];
+                split_trivia(skipped, &mut leading_trivia);
+            }
+            self.trivia_start = span.end;
             match c1_token {
                 C1Token::Linebreak => {
-                    // If the token is a linebreak, increase the line number and get the next token
-                    self.logos_line_number += 1;
-                    self.next_token()
+                    // Line breaks are only kept as trivia
+                    if self.keep_trivia {
+                        leading_trivia.push(Trivia {
+                            kind: TriviaKind::Linebreak,
+                            text: self.logos_lexer.slice(),
+                        });
+                    }
                 }
-                _ => Some(TokenData {
+                _ => {
                     // If the token is not a linebreak, initialize and return a TokenData instance
-                    token_type: c1_token,
-                    token_text: self.logos_lexer.slice(),
-                    token_line: self.logos_line_number,
-                }),
+                    let (line, column) = self.line_index.line_col(span.start);
+                    return Some(TokenData {
+                        token_type: c1_token,
+                        token_text: self.logos_lexer.slice(),
+                        token_value: TokenValue::decode(c1_token, self.logos_lexer.slice()),
+                        token_line: line,
+                        token_column: column,
+                        token_span: span.start + self.start..span.end + self.start,
+                        leading_trivia,
+                    });
+                }
             }
-        } else {
-            None
         }
+        if self.keep_trivia {
+            let skipped = &self.logos_lexer.source()[self.trivia_start..];
+            split_trivia(skipped, &mut leading_trivia);
+            self.trivia_start = self.logos_lexer.source().len();
+            self.eof_trivia.append(&mut leading_trivia);
+        }
+        None
+    }
+}
+
+impl<'a> TokenSource<'a> for C1Lexer<'a> {
+    fn current_token(&self) -> Option<C1Token> {
+        C1Lexer::current_token(self)
+    }
+
+    fn current_text(&self) -> Option<&'a str> {
+        C1Lexer::current_text(self)
+    }
+
+    fn current_line_number(&self) -> Option<usize> {
+        C1Lexer::current_line_number(self)
+    }
+
+    fn current_span(&self) -> Option<Range<usize>> {
+        C1Lexer::current_span(self)
+    }
+
+    fn current_file(&self) -> Option<FileId> {
+        C1Lexer::current_file(self)
+    }
+
+    fn current_leading_trivia(&self) -> &[Trivia<'a>] {
+        C1Lexer::current_leading_trivia(self)
+    }
+
+    fn peek_token(&self) -> Option<C1Token> {
+        C1Lexer::peek_token(self)
+    }
+
+    fn eat(&mut self) {
+        C1Lexer::eat(self)
+    }
+}
+
+/// The different kinds of trivia, i.e. source text without meaning for the parser.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TriviaKind {
+    /// Spaces, tabs, form feeds and carriage returns
+    Whitespace,
+    /// A single '\n'
+    Linebreak,
+    /// A `// ...` comment, including the line break that ends it
+    LineComment,
+    /// A `/* ... */` comment
+    BlockComment,
+}
+
+/// A piece of trivia together with its text.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+}
+
+/// Split text that was skipped by logos back into the single pieces of trivia it consists of.
+fn split_trivia<'a>(mut text: &'a str, trivia: &mut Vec<Trivia<'a>>) {
+    while !text.is_empty() {
+        let (kind, length) = if let Some(comment) = text.strip_prefix("/*") {
+            let end = comment.find("*/").map_or(text.len(), |end| end + 4);
+            (TriviaKind::BlockComment, end)
+        } else if text.starts_with("//") {
+            let end = text.find('\n').map_or(text.len(), |end| end + 1);
+            (TriviaKind::LineComment, end)
+        } else {
+            let end = text
+                .find(|c: char| !matches!(c, ' ' | '\r' | '\t' | '\x0c'))
+                .unwrap_or(text.len());
+            (TriviaKind::Whitespace, end.max(1))
+        };
+        trivia.push(Trivia {
+            kind,
+            text: &text[..length],
+        });
+        text = &text[length..];
     }
 }
 
@@ -298,37 +836,59 @@ impl<'a> C1Lexer<'a> {
 struct TokenData<'a> {
     token_type: C1Token,
     token_text: &'a str,
+    token_value: Option<TokenValue>,
     token_line: usize,
+    token_column: usize,
+    token_span: Range<usize>,
+    leading_trivia: Vec<Trivia<'a>>,
 }
 
 /// Hidden trait that makes it possible to implemented the required getter functionality directly for
-/// Option<TokenData>.
+/// Option<&TokenData>.
 trait TokenDataProvider<'a> {
     /// Return the type of the token, aka. its C1Token variant.
     fn token_type(&self) -> Option<C1Token>;
     /// Return the text of the token
-    fn text(&self) -> Option<&str>;
+    fn text(&self) -> Option<&'a str>;
+    /// Return the value of the token if it is a literal
+    fn value(&self) -> Option<TokenValue>;
     /// Return the line number of the token
     fn line_number(&self) -> Option<usize>;
+    /// Return the column of the token
+    fn column(&self) -> Option<usize>;
+    /// Return the byte range of the token
+    fn span(&self) -> Option<Range<usize>>;
 }
 
-impl<'a> TokenDataProvider<'a> for Option<TokenData<'a>> {
+impl<'a> TokenDataProvider<'a> for Option<&TokenData<'a>> {
     fn token_type(&self) -> Option<C1Token> {
-        self.as_ref().map(|data| data.token_type)
+        self.map(|data| data.token_type)
     }
 
     fn text(&self) -> Option<&'a str> {
-        self.as_ref().map(|data| data.token_text)
+        self.map(|data| data.token_text)
     }
 
     fn line_number(&self) -> Option<usize> {
-        self.as_ref().map(|data| data.token_line)
+        self.map(|data| data.token_line)
+    }
+
+    fn value(&self) -> Option<TokenValue> {
+        self.and_then(|data| data.token_value)
+    }
+
+    fn column(&self) -> Option<usize> {
+        self.map(|data| data.token_column)
+    }
+
+    fn span(&self) -> Option<Range<usize>> {
+        self.map(|data| data.token_span.clone())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lexer::C1Lexer;
+    use crate::lexer::{lexical_error, C1Lexer, Identifiers, TokenValue};
     use crate::C1Token;
 
     #[test]
@@ -384,4 +944,289 @@ mod tests {
         let lexer = C1Lexer::new("33E+2");
         assert_eq!(lexer.current_token(), Some(C1Token::ConstFloat));
     }
+
+    #[test]
+    fn literal_values() {
+        let values = |text| {
+            let mut lexer = C1Lexer::new(text);
+            let mut values = Vec::new();
+            while lexer.current_token().is_some() {
+                values.push(lexer.current_value());
+                lexer.eat();
+            }
+            values
+        };
+        let int = |value| Some(TokenValue::Int(value));
+        let float = |value| Some(TokenValue::Float(value));
+        assert_eq!(
+            values("42 007 1_000_000 0x1F 0XfF 0o17 0b1010 0b1_0 9223372036854775807"),
+            vec![
+                int(42),
+                int(7),
+                int(1_000_000),
+                int(31),
+                int(255),
+                int(15),
+                int(10),
+                int(2),
+                int(i64::MAX)
+            ]
+        );
+        assert_eq!(
+            values("1.5 .25 1.2e4 1.2E-2 33e+2 1_0.2_5 1e1_0"),
+            vec![
+                float(1.5),
+                float(0.25),
+                float(12000.0),
+                float(0.012),
+                float(3300.0),
+                float(10.25),
+                float(1e10)
+            ]
+        );
+        assert_eq!(
+            values("true false x \"s\""),
+            vec![
+                Some(TokenValue::Bool(true)),
+                Some(TokenValue::Bool(false)),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_literals() {
+        for (text, message) in [
+            (
+                "99999999999999999999",
+                "the integer literal does not fit into 64 bits in '99999999999999999999'",
+            ),
+            ("1.5e", "the exponent has no digits in '1.5e'"),
+            ("2E+", "the exponent has no digits in '2E+'"),
+            (".5e-", "the exponent has no digits in '.5e-'"),
+            ("1e999", "the float literal is out of range in '1e999'"),
+            ("0x", "the integer literal has no digits in '0x'"),
+            ("0b102", "invalid digit for base 2 in '0b102'"),
+            ("0x1G", "invalid digit for base 16 in '0x1G'"),
+            ("1__0", "'_' is only allowed between digits in '1__0'"),
+            ("1_", "'_' is only allowed between digits in '1_'"),
+            ("0x_1", "'_' is only allowed between digits in '0x_1'"),
+            ("1_.5", "'_' is only allowed between digits in '1_.5'"),
+        ] {
+            let lexer = C1Lexer::new(text);
+            assert_eq!(lexer.current_token(), Some(C1Token::Error), "{}", text);
+            assert_eq!(lexer.current_text(), Some(text));
+            assert_eq!(lexical_error(text), message);
+        }
+    }
+
+    #[test]
+    fn block_comments() {
+        let mut lexer =
+            C1Lexer::new("a /* x * y / z */ b /** doc **/ c /* http://example.org */ d");
+        for name in ["a", "b", "c", "d"] {
+            assert_eq!(lexer.current_token(), Some(C1Token::Identifier));
+            assert_eq!(lexer.current_text(), Some(name));
+            lexer.eat();
+        }
+        assert_eq!(lexer.current_token(), None);
+
+        let lexer = C1Lexer::new("/***/ a /*/ b */ c");
+        assert_eq!(lexer.current_text(), Some("a"));
+        assert_eq!(lexer.peek_text(), Some("c"));
+    }
+
+    #[test]
+    fn lines_in_block_comments() {
+        let mut lexer = C1Lexer::new("a /* one\ntwo\n*/ b\n/*\n*/c");
+        assert_eq!(lexer.current_line_number(), Some(1));
+        assert_eq!(lexer.peek_line_number(), Some(3));
+        lexer.eat();
+        assert_eq!(lexer.peek_line_number(), Some(5));
+
+        let mut lexer = C1Lexer::with_trivia("/* a\n*/\nb");
+        assert_eq!(lexer.current_line_number(), Some(3));
+        assert_eq!(lexer.current_leading_trivia().len(), 2);
+        lexer.eat();
+        assert_eq!(lexer.current_token(), None);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let mut lexer = C1Lexer::new("a\n/* b * c\nd");
+        lexer.eat();
+        assert_eq!(lexer.current_token(), Some(C1Token::Error));
+        assert_eq!(lexer.current_text(), Some("/* b * c\nd"));
+        assert_eq!(lexer.current_line_number(), Some(2));
+        assert_eq!(lexer.peek_token(), None);
+    }
+
+    #[test]
+    fn identifiers() {
+        let texts = |text| {
+            let mut lexer = C1Lexer::new(text);
+            let mut tokens = Vec::new();
+            while let Some(token) = lexer.current_token() {
+                tokens.push((token, lexer.current_text().unwrap()));
+                lexer.eat();
+            }
+            tokens
+        };
+        for name in [
+            "my_var", "_tmp", "_", "x_1", "integer", "doit", "bool2", "int_x", "if_", "floaty",
+            "returned", "truth", "falsey", "voidness", "whiles", "printfs",
+        ] {
+            assert_eq!(texts(name), vec![(C1Token::Identifier, name)]);
+        }
+        assert_eq!(
+            texts("1x"),
+            vec![(C1Token::ConstInt, "1"), (C1Token::Identifier, "x")]
+        );
+        assert_eq!(texts("int"), vec![(C1Token::KwInt, "int")]);
+        assert_eq!(texts("true"), vec![(C1Token::ConstBoolean, "true")]);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let text = "größe = ñ_1 + x;";
+        let mut lexer = C1Lexer::with_options(text, false, Identifiers::Unicode);
+        assert_eq!(lexer.current_text(), Some("größe"));
+        lexer.eat();
+        lexer.eat();
+        assert_eq!(lexer.current_token(), Some(C1Token::Identifier));
+        assert_eq!(lexer.current_text(), Some("ñ_1"));
+
+        let lexer = C1Lexer::with_options("²x", false, Identifiers::Unicode);
+        assert_eq!(lexer.current_token(), Some(C1Token::Error));
+
+        let lexer = C1Lexer::new(text);
+        assert_eq!(lexer.current_token(), Some(C1Token::Error));
+        assert_eq!(lexer.current_text(), Some("größe"));
+        assert_eq!(
+            lexical_error("größe"),
+            "identifier 'größe' contains characters outside of ASCII, which are only allowed \
+             with Unicode identifiers"
+        );
+    }
+
+    #[test]
+    fn lookahead() {
+        let mut lexer = C1Lexer::new("int x; int f() {}");
+        assert_eq!(lexer.peek_nth(0), lexer.current_token());
+        assert_eq!(lexer.peek_nth(1), lexer.peek_token());
+        assert_eq!(lexer.peek_nth(6), Some(C1Token::RightParenthesis));
+        assert_eq!(lexer.peek_nth_text(4), Some("f"));
+        assert_eq!(lexer.peek_nth(9), None);
+        assert_eq!(lexer.peek_nth(100), None);
+        assert_eq!(lexer.peek_nth(usize::MAX), None);
+        lexer.eat();
+        assert_eq!(lexer.current_text(), Some("x"));
+        assert_eq!(lexer.peek_nth(2), Some(C1Token::KwInt));
+        // Nothing behind the current token is kept without marks
+        assert_eq!(lexer.first, 1);
+    }
+
+    #[test]
+    fn marks() {
+        let mut lexer = C1Lexer::with_trivia("a /* 1 */ b c d");
+        let outer = lexer.mark();
+        lexer.eat();
+        let inner = lexer.mark();
+        lexer.eat();
+        lexer.eat();
+        assert_eq!(lexer.current_text(), Some("d"));
+        lexer.reset(inner);
+        assert_eq!(lexer.current_text(), Some("b"));
+        assert_eq!(lexer.current_leading_trivia().len(), 3);
+        lexer.eat();
+        lexer.reset(outer);
+        assert_eq!(lexer.current_text(), Some("a"));
+        assert_eq!(lexer.peek_text(), Some("b"));
+        assert!(lexer.marks.is_empty());
+
+        // Resetting to an outer mark drops the inner ones
+        let outer = lexer.mark();
+        lexer.eat();
+        let _inner = lexer.mark();
+        lexer.eat();
+        lexer.reset(outer);
+        assert!(lexer.marks.is_empty());
+
+        // Marks can be released at the end of the text
+        lexer.eat();
+        lexer.eat();
+        lexer.eat();
+        let mark = lexer.mark();
+        lexer.eat();
+        assert_eq!(lexer.current_token(), None);
+        lexer.release(mark);
+        assert_eq!(lexer.current_token(), None);
+        assert!(lexer.tokens.is_empty());
+    }
+
+    #[test]
+    fn nested_marks() {
+        // Releasing the inner mark keeps the outer one
+        let mut lexer = C1Lexer::new("a b c d");
+        let outer = lexer.mark();
+        lexer.eat();
+        let inner = lexer.mark();
+        lexer.eat();
+        lexer.release(inner);
+        lexer.reset(outer);
+        assert_eq!(lexer.current_text(), Some("a"));
+
+        // Resetting the inner mark first and the outer one after it
+        let outer = lexer.mark();
+        lexer.eat();
+        let inner = lexer.mark();
+        lexer.eat();
+        lexer.eat();
+        lexer.reset(inner);
+        assert_eq!(lexer.current_text(), Some("b"));
+        lexer.eat();
+        lexer.release(outer);
+        assert_eq!(lexer.current_text(), Some("c"));
+        assert_eq!(lexer.first, 2);
+        assert!(lexer.marks.is_empty());
+    }
+
+    fn reset_stale(release_outer: bool) {
+        let mut lexer = C1Lexer::new("a b c d");
+        let outer = lexer.mark();
+        lexer.eat();
+        let inner = lexer.mark();
+        lexer.eat();
+        lexer.eat();
+        if release_outer {
+            lexer.release(outer);
+        } else {
+            lexer.reset(outer);
+        }
+        lexer.reset(inner);
+    }
+
+    #[test]
+    #[should_panic(expected = "mark at token 1 was already reset or released")]
+    fn reset_after_releasing_outer_mark() {
+        reset_stale(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "mark at token 1 was already reset or released")]
+    fn reset_after_resetting_outer_mark() {
+        reset_stale(false);
+    }
+
+    #[test]
+    #[should_panic(expected = "mark at token 1 was already reset or released")]
+    fn release_after_releasing_outer_mark() {
+        let mut lexer = C1Lexer::new("a b c d");
+        let outer = lexer.mark();
+        lexer.eat();
+        let inner = lexer.mark();
+        lexer.release(outer);
+        lexer.release(inner);
+    }
 }
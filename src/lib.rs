@@ -1,12 +1,34 @@
+pub mod ast;
+pub mod bytecode;
+pub mod codegen;
+mod cst;
+pub mod highlight;
+pub mod ir;
+pub mod json;
 mod lexer;
+mod line_index;
+pub mod lsp;
+pub mod preprocessor;
+mod source_map;
+mod stream_lexer;
+mod token;
+pub mod typeck;
+pub mod vm;
 
 // Type definition for the Result that is being used by the parser. You may change it to anything
 // you want
-pub type ParseResult = Result<(), String>;
+pub type ParseResult = Result<(), ParseError>;
 
 pub use lexer::C1Lexer;
 pub use lexer::C1Token;
+pub use lexer::{Identifiers, Mark, TokenValue, Trivia, TriviaKind};
+pub use line_index::LineIndex;
+pub use source_map::{FileId, Location, SourceFile, SourceMap};
+pub use stream_lexer::{StreamLexer, DEFAULT_CHUNK_SIZE};
+pub use token::{tokenize, Token, TokenSource};
+
+pub use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, SyntaxTree};
 
 // You will need a re-export of your C1Parser definition. Here is an example:
 mod parser;
-pub use parser::C1Parser;
+pub use parser::{C1Parser, ParseError};
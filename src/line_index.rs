@@ -0,0 +1,94 @@
+/// # Overview
+/// Maps byte offsets in a text to line and column numbers. The start of every line is computed
+/// once, so a lookup is a binary search. Only `'\n'` ends a line; the `'\r'` of a `"\r\n"` belongs
+/// to the end of its line. Lines and columns count from 1, columns in characters.
+///
+/// # Examples
+/// ```
+/// use cb_3::LineIndex;
+///
+/// let index = LineIndex::new("int\r\n/* a\nb */ x");
+/// assert_eq!(index.line_col(0), (1, 1));
+/// assert_eq!(index.line_col(5), (2, 1));
+/// assert_eq!(index.line_col(15), (3, 6));
+/// assert_eq!(index.line_count(), 3);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> LineIndex<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        LineIndex { text, line_starts }
+    }
+
+    /// The number of lines; a text ending with a line break has an empty last line
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The line of the byte at the offset. Offsets past the end belong to the last line.
+    pub fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// The line and column of the byte at the offset. An offset inside a character
+    /// belongs to that character.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let start = self.line_starts[line - 1];
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        (line, self.text[start..offset].chars().count() + 1)
+    }
+
+    /// The offset of the first byte of the line, `None` if there is no such line
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        line.checked_sub(1)
+            .and_then(|index| self.line_starts.get(index).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LineIndex;
+
+    #[test]
+    fn crlf() {
+        let text = "a\r\nbc\r\n\r\nd";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_col(1), (1, 2));
+        assert_eq!(index.line_col(2), (1, 3));
+        assert_eq!(index.line_col(4), (2, 2));
+        assert_eq!(index.line_col(text.len() - 1), (4, 1));
+        assert_eq!(index.line_col(text.len()), (4, 2));
+        assert_eq!(index.line_start(3), Some(7));
+        assert_eq!(index.line_start(0), None);
+        assert_eq!(index.line_start(5), None);
+    }
+
+    #[test]
+    fn characters() {
+        let index = LineIndex::new("größe\n😀x");
+        assert_eq!(index.line_col(7), (1, 6));
+        assert_eq!(index.line_col(8), (2, 1));
+        assert_eq!(index.line_col(12), (2, 2));
+    }
+
+    #[test]
+    fn offsets_inside_characters() {
+        let index = LineIndex::new("ä\n😀x");
+        assert_eq!(index.line_col(1), (1, 1));
+        assert_eq!(index.line_col(5), (2, 1));
+        assert_eq!(index.line_col(6), (2, 1));
+        assert_eq!(index.line_col(7), (2, 2));
+    }
+}
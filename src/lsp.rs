@@ -0,0 +1,545 @@
+//! # Overview
+//! Language server for C(-1), speaking the Language Server Protocol over a pair of byte streams
+//! (stdin/stdout for the `c1-lsp` binary). Documents are synchronized in full on every change and
+//! re-parsed with [`C1Parser`].
+//!
+//! Supported requests and notifications:
+//! - diagnostics for syntax errors, published after `didOpen` and `didChange`
+//! - `textDocument/definition` and `textDocument/references` for functions
+//! - `textDocument/hover`, showing the signature of a function
+//! - `textDocument/documentSymbol`, listing the function definitions
+
+use crate::ast::{FunctionCall, FunctionDefinition, Program, Type};
+use crate::cst::{SyntaxNode, SyntaxToken};
+use crate::json::JsonValue;
+use crate::{C1Parser, LineIndex, ParseError};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+
+const SYMBOL_KIND_FUNCTION: usize = 12;
+const DIAGNOSTIC_SEVERITY_ERROR: usize = 1;
+const TEXT_DOCUMENT_SYNC_FULL: usize = 1;
+const ERROR_PARSE: i64 = -32700;
+const ERROR_INVALID_REQUEST: i64 = -32600;
+const ERROR_METHOD_NOT_FOUND: i64 = -32601;
+const ERROR_INVALID_PARAMS: i64 = -32602;
+
+/// Read messages from `input` and write responses and notifications to `output` until the client
+/// sends `exit` or closes the input. Returns the process exit code the protocol asks for: 0 if
+/// `shutdown` was requested before `exit`, 1 otherwise. A message that is not valid JSON is
+/// answered with a parse error; only broken headers and I/O errors end the session early.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<i32> {
+    let mut server = LanguageServer::new();
+    while let Some(content) = read_content(&mut input)? {
+        let replies = match parse_content(content) {
+            Ok(message) => server.handle_message(&message),
+            Err(reason) => vec![error_response(&JsonValue::Null, ERROR_PARSE, &reason)],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exit_code.is_some() {
+            break;
+        }
+    }
+    Ok(server.exit_code.unwrap_or(1))
+}
+
+/// Read a single message with its `Content-Length` header. Returns `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<JsonValue>> {
+    match read_content(input)? {
+        Some(content) => parse_content(content).map(Some).map_err(invalid_data),
+        None => Ok(None),
+    }
+}
+
+/// Read the content of a single message without parsing it
+fn read_content<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = content_length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    // The buffer grows with the content that arrives, not with what the header claims
+    let mut content = Vec::new();
+    input.take(length as u64).read_to_end(&mut content)?;
+    if content.len() < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "message is shorter than its Content-Length",
+        ));
+    }
+    Ok(Some(content))
+}
+
+fn parse_content(content: Vec<u8>) -> Result<JsonValue, String> {
+    let content = String::from_utf8(content).map_err(|_| "message is not UTF-8".to_string())?;
+    JsonValue::parse(&content)
+}
+
+/// Write a single message with its `Content-Length` header
+pub fn write_message<W: Write>(output: &mut W, message: &JsonValue) -> io::Result<()> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// The state of the server: the open documents and the progress of the shutdown sequence.
+#[derive(Default)]
+pub struct LanguageServer {
+    documents: HashMap<String, String>,
+    shutdown_requested: bool,
+    exit_code: Option<i32>,
+}
+
+impl LanguageServer {
+    pub fn new() -> LanguageServer {
+        LanguageServer::default()
+    }
+
+    /// Return the exit code once the client has sent `exit`
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handle a request or notification and return the messages that should be sent back
+    pub fn handle_message(&mut self, message: &JsonValue) -> Vec<JsonValue> {
+        let method = message
+            .get("method")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("");
+        let params = message.get("params").unwrap_or(&JsonValue::Null);
+        match message.get("id") {
+            Some(id) => vec![self.handle_request(id, method, params)],
+            None => self.handle_notification(method, params),
+        }
+    }
+
+    fn handle_request(&mut self, id: &JsonValue, method: &str, params: &JsonValue) -> JsonValue {
+        if self.shutdown_requested {
+            return error_response(id, ERROR_INVALID_REQUEST, "the server is shutting down");
+        }
+        let result = match method {
+            "initialize" => Some(initialize_result()),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Some(JsonValue::Null)
+            }
+            "textDocument/definition" => self.with_position(params, Self::definition),
+            "textDocument/references" => self.with_position(params, Self::references),
+            "textDocument/hover" => self.with_position(params, Self::hover),
+            "textDocument/documentSymbol" => document_uri(params)
+                .and_then(|uri| self.documents.get(uri))
+                .map(|text| document_symbols(text)),
+            _ => return error_response(id, ERROR_METHOD_NOT_FOUND, "method not found"),
+        };
+        match result {
+            Some(result) => response(id, result),
+            None => error_response(id, ERROR_INVALID_PARAMS, "unknown document or position"),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        match method {
+            "exit" => {
+                self.exit_code = Some(if self.shutdown_requested { 0 } else { 1 });
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let uri = document_uri(params);
+                let text = params
+                    .pointer(&["textDocument", "text"])
+                    .and_then(JsonValue::as_str);
+                match (uri, text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string()),
+                    _ => Vec::new(),
+                }
+            }
+            "textDocument/didChange" => {
+                // With full synchronization, the last change contains the whole document
+                let changes = params.get("contentChanges").and_then(JsonValue::as_array);
+                let text = changes
+                    .and_then(<[JsonValue]>::last)
+                    .and_then(|change| change.get("text"))
+                    .and_then(JsonValue::as_str);
+                match (document_uri(params), text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string()),
+                    _ => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => match document_uri(params) {
+                Some(uri) => {
+                    self.documents.remove(uri);
+                    vec![publish_diagnostics(uri, Vec::new())]
+                }
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> Vec<JsonValue> {
+        let diagnostics = match C1Parser::parse(&text) {
+            Ok(()) => Vec::new(),
+            Err(error) => vec![diagnostic(&text, &error)],
+        };
+        self.documents.insert(uri.to_string(), text);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    /// Resolve the document and the byte offset a position request refers to
+    fn with_position(
+        &self,
+        params: &JsonValue,
+        handler: fn(&str, &str, usize, &JsonValue) -> JsonValue,
+    ) -> Option<JsonValue> {
+        let uri = document_uri(params)?;
+        let text = self.documents.get(uri)?;
+        let position = params.get("position")?;
+        let line = usize::try_from(position.get("line")?.as_i64()?).ok()?;
+        let character = usize::try_from(position.get("character")?.as_i64()?).ok()?;
+        let offset = Positions::new(text).offset(line, character);
+        Some(handler(uri, text, offset, params))
+    }
+
+    fn definition(uri: &str, text: &str, offset: usize, _: &JsonValue) -> JsonValue {
+        let Some(symbols) = Symbols::of(text) else {
+            return JsonValue::Null;
+        };
+        let definition = symbols
+            .name_at(offset)
+            .and_then(|name| symbols.definition(name));
+        match definition {
+            Some(function) => location(uri, text, &function.name_span),
+            None => JsonValue::Null,
+        }
+    }
+
+    fn references(uri: &str, text: &str, offset: usize, params: &JsonValue) -> JsonValue {
+        let include_declaration = params
+            .pointer(&["context", "includeDeclaration"])
+            .and_then(JsonValue::as_bool)
+            .unwrap_or(false);
+        let Some(symbols) = Symbols::of(text) else {
+            return JsonValue::Null;
+        };
+        let Some(name) = symbols.name_at(offset) else {
+            return JsonValue::Null;
+        };
+        let mut locations = Vec::new();
+        if include_declaration {
+            for function in symbols.functions.iter().filter(|f| f.name == name) {
+                locations.push(location(uri, text, &function.name_span));
+            }
+        }
+        for call in symbols.calls.iter().filter(|call| call.name == name) {
+            locations.push(location(uri, text, &call.span));
+        }
+        JsonValue::Array(locations)
+    }
+
+    fn hover(_: &str, text: &str, offset: usize, _: &JsonValue) -> JsonValue {
+        let Some(symbols) = Symbols::of(text) else {
+            return JsonValue::Null;
+        };
+        let Some(span) = symbols.name_span_at(offset) else {
+            return JsonValue::Null;
+        };
+        let Some(function) = symbols.definition(&text[span.clone()]) else {
+            return JsonValue::Null;
+        };
+        JsonValue::object(vec![
+            (
+                "contents",
+                JsonValue::object(vec![
+                    ("kind", "markdown".into()),
+                    (
+                        "value",
+                        format!("```c\n{}\n```", function.signature()).into(),
+                    ),
+                ]),
+            ),
+            ("range", range(text, &span)),
+        ])
+    }
+}
+
+fn initialize_result() -> JsonValue {
+    JsonValue::object(vec![
+        (
+            "capabilities",
+            JsonValue::object(vec![
+                ("textDocumentSync", TEXT_DOCUMENT_SYNC_FULL.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("hoverProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+            ]),
+        ),
+        (
+            "serverInfo",
+            JsonValue::object(vec![
+                ("name", "c1-lsp".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn document_uri(params: &JsonValue) -> Option<&str> {
+    params.pointer(&["textDocument", "uri"])?.as_str()
+}
+
+fn response(id: &JsonValue, result: JsonValue) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: &JsonValue, code: i64, message: &str) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        (
+            "error",
+            JsonValue::object(vec![("code", code.into()), ("message", message.into())]),
+        ),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<JsonValue>) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            JsonValue::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", JsonValue::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn diagnostic(text: &str, error: &ParseError) -> JsonValue {
+    // Errors at the end of the text are reported at the very end of the document
+    let span = error.span.clone().unwrap_or(text.len()..text.len());
+    JsonValue::object(vec![
+        ("range", range(text, &span)),
+        ("severity", DIAGNOSTIC_SEVERITY_ERROR.into()),
+        ("source", "c1".into()),
+        ("message", error.message.as_str().into()),
+    ])
+}
+
+fn document_symbols(text: &str) -> JsonValue {
+    let Some(symbols) = Symbols::of(text) else {
+        return JsonValue::Array(Vec::new());
+    };
+    let symbols = symbols
+        .functions
+        .iter()
+        .map(|function| {
+            JsonValue::object(vec![
+                ("name", function.name.as_str().into()),
+                ("detail", function.signature().into()),
+                ("kind", SYMBOL_KIND_FUNCTION.into()),
+                ("range", range(text, &function.span)),
+                ("selectionRange", range(text, &function.name_span)),
+            ])
+        })
+        .collect();
+    JsonValue::Array(symbols)
+}
+
+fn location(uri: &str, text: &str, span: &Range<usize>) -> JsonValue {
+    JsonValue::object(vec![("uri", uri.into()), ("range", range(text, span))])
+}
+
+fn range(text: &str, span: &Range<usize>) -> JsonValue {
+    let index = Positions::new(text);
+    JsonValue::object(vec![
+        ("start", index.position(span.start)),
+        ("end", index.position(span.end)),
+    ])
+}
+
+/// A function definition found in a document
+struct FunctionSymbol {
+    name: String,
+    return_type: Type,
+    name_span: Range<usize>,
+    span: Range<usize>,
+}
+
+impl FunctionSymbol {
+    fn signature(&self) -> String {
+        format!("{} {}()", self.return_type, self.name)
+    }
+}
+
+/// A call of a function found in a document
+struct CallSymbol {
+    name: String,
+    span: Range<usize>,
+}
+
+/// The function definitions and calls of a syntactically valid document
+struct Symbols {
+    functions: Vec<FunctionSymbol>,
+    calls: Vec<CallSymbol>,
+}
+
+impl Symbols {
+    fn of(text: &str) -> Option<Symbols> {
+        let tree = C1Parser::parse_tree(text).ok()?;
+        let program = Program::cast(&tree.root)?;
+        let functions = program
+            .functions()
+            .map(|function: FunctionDefinition| {
+                let tokens = function.syntax().tokens();
+                let start = tokens.first().map_or(0, |token| token.offset);
+                let end = tokens.last().map_or(0, |token| token_span(token).end);
+                FunctionSymbol {
+                    name: function.name().to_string(),
+                    return_type: function.return_type(),
+                    name_span: token_span(function.name_token()),
+                    span: start..end,
+                }
+            })
+            .collect();
+        let mut calls = Vec::new();
+        collect_calls(&tree.root, &mut calls);
+        Some(Symbols { functions, calls })
+    }
+
+    fn definition(&self, name: &str) -> Option<&FunctionSymbol> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// Return the span of the function name (in a definition or a call) at the given offset
+    fn name_span_at(&self, offset: usize) -> Option<Range<usize>> {
+        let definitions = self.functions.iter().map(|function| &function.name_span);
+        let calls = self.calls.iter().map(|call| &call.span);
+        definitions
+            .chain(calls)
+            .find(|span| span.start <= offset && offset <= span.end)
+            .cloned()
+    }
+
+    fn name_at(&self, offset: usize) -> Option<&str> {
+        let span = self.name_span_at(offset)?;
+        let definitions = self.functions.iter().map(|f| (&f.name, &f.name_span));
+        let calls = self.calls.iter().map(|call| (&call.name, &call.span));
+        definitions
+            .chain(calls)
+            .find(|(_, candidate)| **candidate == span)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+fn collect_calls(node: &SyntaxNode, calls: &mut Vec<CallSymbol>) {
+    if let Some(call) = FunctionCall::cast(node) {
+        calls.push(CallSymbol {
+            name: call.name().to_string(),
+            span: token_span(call.name_token()),
+        });
+    }
+    for child in node.child_nodes() {
+        collect_calls(child, calls);
+    }
+}
+
+fn token_span(token: &SyntaxToken) -> Range<usize> {
+    token.offset..token.offset + token.text.len()
+}
+
+/// Converts between byte offsets and LSP positions, whose lines count from 0 and whose characters
+/// are counted in UTF-16 code units.
+struct Positions<'a> {
+    text: &'a str,
+    lines: LineIndex<'a>,
+}
+
+impl<'a> Positions<'a> {
+    fn new(text: &'a str) -> Positions<'a> {
+        Positions {
+            text,
+            lines: LineIndex::new(text),
+        }
+    }
+
+    fn position(&self, offset: usize) -> JsonValue {
+        let line = self.lines.line(offset);
+        let start = self.lines.line_start(line).expect("the line exists");
+        let character: usize = self.text[start..offset].chars().map(char::len_utf16).sum();
+        JsonValue::object(vec![
+            ("line", (line - 1).into()),
+            ("character", character.into()),
+        ])
+    }
+
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let Some(start) = line
+            .checked_add(1)
+            .and_then(|line| self.lines.line_start(line))
+        else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (index, c) in self.text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + index;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::JsonValue;
+    use crate::lsp::Positions;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "a\n// größe 😀 x\r\nend";
+        let index = Positions::new(text);
+        let x = text.find('x').unwrap();
+        let position = index.position(x);
+        assert_eq!(position.get("line").and_then(JsonValue::as_i64), Some(1));
+        assert_eq!(
+            position.get("character").and_then(JsonValue::as_i64),
+            Some(12)
+        );
+        assert_eq!(index.offset(1, 12), x);
+        assert_eq!(index.offset(2, 0), text.find("end").unwrap());
+        assert_eq!(index.offset(1, 100), text.find("\r\n").unwrap() + 1);
+        assert_eq!(index.offset(7, 0), text.len());
+    }
+}
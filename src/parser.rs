@@ -1,22 +1,44 @@
- use crate::lexer::{C1Lexer, C1Token};
- use crate::ParseResult;
- use std::ops::{Deref, DerefMut};
- 
- pub struct C1Parser<'a>(C1Lexer<'a>); 
- // Implement Deref and DerefMut to enable the direct use of the lexer's methods
- impl<'a> Deref for C1Parser<'a> {
-     type Target = C1Lexer<'a>;
-
-     fn deref(&self) -> &Self::Target {
-         &self.0
-     }
- }
-
- impl<'a> DerefMut for C1Parser<'a> {
-     fn deref_mut(&mut self) -> &mut Self::Target {
-         &mut self.0
-     }
- }
+use crate::cst::{Checkpoint, SyntaxKind, SyntaxToken, SyntaxTree, TreeBuilder};
+use crate::lexer::{lexical_error, C1Lexer, C1Token, Identifiers};
+use crate::{FileId, ParseResult, SourceMap, Token, TokenSource};
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range};
+
+/// A syntax error. Besides the message, the error records where the offending token is located;
+/// line and span are `None` if the parser unexpectedly reached the end of the text. The file is
+/// only known when parsing a file of a [`SourceMap`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub file: Option<FileId>,
+    pub line: Option<usize>,
+    pub span: Option<Range<usize>>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Recursive descent parser for C(-1), reading its tokens from a [`TokenSource`]. The second field
+/// holds the builder for the concrete syntax tree and is only present when the parser was asked to
+/// build one.
+pub struct C1Parser<'a, S = C1Lexer<'a>>(S, Option<TreeBuilder<'a>>);
+// Implement Deref and DerefMut to enable the direct use of the token source's methods
+impl<'a, S> Deref for C1Parser<'a, S> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, S> DerefMut for C1Parser<'a, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
 
 impl<'a> C1Parser<'a> {
     pub fn parse(text: &str) -> ParseResult {
@@ -24,252 +46,492 @@ impl<'a> C1Parser<'a> {
         parser.program()
     }
 
-    fn initialize_parser(text: &str) -> C1Parser {
-        C1Parser(C1Lexer::new(text))
+    /// Parse tokens that were lexed before, e.g. a deserialized token stream
+    /// ```
+    /// use cb_3::{tokenize, C1Parser};
+    ///
+    /// let tokens = tokenize("void main() { x = 1; }");
+    /// assert!(C1Parser::parse_tokens(&tokens).is_ok());
+    /// assert!(C1Parser::parse_tokens(&tokens[1..]).is_err());
+    /// ```
+    pub fn parse_tokens(tokens: &[Token]) -> ParseResult {
+        C1Parser::parse_source(tokens)
+    }
+
+    /// Parse the given text into a lossless concrete syntax tree
+    pub fn parse_tree(text: &'a str) -> Result<SyntaxTree<'a>, ParseError> {
+        Self::parse_tree_with(text, Identifiers::Ascii)
+    }
+
+    /// Parse the given text into a lossless concrete syntax tree, accepting the given identifiers
+    pub fn parse_tree_with(
+        text: &'a str,
+        identifiers: Identifiers,
+    ) -> Result<SyntaxTree<'a>, ParseError> {
+        C1Parser::parse_tree_from(C1Lexer::with_options(text, true, identifiers))
+    }
+
+    /// Parse a file of the source map into a lossless concrete syntax tree. The spans of errors
+    /// are global offsets of the source map.
+    /// ```
+    /// use cb_3::{C1Parser, SourceMap};
+    ///
+    /// let mut sources = SourceMap::new();
+    /// let main = sources.add("main.c-1", "void main() {}\n");
+    /// let lib = sources.add("lib.c-1", "int one() {\n  return 1\n}\n");
+    ///
+    /// assert!(C1Parser::parse_file(&sources, main).is_ok());
+    /// let error = C1Parser::parse_file(&sources, lib).unwrap_err();
+    /// assert_eq!((error.file, error.line), (Some(lib), Some(3)));
+    /// let location = sources.resolve(error.span.unwrap().start).unwrap();
+    /// assert_eq!((location.file, location.line, location.column), (lib, 3, 1));
+    /// ```
+    pub fn parse_file(sources: &'a SourceMap, file: FileId) -> Result<SyntaxTree<'a>, ParseError> {
+        C1Parser::parse_tree_from(C1Lexer::for_file(sources, file))
+    }
+
+    fn initialize_parser(text: &str) -> C1Parser<'_> {
+        C1Parser(C1Lexer::new(text), None)
+    }
+}
+
+impl<'a, S: TokenSource<'a>> C1Parser<'a, S> {
+    /// Parse the tokens of the given source
+    pub fn parse_source(source: S) -> ParseResult {
+        C1Parser(source, None).program()
+    }
+
+    /// Parse the tokens of the given source into a concrete syntax tree. The tree is only lossless
+    /// if the source provides the trivia.
+    pub fn parse_tree_from(source: S) -> Result<SyntaxTree<'a>, ParseError> {
+        let mut parser = C1Parser(source, Some(TreeBuilder::new()));
+        parser.program()?;
+        let eof_trivia = parser.current_leading_trivia().to_vec();
+        let builder = parser.1.take().expect("the tree builder is present");
+        Ok(builder.finish(eof_trivia))
     }
 
     fn program(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::Program);
         loop {
             match &self.current_token() {
-                None => break Ok(()),
-                Some(_) => self.functiondefinition()?
+                None => break,
+                Some(_) => self.functiondefinition()?,
             }
         }
+        self.finish_node()
     }
 
     fn functiondefinition(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::FunctionDefinition);
         self.r#type()?;
-        self.check_and_eat_token(&C1Token::Identifier, &self.error_message_current("error"))?; 
-        self.check_and_eat_token(&C1Token::LeftParenthesis, &self.error_message_current("error"))?;
-        self.check_and_eat_token(&C1Token::RightParenthesis, &self.error_message_current("error"))?;
-        self.check_and_eat_token(&C1Token::LeftBrace, &self.error_message_current("error"))?; 
+        self.check_and_eat_token(&C1Token::Identifier, &self.error_message_current("error"))?;
+        self.check_and_eat_token(
+            &C1Token::LeftParenthesis,
+            &self.error_message_current("error"),
+        )?;
+        self.check_and_eat_token(
+            &C1Token::RightParenthesis,
+            &self.error_message_current("error"),
+        )?;
+        self.check_and_eat_token(&C1Token::LeftBrace, &self.error_message_current("error"))?;
         self.statementlist()?;
-        self.check_and_eat_token(&C1Token::RightBrace, &self.error_message_current("Failed at RightBrace in functiondefinition"))
+        self.check_and_eat_token(
+            &C1Token::RightBrace,
+            &self.error_message_current("Failed at RightBrace in functiondefinition"),
+        )?;
+        self.finish_node()
     }
 
     fn function_call(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::FunctionCall);
         self.check_and_eat_token(&C1Token::Identifier, &self.error_message_current("error1"))?;
-        self.check_and_eat_token(&C1Token::LeftParenthesis, &self.error_message_current("error2"))?;
-        self.check_and_eat_token(&C1Token::RightParenthesis, &self.error_message_current("error3"))
+        self.check_and_eat_token(
+            &C1Token::LeftParenthesis,
+            &self.error_message_current("error2"),
+        )?;
+        self.check_and_eat_token(
+            &C1Token::RightParenthesis,
+            &self.error_message_current("error3"),
+        )?;
+        self.finish_node()
     }
 
     fn statementlist(&mut self) -> ParseResult {
-        loop {
-            match self.current_token().unwrap() {
-                C1Token::LeftBrace | C1Token::KwIf | C1Token::KwReturn | C1Token::KwPrintf | C1Token::Identifier => self.block()?,
-                _ => break Ok(()),     
-            }
-        
-        }   
+        self.start_node(SyntaxKind::StatementList);
+        while matches!(
+            self.current_token(),
+            Some(
+                C1Token::LeftBrace
+                    | C1Token::KwIf
+                    | C1Token::KwReturn
+                    | C1Token::KwPrintf
+                    | C1Token::Identifier
+            )
+        ) {
+            self.block()?;
+        }
+        self.finish_node()
     }
-    
 
     fn block(&mut self) -> ParseResult {
         if self.current_matches(&C1Token::LeftBrace) {
-            self.check_and_eat_token(&C1Token::LeftBrace, &self.error_message_current("error4"))?; 
+            self.start_node(SyntaxKind::Block);
+            self.check_and_eat_token(&C1Token::LeftBrace, &self.error_message_current("error4"))?;
             self.statementlist()?;
-            self.check_and_eat_token(&C1Token::RightBrace, &self.error_message_current("error5"))
-        }
-        else {
+            self.check_and_eat_token(&C1Token::RightBrace, &self.error_message_current("error5"))?;
+            self.finish_node()
+        } else {
             self.statement()
-        }    
+        }
     }
 
     fn statement(&mut self) -> ParseResult {
-        match &self.current_token().unwrap() {
-            &C1Token::KwIf => {
-                self.ifstatement()
-            },    
-            &C1Token::KwReturn => {
-                self.returnstatement()?; 
-                self.check_and_eat_token(&C1Token::Semicolon, &self.error_message_current("error7"))
-            },
-            &C1Token::KwPrintf => {
+        self.start_node(SyntaxKind::Statement);
+        match self.current_token().unwrap() {
+            C1Token::KwIf => self.ifstatement()?,
+            C1Token::KwReturn => {
+                self.returnstatement()?;
+                self.check_and_eat_token(
+                    &C1Token::Semicolon,
+                    &self.error_message_current("error7"),
+                )?
+            }
+            C1Token::KwPrintf => {
                 self.printf()?;
-                self.check_and_eat_token(&C1Token::Semicolon, &self.error_message_current("error8"))
-            },
-            &C1Token::Identifier => {
+                self.check_and_eat_token(
+                    &C1Token::Semicolon,
+                    &self.error_message_current("error8"),
+                )?
+            }
+            C1Token::Identifier => {
                 if self.next_matches(&C1Token::Assign) {
                     self.statassignment()?;
-                    self.check_and_eat_token(&C1Token::Semicolon, &self.error_message_current("error9"))
-                }
-                else {
+                    self.check_and_eat_token(
+                        &C1Token::Semicolon,
+                        &self.error_message_current("error9"),
+                    )?
+                } else {
                     self.function_call()?;
-                    self.check_and_eat_token(&C1Token::Semicolon, &self.error_message_current("error10"))
+                    self.check_and_eat_token(
+                        &C1Token::Semicolon,
+                        &self.error_message_current("error10"),
+                    )?
                 }
-            },    
-            _ => Err(self.error_message_current("error11")),
+            }
+            _ => Err(self.error_message_current("error11"))?,
         }
+        self.finish_node()
     }
 
     fn ifstatement(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::IfStatement);
         self.check_and_eat_token(&C1Token::KwIf, &self.error_message_current("error12"))?;
-        self.check_and_eat_token(&C1Token::LeftParenthesis, &self.error_message_current("error13"))?;
+        self.check_and_eat_token(
+            &C1Token::LeftParenthesis,
+            &self.error_message_current("error13"),
+        )?;
         self.assignment()?;
-        self.check_and_eat_token(&C1Token::RightParenthesis, &self.error_message_current("error14"))?;
-        self.block()
+        self.check_and_eat_token(
+            &C1Token::RightParenthesis,
+            &self.error_message_current("error14"),
+        )?;
+        self.block()?;
+        self.finish_node()
     }
 
-    fn returnstatement(&mut self) -> ParseResult { 
-        if self.next_matches(&C1Token::Identifier) || self.next_matches(&C1Token::ConstInt) || self.next_matches(&C1Token::ConstFloat) || self.next_matches(&C1Token::ConstBoolean) {
+    fn returnstatement(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::ReturnStatement);
+        if self.next_matches(&C1Token::Identifier)
+            || self.next_matches(&C1Token::ConstInt)
+            || self.next_matches(&C1Token::ConstFloat)
+            || self.next_matches(&C1Token::ConstBoolean)
+        {
             self.check_and_eat_token(&C1Token::KwReturn, &self.error_message_current("error15"))?;
-            self.assignment()
-        }
-        else {
-            self.check_and_eat_token(&C1Token::KwReturn, &self.error_message_current("error16"))
+            self.assignment()?
+        } else {
+            self.check_and_eat_token(&C1Token::KwReturn, &self.error_message_current("error16"))?
         }
+        self.finish_node()
     }
 
     fn printf(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::Printf);
         self.check_and_eat_token(&C1Token::KwPrintf, &self.error_message_current("error17"))?;
-        self.check_and_eat_token(&C1Token::LeftParenthesis, &self.error_message_current("error18"))?;
+        self.check_and_eat_token(
+            &C1Token::LeftParenthesis,
+            &self.error_message_current("error18"),
+        )?;
         self.assignment()?;
-        self.check_and_eat_token(&C1Token::RightParenthesis, &self.error_message_current("error19"))
+        self.check_and_eat_token(
+            &C1Token::RightParenthesis,
+            &self.error_message_current("error19"),
+        )?;
+        self.finish_node()
     }
 
     fn r#type(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::Type);
         match self.current_token().unwrap() {
-            C1Token::KwBoolean => self.check_and_eat_token(&C1Token::KwBoolean, &self.error_message_current("errorh"))?,
-            C1Token::KwFloat => self.check_and_eat_token(&C1Token::KwFloat, &self.error_message_current("errorhh"))?,
-            C1Token::KwInt => self.check_and_eat_token(&C1Token::KwInt, &self.error_message_current("errorhhh"))?,
-            C1Token::KwVoid => self.check_and_eat_token(&C1Token::KwVoid, &self.error_message_current("errorhhhh"))?,
-            _ => Err(&self.error_message_current("errorhhhhh"))?,
+            C1Token::KwBoolean => self
+                .check_and_eat_token(&C1Token::KwBoolean, &self.error_message_current("errorh"))?,
+            C1Token::KwFloat => {
+                self.check_and_eat_token(&C1Token::KwFloat, &self.error_message_current("errorhh"))?
+            }
+            C1Token::KwInt => {
+                self.check_and_eat_token(&C1Token::KwInt, &self.error_message_current("errorhhh"))?
+            }
+            C1Token::KwVoid => self
+                .check_and_eat_token(&C1Token::KwVoid, &self.error_message_current("errorhhhh"))?,
+            _ => Err(self.error_message_current("errorhhhhh"))?,
         }
-        Ok(())
+        self.finish_node()
     }
 
     fn statassignment(&mut self) -> ParseResult {
+        self.start_node(SyntaxKind::StatAssignment);
         self.check_and_eat_token(&C1Token::Identifier, &self.error_message_current("error21"))?;
         self.check_and_eat_token(&C1Token::Assign, &self.error_message_current("error22"))?;
-        self.assignment()     
+        self.assignment()?;
+        self.finish_node()
     }
 
     fn assignment(&mut self) -> ParseResult {
         if self.current_matches(&C1Token::Identifier) && self.next_matches(&C1Token::Assign) {
+            self.start_node(SyntaxKind::Assignment);
             self.check_and_eat_token(&C1Token::Identifier, &self.error_message_current("error23"))?;
             self.check_and_eat_token(&C1Token::Assign, &self.error_message_current("error24"))?;
-            self.assignment()
-        }
-        else {
+            self.assignment()?;
+            self.finish_node()
+        } else {
             self.expr()
         }
     }
 
     fn expr(&mut self) -> ParseResult {
+        let checkpoint = self.checkpoint();
         self.simpexpr()?;
-        if self.current_matches(&C1Token::Equal) 
-            || self.current_matches(&C1Token::NotEqual) 
-            || self.current_matches(&C1Token::LessEqual) 
-            || self.current_matches(&C1Token::GreaterEqual) 
-            || self.current_matches(&C1Token::Greater) 
-            || self.current_matches(&C1Token::Less) {
-            match &self.current_token().unwrap() {
-                &C1Token::Equal => self.check_and_eat_token(&C1Token::Equal, &self.error_message_current("error25"))?,
-                &C1Token::NotEqual => self.check_and_eat_token(&C1Token::NotEqual, &self.error_message_current("error26"))?,
-                &C1Token::LessEqual => self.check_and_eat_token(&C1Token::LessEqual, &self.error_message_current("error27"))?,
-                &C1Token::GreaterEqual => self.check_and_eat_token(&C1Token::GreaterEqual, &self.error_message_current("error28"))?,
-                &C1Token::Greater => self.check_and_eat_token(&C1Token::Greater, &self.error_message_current("error29"))?,
-                &C1Token::Less => self.check_and_eat_token(&C1Token::Less, &self.error_message_current("error30"))?,
-                _ => Err(&self.error_message_current("error31"))?,
+        if self.current_matches(&C1Token::Equal)
+            || self.current_matches(&C1Token::NotEqual)
+            || self.current_matches(&C1Token::LessEqual)
+            || self.current_matches(&C1Token::GreaterEqual)
+            || self.current_matches(&C1Token::Greater)
+            || self.current_matches(&C1Token::Less)
+        {
+            self.start_node_at(checkpoint, SyntaxKind::BinaryExpr);
+            match self.current_token().unwrap() {
+                C1Token::Equal => self
+                    .check_and_eat_token(&C1Token::Equal, &self.error_message_current("error25"))?,
+                C1Token::NotEqual => self.check_and_eat_token(
+                    &C1Token::NotEqual,
+                    &self.error_message_current("error26"),
+                )?,
+                C1Token::LessEqual => self.check_and_eat_token(
+                    &C1Token::LessEqual,
+                    &self.error_message_current("error27"),
+                )?,
+                C1Token::GreaterEqual => self.check_and_eat_token(
+                    &C1Token::GreaterEqual,
+                    &self.error_message_current("error28"),
+                )?,
+                C1Token::Greater => self.check_and_eat_token(
+                    &C1Token::Greater,
+                    &self.error_message_current("error29"),
+                )?,
+                C1Token::Less => self
+                    .check_and_eat_token(&C1Token::Less, &self.error_message_current("error30"))?,
+                _ => Err(self.error_message_current("error31"))?,
             };
-            self.simpexpr()
-        }
-        else {
-           Ok(()) 
+            self.simpexpr()?;
+            self.finish_node()
+        } else {
+            Ok(())
         }
     }
 
     fn simpexpr(&mut self) -> ParseResult {
+        let checkpoint = self.checkpoint();
         if self.current_matches(&C1Token::Minus) {
+            self.start_node(SyntaxKind::UnaryExpr);
             self.check_and_eat_token(&C1Token::Minus, &self.error_message_current("error32"))?;
+            self.term()?;
+            self.finish_node()?;
+        } else {
+            self.term()?;
         }
-        self.term()?;
         loop {
-            if self.current_matches(&C1Token::Plus) || self.current_matches(&C1Token::Minus) || self.current_matches(&C1Token::Or) {
-                match &self.current_token().unwrap() {
-                    &C1Token::Plus => self.check_and_eat_token(&C1Token::Plus, &self.error_message_current("error33"))?,
-                    &C1Token::Minus => self.check_and_eat_token(&C1Token::Minus, &self.error_message_current("error34"))?,
-                    &C1Token::Or => self.check_and_eat_token(&C1Token::Or, &self.error_message_current("error35"))?,
-                    _ => Err(&self.error_message_current("error36"))?,
+            if self.current_matches(&C1Token::Plus)
+                || self.current_matches(&C1Token::Minus)
+                || self.current_matches(&C1Token::Or)
+            {
+                self.start_node_at(checkpoint, SyntaxKind::BinaryExpr);
+                match self.current_token().unwrap() {
+                    C1Token::Plus => self.check_and_eat_token(
+                        &C1Token::Plus,
+                        &self.error_message_current("error33"),
+                    )?,
+                    C1Token::Minus => self.check_and_eat_token(
+                        &C1Token::Minus,
+                        &self.error_message_current("error34"),
+                    )?,
+                    C1Token::Or => self.check_and_eat_token(
+                        &C1Token::Or,
+                        &self.error_message_current("error35"),
+                    )?,
+                    _ => Err(self.error_message_current("error36"))?,
                 }
                 self.term()?;
-            }
-            else {
-                break Ok(())
+                self.finish_node()?;
+            } else {
+                break Ok(());
             }
         }
     }
 
     fn term(&mut self) -> ParseResult {
+        let checkpoint = self.checkpoint();
         self.factor()?;
         loop {
-            if self.current_matches(&C1Token::Asterisk) || self.current_matches(&C1Token::Slash) || self.current_matches(&C1Token::And) {
-                match &self.current_token().unwrap() {                                             
-                    &C1Token::Asterisk => self.check_and_eat_token(&C1Token::Asterisk, &self.error_message_current("error37"))?,
-                    &C1Token::Slash => self.check_and_eat_token(&C1Token::Slash, &self.error_message_current("error38"))?,
-                    &C1Token::And => self.check_and_eat_token(&C1Token::And, &self.error_message_current("error39"))?,
-                    _ => Err(&self.error_message_current("error40"))?,
+            if self.current_matches(&C1Token::Asterisk)
+                || self.current_matches(&C1Token::Slash)
+                || self.current_matches(&C1Token::And)
+            {
+                self.start_node_at(checkpoint, SyntaxKind::BinaryExpr);
+                match self.current_token().unwrap() {
+                    C1Token::Asterisk => self.check_and_eat_token(
+                        &C1Token::Asterisk,
+                        &self.error_message_current("error37"),
+                    )?,
+                    C1Token::Slash => self.check_and_eat_token(
+                        &C1Token::Slash,
+                        &self.error_message_current("error38"),
+                    )?,
+                    C1Token::And => self.check_and_eat_token(
+                        &C1Token::And,
+                        &self.error_message_current("error39"),
+                    )?,
+                    _ => Err(self.error_message_current("error40"))?,
                 };
                 self.factor()?;
-            }
-            else {
-                break Ok(())
+                self.finish_node()?;
+            } else {
+                break Ok(());
             }
         }
     }
 
     fn factor(&mut self) -> ParseResult {
-        match &self.current_token().unwrap() {
-            &C1Token::ConstInt => self.check_and_eat_token(&C1Token::ConstInt, &self.error_message_current("error41")),
-            &C1Token::ConstFloat => self.check_and_eat_token(&C1Token::ConstFloat, &self.error_message_current("error42")),
-            &C1Token::ConstBoolean => self.check_and_eat_token(&C1Token::ConstBoolean, &self.error_message_current("error43")),
-            &C1Token::Identifier =>  { 
+        let Some(current) = self.current_token() else {
+            return Err(self.error_message_current("error47"));
+        };
+        match current {
+            C1Token::ConstInt => self.leaf(SyntaxKind::Literal, &C1Token::ConstInt, "error41"),
+            C1Token::ConstFloat => self.leaf(SyntaxKind::Literal, &C1Token::ConstFloat, "error42"),
+            C1Token::ConstBoolean => {
+                self.leaf(SyntaxKind::Literal, &C1Token::ConstBoolean, "error43")
+            }
+            C1Token::Identifier => {
                 if self.next_matches(&C1Token::LeftParenthesis) {
                     self.function_call()
+                } else {
+                    self.leaf(SyntaxKind::NameRef, &C1Token::Identifier, "error44")
                 }
-                else {
-                    self.check_and_eat_token(&C1Token::Identifier, &self.error_message_current("error44"))
-                }    
-            },
-            &C1Token::LeftParenthesis => {
-                self.check_and_eat_token(&C1Token::LeftParenthesis, &self.error_message_current("error45"))?;
+            }
+            C1Token::LeftParenthesis => {
+                self.start_node(SyntaxKind::ParenExpr);
+                self.check_and_eat_token(
+                    &C1Token::LeftParenthesis,
+                    &self.error_message_current("error45"),
+                )?;
                 self.assignment()?;
-                self.check_and_eat_token(&C1Token::RightParenthesis, &self.error_message_current("error46"))
-            },
-            _ => Err(self.error_message_current("error47"))
-        }  
+                self.check_and_eat_token(
+                    &C1Token::RightParenthesis,
+                    &self.error_message_current("error46"),
+                )?;
+                self.finish_node()
+            }
+            _ => Err(self.error_message_current("error47")),
+        }
+    }
+
+    /// Wrap a single token into a node of the given kind
+    fn leaf(&mut self, kind: SyntaxKind, token: &C1Token, reason: &'static str) -> ParseResult {
+        self.start_node(kind);
+        self.check_and_eat_token(token, &self.error_message_current(reason))?;
+        self.finish_node()
     }
 
     // uses eat from lexer
     pub fn eat(&mut self) {
+        if let Some(builder) = &mut self.1 {
+            if let (Some(kind), Some(text), Some(line), Some(span)) = (
+                self.0.current_token(),
+                self.0.current_text(),
+                self.0.current_line_number(),
+                self.0.current_span(),
+            ) {
+                builder.token(SyntaxToken {
+                    kind,
+                    text,
+                    line,
+                    offset: span.start,
+                    leading_trivia: self.0.current_leading_trivia().to_vec(),
+                });
+            }
+        }
         self.deref_mut().eat();
     }
 
+    fn start_node(&mut self, kind: SyntaxKind) {
+        if let Some(builder) = &mut self.1 {
+            builder.start_node(kind);
+        }
+    }
+
+    fn start_node_at(&mut self, checkpoint: Option<Checkpoint>, kind: SyntaxKind) {
+        if let (Some(builder), Some(checkpoint)) = (&mut self.1, checkpoint) {
+            builder.start_node_at(checkpoint, kind);
+        }
+    }
+
+    fn checkpoint(&self) -> Option<Checkpoint> {
+        self.1.as_ref().map(TreeBuilder::checkpoint)
+    }
+
+    /// Close the most recently started node. Always succeeds, so it can be used as the tail of a
+    /// parse method.
+    fn finish_node(&mut self) -> ParseResult {
+        if let Some(builder) = &mut self.1 {
+            builder.finish_node();
+        }
+        Ok(())
+    }
+
     /// Check whether the current token is equal to the given token. If yes, consume it, otherwise
     /// return an error with the given error message
-    fn check_and_eat_token(&mut self, token: &C1Token, error_message: &str) -> ParseResult {
+    fn check_and_eat_token(&mut self, token: &C1Token, error: &ParseError) -> ParseResult {
         if self.current_matches(token) {
             self.eat();
             Ok(())
         } else {
-            Err(String::from(error_message))
+            Err(error.clone())
         }
     }
 
-//    /// For each token in the given slice, check whether the token is equal to the current token,
-//    /// consume the current token, and check the next token in the slice against the next token
-//    /// provided by the lexer.
-//    fn check_and_eat_tokens(&mut self, token: &[C1Token], error_message: &str) -> ParseResult {
-//        match token
-//            .iter()
-//            .map(|t| self.check_and_eat_token(t, error_message))
-//            .filter(ParseResult::is_err)
-//            .last()
-//        {
-//            None => Ok(()),
-//            Some(err) => err,
-//        }
-//    }
+    //    /// For each token in the given slice, check whether the token is equal to the current token,
+    //    /// consume the current token, and check the next token in the slice against the next token
+    //    /// provided by the lexer.
+    //    fn check_and_eat_tokens(&mut self, token: &[C1Token], error_message: &str) -> ParseResult {
+    //        match token
+    //            .iter()
+    //            .map(|t| self.check_and_eat_token(t, error_message))
+    //            .filter(ParseResult::is_err)
+    //            .last()
+    //        {
+    //            None => Ok(()),
+    //            Some(err) => err,
+    //        }
+    //    }
 
     /// Check whether the given token matches the current token
     fn current_matches(&self, token: &C1Token) -> bool {
@@ -287,47 +549,58 @@ impl<'a> C1Parser<'a> {
         }
     }
 
-    /// Check whether any of the tokens matches the current token.
-//    fn any_match_current(&self, token: &[C1Token]) -> bool {
-//        token.iter().any(|t| self.current_matches(t))
-//    }
-
-    /// Check whether any of the tokens matches the current token, then consume it
-//    fn any_match_and_eat(&mut self, token: &[C1Token], error_message: &String) -> ParseResult {
-//        if token
-//            .iter()
-//            .any(|t| self.check_and_eat_token(t, "").is_ok())
-//        {
-//            Ok(())
-//        } else {
-//            Err(String::from(error_message))
-//        }
-//    }
-
-    fn error_message_current(&self, reason: &'static str) -> String {
-        match self.current_token() {
+    //    /// Check whether any of the tokens matches the current token.
+    //    fn any_match_current(&self, token: &[C1Token]) -> bool {
+    //        token.iter().any(|t| self.current_matches(t))
+    //    }
+
+    //    /// Check whether any of the tokens matches the current token, then consume it
+    //    fn any_match_and_eat(&mut self, token: &[C1Token], error_message: &String) -> ParseResult {
+    //        if token
+    //            .iter()
+    //            .any(|t| self.check_and_eat_token(t, "").is_ok())
+    //        {
+    //            Ok(())
+    //        } else {
+    //            Err(String::from(error_message))
+    //        }
+    //    }
+
+    fn error_message_current(&self, reason: &'static str) -> ParseError {
+        let message = match self.current_token() {
             None => format!("{}. Reached EOF", reason),
+            Some(C1Token::Error) => format!(
+                "Lexical error at line {:?}: {}",
+                self.current_line_number().unwrap(),
+                lexical_error(self.current_text().unwrap())
+            ),
             Some(_) => format!(
                 "{} at line {:?} with text: '{}'",
                 reason,
                 self.current_line_number().unwrap(),
                 self.current_text().unwrap()
             ),
+        };
+        ParseError {
+            message,
+            file: self.current_file(),
+            line: self.current_line_number(),
+            span: self.current_span(),
         }
     }
 
-//    fn error_message_peek(&mut self, reason: &'static str) -> String {
-//        match self.peek_token() {
-//            None => format!("{}. Reached EOF", reason),
-//            Some(_) => format!(
-//                "{} at line {:?} with text: '{}'",
-//                reason,
-//                self.peek_line_number().unwrap(),
-//                self.peek_text().unwrap()
-//            ),
-//        }
-//    }
-}    
+    //    fn error_message_peek(&mut self, reason: &'static str) -> String {
+    //        match self.peek_token() {
+    //            None => format!("{}. Reached EOF", reason),
+    //            Some(_) => format!(
+    //                "{} at line {:?} with text: '{}'",
+    //                reason,
+    //                self.peek_line_number().unwrap(),
+    //                self.peek_text().unwrap()
+    //            ),
+    //        }
+    //    }
+}
 //
 // #[cfg(test)]
 // mod tests {
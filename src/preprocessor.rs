@@ -0,0 +1,609 @@
+//! Preprocessor that runs before the lexer. It supports `#include "file"`, object-like
+//! `#define NAME value` and conditionals with `#ifdef`, `#ifndef`, `#else` and `#endif`.
+//!
+//! The preprocessor works on lines: every line of the output comes from exactly one line of an
+//! input file, and the [`LineMap`] records which one. Directives and lines excluded by a
+//! conditional become empty lines, and an `#include` is replaced by the lines of the included
+//! file. Macros are only replaced outside of comments and string literals. The rest of a block
+//! comment that starts on a directive or an excluded line becomes spaces.
+//!
+//! ```
+//! use cb_3::preprocessor::preprocess;
+//! use std::collections::HashMap;
+//!
+//! let mut files = HashMap::new();
+//! files.insert("max.c-1".to_string(), "#define MAX 10\nint max() { return MAX; }\n".to_string());
+//!
+//! let main = "#include \"max.c-1\"\nvoid main() { printf(max() + MAX); }\n";
+//! let preprocessed = preprocess("main.c-1", main, &mut files).unwrap();
+//! assert_eq!(
+//!     preprocessed.text,
+//!     "\nint max() { return 10; }\nvoid main() { printf(max() + 10); }\n"
+//! );
+//! assert_eq!(preprocessed.line_map.locate(2), Some(("max.c-1", 2)));
+//! assert_eq!(preprocessed.line_map.locate(3), Some(("main.c-1", 2)));
+//! ```
+
+use crate::ParseError;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Provides the text of included files. Implemented by [`FileLoader`] and, for tests, by a map
+/// from paths to file contents.
+pub trait Loader {
+    /// The path of the file that `#include "name"` in the file at `includer` refers to
+    fn resolve(&self, name: &str, includer: &str) -> String {
+        let _ = includer;
+        name.to_string()
+    }
+
+    fn load(&mut self, path: &str) -> io::Result<String>;
+}
+
+/// Loads files from the file system. Included files are looked up relative to the directory of the
+/// including file.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FileLoader;
+
+impl Loader for FileLoader {
+    fn resolve(&self, name: &str, includer: &str) -> String {
+        match Path::new(includer).parent() {
+            Some(directory) => directory.join(name).to_string_lossy().into_owned(),
+            None => name.to_string(),
+        }
+    }
+
+    fn load(&mut self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+impl Loader for HashMap<String, String> {
+    fn load(&mut self, path: &str) -> io::Result<String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+    }
+}
+
+/// The output of the preprocessor
+#[derive(Debug, PartialEq, Clone)]
+pub struct Preprocessed {
+    pub text: String,
+    pub line_map: LineMap,
+}
+
+/// Maps the lines of the preprocessed text back to the files and lines they came from
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LineMap {
+    files: Vec<String>,
+    /// The index of the file and the line in it, for every line of the output
+    lines: Vec<(usize, usize)>,
+}
+
+impl LineMap {
+    /// The paths of all files that were read, the preprocessed file first
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The file and the line in it that the line of the preprocessed text came from
+    pub fn locate(&self, line: usize) -> Option<(&str, usize)> {
+        let &(file, original) = self.lines.get(line.checked_sub(1)?)?;
+        Some((&self.files[file], original))
+    }
+
+    /// Rewrite a diagnostic about the line of the preprocessed text to name the original file and
+    /// line instead
+    pub fn relocate(&self, message: &str, line: usize) -> String {
+        match self.locate(line) {
+            Some((file, original)) => {
+                let location = format!("at line {} of {}", original, file);
+                let at_line = format!("at line {}", line);
+                match message.find(&at_line) {
+                    Some(start) => format!(
+                        "{}{}{}",
+                        &message[..start],
+                        location,
+                        &message[start + at_line.len()..]
+                    ),
+                    None => format!("{} {}", message, location),
+                }
+            }
+            None => message.to_string(),
+        }
+    }
+
+    /// Point a parse error of the preprocessed text at the original file and line. The span is
+    /// dropped, as it refers to the preprocessed text.
+    pub fn map_error(&self, error: ParseError) -> ParseError {
+        match error
+            .line
+            .and_then(|line| Some((line, self.locate(line)?.1)))
+        {
+            Some((line, original)) => ParseError {
+                message: self.relocate(&error.message, line),
+                line: Some(original),
+                span: None,
+                ..error
+            },
+            None => error,
+        }
+    }
+}
+
+/// An invalid directive, or an included file that could not be read
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PreprocessError {
+    pub message: String,
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {} of {}", self.message, self.line, self.file)
+    }
+}
+
+/// Preprocess the text of the file at the given path, loading included files with the loader
+pub fn preprocess<L: Loader>(
+    path: &str,
+    text: &str,
+    loader: &mut L,
+) -> Result<Preprocessed, PreprocessError> {
+    let mut preprocessor = Preprocessor {
+        loader,
+        defines: HashMap::new(),
+        includes: Vec::new(),
+        output: String::new(),
+        line_map: LineMap::default(),
+    };
+    preprocessor.file(path, text)?;
+    Ok(Preprocessed {
+        text: preprocessor.output,
+        line_map: preprocessor.line_map,
+    })
+}
+
+struct Preprocessor<'l, L> {
+    loader: &'l mut L,
+    defines: HashMap<String, String>,
+    /// The paths of the files currently being included, outermost first
+    includes: Vec<String>,
+    output: String,
+    line_map: LineMap,
+}
+
+/// An `#ifdef` or `#ifndef` whose `#endif` was not reached yet
+struct Condition {
+    directive: &'static str,
+    line: usize,
+    active: bool,
+    in_else: bool,
+}
+
+impl<'l, L: Loader> Preprocessor<'l, L> {
+    fn file(&mut self, path: &str, text: &str) -> Result<(), PreprocessError> {
+        let file = match self.line_map.files.iter().position(|file| file == path) {
+            Some(file) => file,
+            None => {
+                self.line_map.files.push(path.to_string());
+                self.line_map.files.len() - 1
+            }
+        };
+        self.includes.push(path.to_string());
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut in_comment = false;
+        // Whether the start of the open block comment is in the output
+        let mut comment_emitted = false;
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let error = |message: String| PreprocessError {
+                message,
+                file: path.to_string(),
+                line: number,
+            };
+            let active = conditions.iter().all(|condition| condition.active);
+            let hidden_comment = in_comment && !comment_emitted;
+            let parts = split_line(line, &mut in_comment);
+            let code: String = parts
+                .iter()
+                .map(|&(part, text)| if part == Part::Comment { " " } else { text })
+                .collect();
+            let directive = match code.trim_start().strip_prefix('#') {
+                Some(directive) => directive.trim_start(),
+                None => {
+                    if active {
+                        let expanded = self.expand_line(&parts, hidden_comment);
+                        self.emit(file, number, &expanded);
+                        comment_emitted = !hidden_comment || parts.len() > 1;
+                    } else {
+                        self.emit(file, number, "");
+                        comment_emitted = false;
+                    }
+                    continue;
+                }
+            };
+            comment_emitted = false;
+            let name_end = identifier_end(directive);
+            let (name, rest) = (&directive[..name_end], directive[name_end..].trim());
+            match name {
+                "ifdef" | "ifndef" => {
+                    let directive = if name == "ifdef" { "#ifdef" } else { "#ifndef" };
+                    let defined = self
+                        .defines
+                        .contains_key(macro_name(rest, directive).map_err(error)?);
+                    conditions.push(Condition {
+                        directive,
+                        line: number,
+                        active: defined == (name == "ifdef"),
+                        in_else: false,
+                    });
+                }
+                "else" => match conditions.last_mut() {
+                    Some(condition) if !condition.in_else => {
+                        condition.active = !condition.active;
+                        condition.in_else = true;
+                    }
+                    Some(_) => return Err(error("#else after #else".to_string())),
+                    None => return Err(error("#else without #ifdef or #ifndef".to_string())),
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(error("#endif without #ifdef or #ifndef".to_string()));
+                    }
+                }
+                _ if !active => {}
+                "define" => {
+                    let end = identifier_end(rest);
+                    let value = &rest[end..];
+                    macro_name(&rest[..end], "#define").map_err(error)?;
+                    if value.starts_with('(') {
+                        return Err(error("function-like macros are not supported".to_string()));
+                    }
+                    if !value.is_empty() && !value.starts_with(char::is_whitespace) {
+                        return Err(error("expected a space after the macro name".to_string()));
+                    }
+                    self.defines
+                        .insert(rest[..end].to_string(), value.trim().to_string());
+                }
+                "include" => {
+                    let name = rest
+                        .strip_prefix('"')
+                        .and_then(|rest| rest.strip_suffix('"'))
+                        .filter(|name| !name.is_empty() && !name.contains('"'))
+                        .ok_or_else(|| {
+                            error("expected a file name in quotes after #include".to_string())
+                        })?;
+                    let included = self.loader.resolve(name, path);
+                    if self.includes.contains(&included) {
+                        return Err(error(format!("'{}' includes itself", included)));
+                    }
+                    let text = self
+                        .loader
+                        .load(&included)
+                        .map_err(|cause| error(format!("cannot include '{}': {}", name, cause)))?;
+                    self.file(&included, &text)?;
+                    continue;
+                }
+                "" => {}
+                _ => return Err(error(format!("unknown directive '#{}'", name))),
+            }
+            self.emit(file, number, "");
+        }
+        if let Some(condition) = conditions.last() {
+            return Err(PreprocessError {
+                message: format!("{} without #endif", condition.directive),
+                file: path.to_string(),
+                line: condition.line,
+            });
+        }
+        self.includes.pop();
+        Ok(())
+    }
+
+    fn emit(&mut self, file: usize, line: usize, text: &str) {
+        self.output.push_str(text);
+        self.output.push('\n');
+        self.line_map.lines.push((file, line));
+    }
+
+    /// Replace the macros in the code of a line. With `hidden_comment`, the line starts in a block
+    /// comment that was opened on a line that is not in the output, so the rest of that comment
+    /// becomes spaces.
+    fn expand_line(&self, parts: &[(Part, &str)], hidden_comment: bool) -> String {
+        parts
+            .iter()
+            .enumerate()
+            .map(|(index, &(part, text))| match part {
+                Part::Code => self.expand(text, &mut Vec::new()),
+                Part::Comment if hidden_comment && index == 0 => " ".repeat(text.chars().count()),
+                _ => text.to_string(),
+            })
+            .collect()
+    }
+
+    /// Replace the macros in the code. Macros in the value of a macro are replaced as well, except
+    /// for the macros that are already being replaced.
+    fn expand<'t>(&'t self, code: &str, hidden: &mut Vec<&'t str>) -> String {
+        let mut output = String::new();
+        let mut rest = code;
+        while let Some(c) = rest.chars().next() {
+            let end = if c.is_alphabetic() || c == '_' {
+                let end = identifier_end(rest);
+                match self.defines.get_key_value(&rest[..end]) {
+                    Some((name, value)) if !hidden.contains(&name.as_str()) => {
+                        hidden.push(name);
+                        let mut in_comment = false;
+                        for (part, text) in split_line(value, &mut in_comment) {
+                            match part {
+                                Part::Code => output.push_str(&self.expand(text, hidden)),
+                                _ => output.push_str(text),
+                            }
+                        }
+                        hidden.pop();
+                    }
+                    _ => output.push_str(&rest[..end]),
+                }
+                end
+            } else if c.is_ascii_digit() {
+                // Numbers like 1e5 or 0xFF contain letters that are not identifiers
+                let end = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+                    .unwrap_or(rest.len());
+                output.push_str(&rest[..end]);
+                end
+            } else {
+                output.push(c);
+                c.len_utf8()
+            };
+            rest = &rest[end..];
+        }
+        output
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Part {
+    Code,
+    Comment,
+    String,
+}
+
+/// Split a line into code, comments and string literals. A block comment that is still open at the
+/// end of the line continues on the next one.
+fn split_line<'t>(line: &'t str, in_comment: &mut bool) -> Vec<(Part, &'t str)> {
+    let mut parts = Vec::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        if *in_comment {
+            let end = match rest.find("*/") {
+                Some(end) => {
+                    *in_comment = false;
+                    end + 2
+                }
+                None => rest.len(),
+            };
+            parts.push((Part::Comment, &rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+        let start = rest
+            .char_indices()
+            .find(|&(index, c)| {
+                c == '"' || rest[index..].starts_with("//") || rest[index..].starts_with("/*")
+            })
+            .map(|(index, _)| index);
+        let start = match start {
+            Some(start) => start,
+            None => {
+                parts.push((Part::Code, rest));
+                break;
+            }
+        };
+        if start > 0 {
+            parts.push((Part::Code, &rest[..start]));
+        }
+        rest = &rest[start..];
+        let (part, end) = if rest.starts_with("//") {
+            (Part::Comment, rest.len())
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(end) => (Part::Comment, end + 4),
+                None => {
+                    *in_comment = true;
+                    (Part::Comment, rest.len())
+                }
+            }
+        } else {
+            // An unterminated string literal is left for the lexer to report
+            let end = rest[1..].find('"').map_or(rest.len(), |end| end + 2);
+            (Part::String, end)
+        };
+        parts.push((part, &rest[..end]));
+        rest = &rest[end..];
+    }
+    parts
+}
+
+/// The length of the identifier at the start of the text, 0 if there is none
+fn identifier_end(text: &str) -> usize {
+    match text.chars().next() {
+        Some(c) if c.is_alphabetic() || c == '_' => text
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(text.len()),
+        _ => 0,
+    }
+}
+
+/// Check that the argument of a directive is a single macro name
+fn macro_name<'t>(text: &'t str, directive: &str) -> Result<&'t str, String> {
+    if !text.is_empty() && identifier_end(text) == text.len() {
+        Ok(text)
+    } else {
+        Err(format!("expected a macro name after {}", directive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preprocess, FileLoader, Loader, PreprocessError, Preprocessed};
+    use crate::ast::Program;
+    use crate::bytecode;
+    use crate::vm::Vm;
+    use crate::C1Parser;
+    use std::collections::HashMap;
+
+    fn run(files: &[(&str, &str)]) -> Result<Preprocessed, PreprocessError> {
+        let mut loader: HashMap<String, String> = files
+            .iter()
+            .map(|&(path, text)| (path.to_string(), text.to_string()))
+            .collect();
+        let main = loader["main"].clone();
+        preprocess("main", &main, &mut loader)
+    }
+
+    fn error(files: &[(&str, &str)]) -> String {
+        run(files).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn includes() {
+        let guarded = "#ifndef LIB\n#define LIB\nint one() { return 1; }\n#endif\n";
+        let main = "#include \"lib\"\n#include \"lib\"\nvoid main() {\n  printf(one());\n}\n";
+        let preprocessed = run(&[("main", main), ("lib", guarded)]).unwrap();
+        assert_eq!(
+            preprocessed.text,
+            "\n\nint one() { return 1; }\n\n\n\n\n\nvoid main() {\n  printf(one());\n}\n"
+        );
+        let map = &preprocessed.line_map;
+        assert_eq!(map.files(), ["main", "lib"]);
+        assert_eq!(map.locate(3), Some(("lib", 3)));
+        assert_eq!(map.locate(8), Some(("lib", 4)));
+        assert_eq!(map.locate(9), Some(("main", 3)));
+        assert_eq!(map.locate(12), None);
+    }
+
+    #[test]
+    fn defines() {
+        let main = "#define N 4\n#define TWICE N + N // twice\n#define LOOP LOOP + 1\n\
+                    x = TWICE * N2 /* N */ + 1e5N; printf(\"N\"); N\n\
+                    y = LOOP; /* N\nN */ N\n";
+        let preprocessed = run(&[("main", main)]).unwrap();
+        assert_eq!(
+            preprocessed.text,
+            "\n\n\nx = 4 + 4 * N2 /* N */ + 1e5N; printf(\"N\"); 4\ny = LOOP + 1; /* N\nN */ 4\n"
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let main = "#define A\n#ifdef A\na\n# ifndef A\nb\n#else\nc\n#  endif\n#else\n\
+                    #include \"missing\"\n#bogus\nd\n#endif\n/*\n#define B */\n#ifdef B\ne\n#endif\n";
+        let preprocessed = run(&[("main", main)]).unwrap();
+        let lines: Vec<&str> = preprocessed
+            .text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .collect();
+        assert_eq!(lines, ["a", "c", "/*", "#define B */"]);
+    }
+
+    #[test]
+    fn comments_on_directive_lines() {
+        let main = "#define X 1 /* start\n end */\nvoid main() { printf(X);\n\
+                    #ifdef X /* a\nb\n*/ x = X; /* c\n d */ }\n#endif // e\n";
+        let preprocessed = run(&[("main", main)]).unwrap();
+        assert_eq!(
+            preprocessed.text,
+            "\n       \nvoid main() { printf(1);\n\n \n   x = 1; /* c\n d */ }\n\n"
+        );
+        assert!(C1Parser::parse(&preprocessed.text).is_ok());
+
+        let main = "#ifdef X\n/* a\n#endif */ x\n#endif /* b\n*/\n";
+        let preprocessed = run(&[("main", main)]).unwrap();
+        assert_eq!(preprocessed.text, "\n\n\n\n  \n");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error(&[
+                ("main", "\n#include \"lib\"\n"),
+                ("lib", "#include \"main\"")
+            ]),
+            "'main' includes itself at line 1 of lib"
+        );
+        assert_eq!(
+            error(&[("main", "#include \"lib\"\n")]),
+            "cannot include 'lib': no such file at line 1 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#include <lib>\n")]),
+            "expected a file name in quotes after #include at line 1 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#ifdef A\n#ifndef B\n#endif\n")]),
+            "#ifdef without #endif at line 1 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#ifdef A\n#else\n#else\n#endif\n")]),
+            "#else after #else at line 3 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#endif\n")]),
+            "#endif without #ifdef or #ifndef at line 1 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#define F(x) x\n")]),
+            "function-like macros are not supported at line 1 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#define 1 2\n")]),
+            "expected a macro name after #define at line 1 of main"
+        );
+        assert_eq!(
+            error(&[("main", "#if A\n")]),
+            "unknown directive '#if' at line 1 of main"
+        );
+    }
+
+    #[test]
+    fn file_paths() {
+        let loader = FileLoader;
+        assert_eq!(loader.resolve("lib.c-1", "tests/main.c-1"), "tests/lib.c-1");
+        assert_eq!(loader.resolve("lib.c-1", "main.c-1"), "lib.c-1");
+        assert_eq!(loader.resolve("lib.c-1", "-"), "lib.c-1");
+    }
+
+    #[test]
+    fn parse_errors_in_included_files() {
+        let lib = "int one() {\n  return 1\n}\n";
+        let main = "#include \"lib\"\nvoid main() {}\n";
+        let preprocessed = run(&[("main", main), ("lib", lib)]).unwrap();
+        let error = C1Parser::parse(&preprocessed.text).unwrap_err();
+        let error = preprocessed.line_map.map_error(error);
+        assert_eq!(error.message, "error7 at line 3 of lib with text: '}'");
+        assert_eq!(error.line, Some(3));
+        assert_eq!(error.span, None);
+    }
+
+    #[test]
+    fn runtime_errors_in_included_files() {
+        let lib = "int divide() {\n  zero = 0;\n  return 1 / zero;\n}\n";
+        let main = "#include \"lib\"\nvoid main() {\n  divide();\n}\n";
+        let preprocessed = run(&[("main", main), ("lib", lib)]).unwrap();
+        let tree = C1Parser::parse_tree(&preprocessed.text).unwrap();
+        let module = bytecode::compile(Program::cast(&tree.root).unwrap()).unwrap();
+        let error = Vm::new(&module).run("main", &mut Vec::new()).unwrap_err();
+        let line = error.location().unwrap().line;
+        assert_eq!(
+            preprocessed.line_map.relocate(&error.to_string(), line),
+            "division by zero in divide() at line 3 of lib"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::LineIndex;
+use std::ops::Range;
+
+/// Identifies a file of a [`SourceMap`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct FileId(u32);
+
+/// A file of a [`SourceMap`] with its name and text
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SourceFile {
+    name: String,
+    text: String,
+    start: usize,
+}
+
+impl SourceFile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The global offset of the first byte of the file
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The global offsets of the file, including the offset right after its last byte
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.start + self.text.len() + 1
+    }
+
+    pub fn line_index(&self) -> LineIndex<'_> {
+        LineIndex::new(&self.text)
+    }
+}
+
+/// A position in a file of a [`SourceMap`]. Lines and columns count from 1, columns in characters.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Location {
+    pub file: FileId,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// # Overview
+/// Owns the files of a program that is split across several files. The files are laid out one
+/// after the other in one global range of byte offsets, so an offset alone tells the file and the
+/// position in it. Lexers created with [`C1Lexer::for_file`](crate::C1Lexer::for_file) report the
+/// spans of their tokens as global offsets.
+///
+/// # Examples
+/// ```
+/// use cb_3::{Location, SourceMap};
+///
+/// let mut sources = SourceMap::new();
+/// let main = sources.add("main.c-1", "void main() {\n  f();\n}\n");
+/// let lib = sources.add("lib.c-1", "void f() {}\n");
+///
+/// let start = sources.file(lib).start();
+/// assert_eq!(sources.file(lib).name(), "lib.c-1");
+/// assert_eq!(sources.resolve(18), Some(Location { file: main, line: 2, column: 5 }));
+/// assert_eq!(sources.resolve(start + 5), Some(Location { file: lib, line: 1, column: 6 }));
+/// assert_eq!(sources.resolve(start + 100), None);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Add a file after all files that were added before
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        let start = self.files.last().map_or(0, |file| file.span().end);
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile {
+            name: name.into(),
+            text: text.into(),
+            start,
+        });
+        id
+    }
+
+    /// The file with the given id. Panics if the id belongs to another source map.
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    /// The ids of all files in the order they were added
+    pub fn file_ids(&self) -> impl Iterator<Item = FileId> {
+        (0..self.files.len() as u32).map(FileId)
+    }
+
+    /// The file that the global offset lies in
+    pub fn file_at(&self, offset: usize) -> Option<FileId> {
+        let index = self
+            .files
+            .partition_point(|file| file.start <= offset)
+            .checked_sub(1)?;
+        self.files[index]
+            .span()
+            .contains(&offset)
+            .then_some(FileId(index as u32))
+    }
+
+    /// The file, line and column of the global offset
+    pub fn resolve(&self, offset: usize) -> Option<Location> {
+        let id = self.file_at(offset)?;
+        let file = self.file(id);
+        let (line, column) = file.line_index().line_col(offset - file.start);
+        Some(Location {
+            file: id,
+            line,
+            column,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Location, SourceMap};
+
+    #[test]
+    fn file_boundaries() {
+        let mut sources = SourceMap::new();
+        let first = sources.add("a", "ab\n");
+        let empty = sources.add("b", "");
+        let last = sources.add("c", "x");
+        assert_eq!(sources.file(first).span(), 0..4);
+        assert_eq!(sources.file(empty).span(), 4..5);
+        assert_eq!(sources.file(last).span(), 5..7);
+        assert_eq!(sources.file_ids().collect::<Vec<_>>(), [first, empty, last]);
+
+        let location = |file, line, column| Some(Location { file, line, column });
+        assert_eq!(sources.resolve(2), location(first, 1, 3));
+        assert_eq!(sources.resolve(3), location(first, 2, 1));
+        assert_eq!(sources.resolve(4), location(empty, 1, 1));
+        assert_eq!(sources.resolve(6), location(last, 1, 2));
+        assert_eq!(sources.resolve(7), None);
+        assert_eq!(SourceMap::new().resolve(0), None);
+    }
+
+    #[test]
+    fn offsets_inside_characters() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("a", "ä");
+        let location = Some(Location {
+            file,
+            line: 1,
+            column: 1,
+        });
+        assert_eq!(sources.resolve(1), location);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{C1Token, Identifiers, LineIndex, Token, TokenValue};
+use logos::Logos;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+/// The number of bytes the streaming lexer reads before it lexes them, unless configured otherwise
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// # Overview
+/// Lexer that reads its input from a [`BufRead`] instead of lexing one string slice, for inputs too
+/// large to hold in memory. It offers the same methods as [`C1Lexer`](crate::C1Lexer) and yields
+/// the same tokens, but does not keep trivia.
+///
+/// The input is read in chunks of whole lines. Only block comments can span several lines; a
+/// comment that is still open at the end of a chunk is lexed again once more lines were read.
+/// Lines and columns are computed per chunk and offset by the position of the chunk in the input.
+///
+/// Reading stops at the first I/O error, which includes invalid UTF-8. The lexer then behaves as
+/// if the input ended there, and [`error`](StreamLexer::error) returns the error.
+///
+/// # Examples
+/// ```
+/// use cb_3::{C1Token, StreamLexer};
+///
+/// let input = "void main() {\n  /* a\n     comment */ x = 4;\n}\n";
+/// let mut lexer = StreamLexer::new(input.as_bytes());
+/// assert_eq!(lexer.current_token(), Some(C1Token::KwVoid));
+///
+/// for _ in 0..5 {
+///     lexer.eat();
+/// }
+/// assert_eq!(lexer.current_text(), Some("x"));
+/// assert_eq!(lexer.current_line_number(), Some(3));
+/// assert_eq!(lexer.current_column(), Some(17));
+/// assert!(lexer.error().is_none());
+/// ```
+pub struct StreamLexer<R> {
+    reader: R,
+    chunk_size: usize,
+    identifiers: Identifiers,
+    /// Text that was read but not lexed yet. It always starts at the beginning of a line.
+    buffer: String,
+    /// The offset and line of the start of the buffer in the whole input
+    buffer_offset: usize,
+    buffer_line: usize,
+    /// The offset in the buffer up to which the tokens were already produced, when the buffer
+    /// starts with the line of an unterminated block comment
+    produced: usize,
+    /// Whether the buffer ends inside a block comment
+    open_comment: bool,
+    at_eof: bool,
+    error: Option<io::Error>,
+    tokens: VecDeque<Token>,
+    current_token: Option<Token>,
+    peek_token: Option<Token>,
+}
+
+impl<R: BufRead> StreamLexer<R> {
+    /// Initialize a new StreamLexer that reads from the given reader
+    pub fn new(reader: R) -> StreamLexer<R> {
+        Self::with_chunk_size(reader, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Initialize a new StreamLexer that reads at least `chunk_size` bytes, or up to the end of
+    /// the input, before lexing
+    pub fn with_chunk_size(reader: R, chunk_size: usize) -> StreamLexer<R> {
+        Self::with_options(reader, chunk_size, Identifiers::Ascii)
+    }
+
+    /// Initialize a new StreamLexer that reads chunks of the given size and accepts the given
+    /// identifiers
+    pub fn with_options(reader: R, chunk_size: usize, identifiers: Identifiers) -> StreamLexer<R> {
+        let mut lexer = StreamLexer {
+            reader,
+            chunk_size,
+            identifiers,
+            buffer: String::new(),
+            buffer_offset: 0,
+            buffer_line: 1,
+            produced: 0,
+            open_comment: false,
+            at_eof: false,
+            error: None,
+            tokens: VecDeque::new(),
+            current_token: None,
+            peek_token: None,
+        };
+        lexer.current_token = lexer.next_token();
+        lexer.peek_token = lexer.next_token();
+        lexer
+    }
+
+    /// Return the C1Token variant of the current token without consuming it
+    pub fn current_token(&self) -> Option<C1Token> {
+        self.current_token.as_ref().map(|token| token.kind)
+    }
+
+    /// Return the C1Token variant of the next token without consuming it
+    pub fn peek_token(&self) -> Option<C1Token> {
+        self.peek_token.as_ref().map(|token| token.kind)
+    }
+
+    /// Return the text of the current token
+    pub fn current_text(&self) -> Option<&str> {
+        self.current_token.as_ref().map(|token| token.text.as_str())
+    }
+
+    /// Return the text of the next token
+    pub fn peek_text(&self) -> Option<&str> {
+        self.peek_token.as_ref().map(|token| token.text.as_str())
+    }
+
+    /// Return the value of the current token if it is a literal
+    pub fn current_value(&self) -> Option<TokenValue> {
+        self.current_token.as_ref().and_then(|token| token.value)
+    }
+
+    /// Return the value of the next token if it is a literal
+    pub fn peek_value(&self) -> Option<TokenValue> {
+        self.peek_token.as_ref().and_then(|token| token.value)
+    }
+
+    /// Return the line number where the current token is located
+    pub fn current_line_number(&self) -> Option<usize> {
+        self.current_token.as_ref().map(|token| token.line)
+    }
+
+    /// Return the line number where the next token is located
+    pub fn peek_line_number(&self) -> Option<usize> {
+        self.peek_token.as_ref().map(|token| token.line)
+    }
+
+    /// Return the column where the current token starts, counted in characters from 1
+    pub fn current_column(&self) -> Option<usize> {
+        self.current_token.as_ref().map(|token| token.column)
+    }
+
+    /// Return the column where the next token starts, counted in characters from 1
+    pub fn peek_column(&self) -> Option<usize> {
+        self.peek_token.as_ref().map(|token| token.column)
+    }
+
+    /// Return the byte range of the current token in the input
+    pub fn current_span(&self) -> Option<Range<usize>> {
+        self.current_token.as_ref().map(|token| token.span.clone())
+    }
+
+    /// Return the byte range of the next token in the input
+    pub fn peek_span(&self) -> Option<Range<usize>> {
+        self.peek_token.as_ref().map(|token| token.span.clone())
+    }
+
+    /// Drop the current token and retrieve the next token from the input
+    pub fn eat(&mut self) {
+        self.next();
+    }
+
+    /// The I/O error that ended the input early, if any
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(token);
+            }
+            if self.at_eof && self.buffer.is_empty() {
+                return None;
+            }
+            self.read_chunk();
+            self.lex_buffer();
+        }
+    }
+
+    /// Read whole lines until the buffer holds a chunk. A buffer that ends inside a block comment
+    /// is at least doubled, so long comments are not lexed over and over.
+    fn read_chunk(&mut self) {
+        let target = if self.open_comment {
+            self.chunk_size.max(2 * self.buffer.len())
+        } else {
+            self.chunk_size
+        };
+        while !self.at_eof && self.buffer.len() < target.max(1) {
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => self.at_eof = true,
+                Ok(_) => {}
+                Err(error) => {
+                    self.error = Some(error);
+                    self.at_eof = true;
+                }
+            }
+        }
+    }
+
+    /// Turn the buffer into tokens. An unterminated block comment stays in the buffer together
+    /// with the start of its line, unless the input has ended.
+    fn lex_buffer(&mut self) {
+        let index = LineIndex::new(&self.buffer);
+        let mut lexer = C1Token::lexer_with_extras(&self.buffer, self.identifiers);
+        let mut open_comment = None;
+        while let Some(kind) = lexer.next() {
+            let span = lexer.span();
+            if kind == C1Token::Error && lexer.slice().starts_with("/*") && !self.at_eof {
+                open_comment = Some(span.start);
+                break;
+            }
+            if kind == C1Token::Linebreak || span.start < self.produced {
+                continue;
+            }
+            let (line, column) = index.line_col(span.start);
+            self.tokens.push_back(Token {
+                kind,
+                text: lexer.slice().to_string(),
+                value: TokenValue::decode(kind, lexer.slice()),
+                line: self.buffer_line + line - 1,
+                column,
+                span: self.buffer_offset + span.start..self.buffer_offset + span.end,
+            });
+        }
+        let consumed = match open_comment {
+            Some(start) => {
+                let line = index.line(start);
+                let line_start = index.line_start(line).expect("the line exists");
+                self.produced = start - line_start;
+                self.buffer_line += line - 1;
+                line_start
+            }
+            None => {
+                self.produced = 0;
+                self.buffer_line += index.line_count() - 1;
+                self.buffer.len()
+            }
+        };
+        self.open_comment = open_comment.is_some();
+        self.buffer_offset += consumed;
+        self.buffer.drain(..consumed);
+    }
+}
+
+/// Iterating moves the tokens out of the lexer, starting with the current one
+impl<R: BufRead> Iterator for StreamLexer<R> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let next = self.next_token();
+        let current = std::mem::replace(&mut self.peek_token, next);
+        std::mem::replace(&mut self.current_token, current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{C1Lexer, C1Token, Identifiers, StreamLexer};
+    use std::io::{self, BufReader, Read};
+
+    /// Check that both lexers produce the same tokens at the same positions
+    fn assert_same_tokens(text: &str, chunk_size: usize) {
+        let mut lexer = C1Lexer::new(text);
+        let mut stream = StreamLexer::with_chunk_size(text.as_bytes(), chunk_size);
+        loop {
+            assert_eq!(stream.current_token(), lexer.current_token());
+            assert_eq!(stream.current_text(), lexer.current_text());
+            assert_eq!(stream.current_line_number(), lexer.current_line_number());
+            assert_eq!(stream.current_column(), lexer.current_column());
+            assert_eq!(stream.current_span(), lexer.current_span());
+            assert_eq!(stream.current_value(), lexer.current_value());
+            assert_eq!(stream.peek_text(), lexer.peek_text());
+            if lexer.current_token().is_none() {
+                break;
+            }
+            lexer.eat();
+            stream.eat();
+        }
+    }
+
+    #[test]
+    fn same_as_borrowed_lexer() {
+        for file in ["beispiel.c-1", "comments.c-1", "features.c-1"] {
+            let text = std::fs::read_to_string(format!("tests/data/{}", file)).unwrap();
+            for chunk_size in [0, 1, 10, 100, super::DEFAULT_CHUNK_SIZE] {
+                assert_same_tokens(&text, chunk_size);
+                assert_same_tokens(&text.replace('\n', "\r\n"), chunk_size);
+            }
+        }
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let text = "größe = _x1 + straße2;\n/* ä\n */ π = 3.14;";
+        let unicode: Vec<_> = StreamLexer::with_options(text.as_bytes(), 1, Identifiers::Unicode)
+            .map(|token| (token.kind, token.text, token.column))
+            .collect();
+        assert_eq!(unicode[0], (C1Token::Identifier, "größe".to_string(), 1));
+        assert_eq!(unicode[4], (C1Token::Identifier, "straße2".to_string(), 15));
+        assert_eq!(unicode[6], (C1Token::Identifier, "π".to_string(), 5));
+        let ascii: Vec<_> = StreamLexer::new(text.as_bytes())
+            .map(|token| token.kind)
+            .collect();
+        assert_eq!(ascii[0], C1Token::Error);
+        assert_eq!(ascii[2], C1Token::Identifier);
+        assert_eq!(ascii.len(), unicode.len());
+    }
+
+    #[test]
+    fn comments_across_chunks() {
+        let text = "a /* one\ntwo\nthree */ b /* four\n\n*/\n  c /* five";
+        for chunk_size in [0, 3, 20, 1000] {
+            assert_same_tokens(text, chunk_size);
+        }
+        let tokens: Vec<_> = StreamLexer::with_chunk_size(text.as_bytes(), 1).collect();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[2].text, "c");
+        assert_eq!((tokens[2].line, tokens[2].column), (6, 3));
+        assert_eq!(tokens[3].text, "/* five");
+    }
+
+    #[test]
+    fn small_reads() {
+        // A reader that returns one byte at a time
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                let Some((&first, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                buffer[0] = first;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+        let text = "int f() {\n\treturn 1 /* * / */ + 2;\n}\n";
+        let reader = BufReader::with_capacity(1, Trickle(text.as_bytes()));
+        let texts: Vec<String> = StreamLexer::new(reader).map(|token| token.text).collect();
+        assert_eq!(
+            texts,
+            vec!["int", "f", "(", ")", "{", "return", "1", "+", "2", ";", "}"]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let input: &[u8] = b"a b\nc \xff d\ne";
+        let mut lexer = StreamLexer::new(input);
+        let mut texts = Vec::new();
+        while let Some(text) = lexer.current_text() {
+            texts.push(text.to_string());
+            lexer.eat();
+        }
+        assert_eq!(texts, vec!["a", "b"]);
+        assert_eq!(
+            lexer.error().map(io::Error::kind),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::json::JsonValue;
+use crate::{C1Token, FileId, StreamLexer, TokenValue, Trivia};
+use std::fmt;
+use std::ops::Range;
+
+/// # Overview
+/// A token that owns its text, as produced by [`StreamLexer`] and [`tokenize`]. The span holds byte
+/// offsets into the whole input.
+///
+/// With the `serde` feature, tokens implement `Serialize` and `Deserialize`. A slice of tokens is a
+/// [`TokenSource`], so a deserialized token stream can be parsed again.
+///
+/// # Examples
+/// ```
+/// use cb_3::{tokenize, C1Token};
+///
+/// let tokens = tokenize("int x;");
+/// assert_eq!(tokens[1].kind, C1Token::Identifier);
+/// assert_eq!(tokens[1].to_string(), r#"1:5 Identifier "x""#);
+/// assert_eq!(
+///     tokens[1].to_json().to_string(),
+///     r#"{"kind":"Identifier","text":"x","value":null,"line":1,"column":5,"span":{"start":4,"end":5}}"#
+/// );
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token {
+    pub kind: C1Token,
+    pub text: String,
+    /// The decoded value of a literal
+    pub value: Option<TokenValue>,
+    pub line: usize,
+    pub column: usize,
+    pub span: Range<usize>,
+}
+
+impl Token {
+    /// The token as JSON, in the same shape as its `serde` serialization
+    pub fn to_json(&self) -> JsonValue {
+        let value = match self.value {
+            None => JsonValue::Null,
+            Some(TokenValue::Int(value)) => JsonValue::object(vec![("Int", value.into())]),
+            Some(TokenValue::Float(value)) => JsonValue::object(vec![("Float", value.into())]),
+            Some(TokenValue::Bool(value)) => JsonValue::object(vec![("Bool", value.into())]),
+        };
+        JsonValue::object(vec![
+            ("kind", format!("{:?}", self.kind).into()),
+            ("text", self.text.as_str().into()),
+            ("value", value),
+            ("line", self.line.into()),
+            ("column", self.column.into()),
+            (
+                "span",
+                JsonValue::object(vec![
+                    ("start", self.span.start.into()),
+                    ("end", self.span.end.into()),
+                ]),
+            ),
+        ])
+    }
+}
+
+/// One token per line: its position, kind and quoted text
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} {:?} {:?}",
+            self.line, self.column, self.kind, self.text
+        )
+    }
+}
+
+/// Lex the whole text into owned tokens
+pub fn tokenize(text: &str) -> Vec<Token> {
+    StreamLexer::new(text.as_bytes()).collect()
+}
+
+/// # Overview
+/// The tokens [`C1Parser`](crate::C1Parser) reads. Besides [`C1Lexer`](crate::C1Lexer), a slice of
+/// [`Token`]s is a token source, and so is anything else that hands out tokens one at a time, such
+/// as a preprocessor or a test double. All methods return `None` at the end of the tokens.
+///
+/// # Examples
+/// ```
+/// use cb_3::{tokenize, C1Parser, Token, TokenSource};
+///
+/// let tokens = tokenize("void main() { x = 1; }");
+/// let mut source: &[Token] = &tokens;
+/// source.eat();
+/// assert_eq!(source.current_text(), Some("main"));
+/// assert_eq!(source.current_line_number(), Some(1));
+/// assert!(C1Parser::parse_source(source).is_err());
+/// assert!(C1Parser::parse_source(&tokens[..]).is_ok());
+/// ```
+pub trait TokenSource<'a> {
+    /// The kind of the current token
+    fn current_token(&self) -> Option<C1Token>;
+
+    fn current_text(&self) -> Option<&'a str>;
+
+    fn current_line_number(&self) -> Option<usize>;
+
+    /// The byte offsets of the current token in the input
+    fn current_span(&self) -> Option<Range<usize>>;
+
+    /// The file of a [`SourceMap`](crate::SourceMap) that the current token is from, if any
+    fn current_file(&self) -> Option<FileId> {
+        None
+    }
+
+    /// Whitespace and comments in front of the current token, or after the last token once the
+    /// end has been reached. Only needed to build a lossless syntax tree.
+    fn current_leading_trivia(&self) -> &[Trivia<'a>] {
+        &[]
+    }
+
+    /// The kind of the token after the current one
+    fn peek_token(&self) -> Option<C1Token>;
+
+    /// Move on to the next token
+    fn eat(&mut self);
+}
+
+impl<'a> TokenSource<'a> for &'a [Token] {
+    fn current_token(&self) -> Option<C1Token> {
+        self.first().map(|token| token.kind)
+    }
+
+    fn current_text(&self) -> Option<&'a str> {
+        self.first().map(|token| token.text.as_str())
+    }
+
+    fn current_line_number(&self) -> Option<usize> {
+        self.first().map(|token| token.line)
+    }
+
+    fn current_span(&self) -> Option<Range<usize>> {
+        self.first().map(|token| token.span.clone())
+    }
+
+    fn peek_token(&self) -> Option<C1Token> {
+        self.get(1).map(|token| token.kind)
+    }
+
+    fn eat(&mut self) {
+        if let Some((_, rest)) = self.split_first() {
+            *self = rest;
+        }
+    }
+}
@@ -0,0 +1,570 @@
+//! # Overview
+//! Static type checking of C(-1) programs, as needed by the code generators in
+//! [`codegen`](crate::codegen).
+//!
+//! Variables are not declared in C(-1). Every name that is assigned in a function is a local of
+//! that function and gets the type of the value of its first assignment in source order. Later
+//! assignments must have the same type, except that integers may be assigned to float variables.
+//! A variable must not be read before the first assignment to it.
+//!
+//! Arithmetic needs numbers and yields an integer if both operands are integers and a float
+//! otherwise. Comparisons yield booleans; booleans can only be compared for (in)equality. `&&`,
+//! `||` and conditions need booleans. Return values are converted between integers and floats
+//! like in C. Calls of `void` functions cannot be used as values. Integer literals must fit into 32
+//! bits.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::{Program, Type};
+//! use cb_3::typeck::check;
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("float half() { x = 1; x = x / 2.0; return x; }").unwrap();
+//! let error = check(Program::cast(&tree.root).unwrap()).unwrap_err();
+//! assert_eq!(error.message, "cannot assign float to int variable 'x'");
+//!
+//! let tree = C1Parser::parse_tree("float half() { x = 1.0; x = x / 2; return x; }").unwrap();
+//! let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+//! assert_eq!(checked.functions()[0].local("x").unwrap().ty, Type::Float);
+//! ```
+
+use crate::ast::{BinaryOp, Expr, FunctionDefinition, LiteralKind, Program, Statement, Type};
+use crate::cst::SyntaxNode;
+use crate::{FileId, TokenValue};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A local variable of a function
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub ty: Type,
+}
+
+/// The types of a function and its local variables
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub return_type: Type,
+    /// The local variables in order of their first assignment
+    pub locals: Vec<Variable>,
+}
+
+impl FunctionInfo {
+    pub fn local(&self, name: &str) -> Option<&Variable> {
+        self.locals.iter().find(|local| local.name == name)
+    }
+}
+
+/// A program that passed the type check, together with the types of all functions and variables
+#[derive(Debug, Clone)]
+pub struct CheckedProgram<'t> {
+    program: Program<'t>,
+    functions: Vec<FunctionInfo>,
+    /// The return types of all functions that can be called, including those of other files
+    /// that were checked together with this one
+    return_types: HashMap<&'t str, Type>,
+}
+
+impl<'t> CheckedProgram<'t> {
+    pub fn program(&self) -> Program<'t> {
+        self.program
+    }
+
+    /// The functions in order of their definition
+    pub fn functions(&self) -> &[FunctionInfo] {
+        &self.functions
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionInfo> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// The function definitions together with their types
+    pub fn definitions(&self) -> impl Iterator<Item = (FunctionDefinition<'t>, &FunctionInfo)> {
+        self.program.functions().zip(self.functions.iter())
+    }
+
+    /// The type of an expression in the body of the given function
+    pub fn expr_type(&self, function: &FunctionInfo, expr: Expr) -> Type {
+        match expr {
+            Expr::Assignment(assignment) => self.local_type(function, assignment.target()),
+            Expr::Binary(binary) => {
+                let lhs = self.expr_type(function, binary.lhs());
+                let rhs = self.expr_type(function, binary.rhs());
+                binary_type(binary.operator(), lhs, rhs).expect("operands were checked")
+            }
+            Expr::Unary(unary) => self.expr_type(function, unary.operand()),
+            Expr::Literal(literal) => literal_type(literal.kind()),
+            Expr::Name(name) => self.local_type(function, name.name()),
+            Expr::Call(call) => *self
+                .return_types
+                .get(call.name())
+                .expect("called functions were checked"),
+            Expr::Paren(paren) => self.expr_type(function, paren.inner()),
+        }
+    }
+
+    fn local_type(&self, function: &FunctionInfo, name: &str) -> Type {
+        function.local(name).expect("variables were checked").ty
+    }
+}
+
+/// An error in the types of a syntactically valid program. The file is only known when several
+/// files were checked with [`check_files`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeError {
+    pub message: String,
+    pub file: Option<FileId>,
+    pub line: usize,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}", self.message, self.line)
+    }
+}
+
+/// Check all functions of the program and infer the types of their variables
+pub fn check(program: Program) -> Result<CheckedProgram, TypeError> {
+    let mut checked = check_unit(&[(None, program)])?;
+    Ok(checked.remove(0))
+}
+
+/// Check the programs of several files as one unit: functions may call the functions of all files,
+/// and no two functions may have the same name, even in different files. Returns the checked
+/// programs in the order of the files.
+/// ```
+/// use cb_3::ast::Program;
+/// use cb_3::typeck::check_files;
+/// use cb_3::{C1Parser, SourceMap};
+///
+/// let mut sources = SourceMap::new();
+/// let main = sources.add("main.c-1", "void main() { printf(one()); }\n");
+/// let lib = sources.add("lib.c-1", "int one() { return 1; }\n");
+/// let trees = [main, lib].map(|file| (file, C1Parser::parse_file(&sources, file).unwrap()));
+/// let programs: Vec<_> = trees
+///     .iter()
+///     .map(|(file, tree)| (*file, Program::cast(&tree.root).unwrap()))
+///     .collect();
+/// assert_eq!(check_files(&programs).unwrap().len(), 2);
+///
+/// let error = check_files(&[programs[1], programs[1]]).unwrap_err();
+/// assert_eq!(error.message, "function 'one' is defined more than once");
+/// assert_eq!(error.file, Some(lib));
+/// ```
+pub fn check_files<'t>(
+    files: &[(FileId, Program<'t>)],
+) -> Result<Vec<CheckedProgram<'t>>, TypeError> {
+    let files: Vec<_> = files
+        .iter()
+        .map(|&(file, program)| (Some(file), program))
+        .collect();
+    check_unit(&files)
+}
+
+fn check_unit<'t>(
+    files: &[(Option<FileId>, Program<'t>)],
+) -> Result<Vec<CheckedProgram<'t>>, TypeError> {
+    let mut signatures = HashMap::new();
+    for &(file, program) in files {
+        for definition in program.functions() {
+            if signatures
+                .insert(definition.name(), definition.return_type())
+                .is_some()
+            {
+                return Err(TypeError {
+                    message: format!("function '{}' is defined more than once", definition.name()),
+                    file,
+                    line: definition.name_token().line,
+                });
+            }
+        }
+    }
+    files
+        .iter()
+        .map(|&(file, program)| {
+            let functions = program
+                .functions()
+                .map(|definition| {
+                    let mut checker = Checker {
+                        signatures: &signatures,
+                        return_type: definition.return_type(),
+                        locals: Vec::new(),
+                        file,
+                        line: definition.name_token().line,
+                    };
+                    for statement in definition.body().statements() {
+                        checker.statement(statement)?;
+                    }
+                    Ok(FunctionInfo {
+                        name: definition.name().to_string(),
+                        return_type: definition.return_type(),
+                        locals: checker.locals,
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(CheckedProgram {
+                program,
+                functions,
+                return_types: signatures.clone(),
+            })
+        })
+        .collect()
+}
+
+fn literal_type(kind: LiteralKind) -> Type {
+    match kind {
+        LiteralKind::Int => Type::Int,
+        LiteralKind::Float => Type::Float,
+        LiteralKind::Bool => Type::Bool,
+    }
+}
+
+/// Whether a value of type `from` can be stored where a `to` is expected
+pub fn is_assignable(from: Type, to: Type) -> bool {
+    from == to || (from == Type::Int && to == Type::Float)
+}
+
+/// Whether a value of type `from` can be returned from a function returning `to`
+pub fn is_returnable(from: Type, to: Type) -> bool {
+    from == to
+        || matches!(
+            (from, to),
+            (Type::Int, Type::Float) | (Type::Float, Type::Int)
+        )
+}
+
+fn binary_type(operator: BinaryOp, lhs: Type, rhs: Type) -> Result<Type, String> {
+    let numeric = |ty| matches!(ty, Type::Int | Type::Float);
+    let result = match operator {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+            if numeric(lhs) && numeric(rhs) =>
+        {
+            if lhs == Type::Int && rhs == Type::Int {
+                Type::Int
+            } else {
+                Type::Float
+            }
+        }
+        BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual
+            if numeric(lhs) && numeric(rhs) =>
+        {
+            Type::Bool
+        }
+        BinaryOp::Equal | BinaryOp::NotEqual
+            if (numeric(lhs) && numeric(rhs)) || (lhs == Type::Bool && rhs == Type::Bool) =>
+        {
+            Type::Bool
+        }
+        BinaryOp::And | BinaryOp::Or if lhs == Type::Bool && rhs == Type::Bool => Type::Bool,
+        _ => {
+            return Err(format!(
+                "operator '{}' cannot be applied to {} and {}",
+                operator_symbol(operator),
+                lhs,
+                rhs
+            ))
+        }
+    };
+    Ok(result)
+}
+
+fn operator_symbol(operator: BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Or => "||",
+        BinaryOp::And => "&&",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::Less => "<",
+        BinaryOp::Greater => ">",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::GreaterEqual => ">=",
+    }
+}
+
+struct Checker<'s> {
+    signatures: &'s HashMap<&'s str, Type>,
+    return_type: Type,
+    locals: Vec<Variable>,
+    file: Option<FileId>,
+    line: usize,
+}
+
+impl<'s> Checker<'s> {
+    fn error(&self, message: String) -> TypeError {
+        TypeError {
+            message,
+            file: self.file,
+            line: self.line,
+        }
+    }
+
+    fn set_line(&mut self, node: &SyntaxNode) {
+        if let Some(token) = node.first_token() {
+            self.line = token.line;
+        }
+    }
+
+    fn statement(&mut self, statement: Statement) -> Result<(), TypeError> {
+        self.set_line(statement.syntax());
+        match statement {
+            Statement::Block(block) => {
+                for statement in block.statements() {
+                    self.statement(statement)?;
+                }
+            }
+            Statement::If(if_statement) => {
+                let condition = self.expr(if_statement.condition())?;
+                if condition != Type::Bool {
+                    return Err(self.error(format!("condition must be bool, found {}", condition)));
+                }
+                self.statement(if_statement.then_branch())?;
+            }
+            Statement::Return(ret) => match (ret.value(), self.return_type) {
+                (None, Type::Void) => {}
+                (None, _) => return Err(self.error("missing return value".to_string())),
+                (Some(_), Type::Void) => {
+                    return Err(self.error("void function cannot return a value".to_string()))
+                }
+                (Some(value), return_type) => {
+                    let ty = self.expr(value)?;
+                    if !is_returnable(ty, return_type) {
+                        return Err(self.error(format!(
+                            "cannot return {} from a function returning {}",
+                            ty, return_type
+                        )));
+                    }
+                }
+            },
+            Statement::Printf(printf) => {
+                self.expr(printf.argument())?;
+            }
+            Statement::Assignment(assignment) => {
+                let ty = self.expr(assignment.value())?;
+                self.assign(assignment.target(), ty)?;
+            }
+            Statement::Call(call) => {
+                self.function(call.name())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&self, name: &str) -> Result<Type, TypeError> {
+        match self.signatures.get(name) {
+            Some(return_type) => Ok(*return_type),
+            None => Err(self.error(format!("function '{}' is not defined", name))),
+        }
+    }
+
+    fn assign(&mut self, name: &str, ty: Type) -> Result<Type, TypeError> {
+        match self.locals.iter().find(|local| local.name == name) {
+            Some(local) if is_assignable(ty, local.ty) => Ok(local.ty),
+            Some(local) => Err(self.error(format!(
+                "cannot assign {} to {} variable '{}'",
+                ty, local.ty, name
+            ))),
+            None => {
+                self.locals.push(Variable {
+                    name: name.to_string(),
+                    ty,
+                });
+                Ok(ty)
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Result<Type, TypeError> {
+        self.set_line(expr.syntax());
+        let ty = match expr {
+            Expr::Assignment(assignment) => {
+                let ty = self.expr(assignment.value())?;
+                self.assign(assignment.target(), ty)?
+            }
+            Expr::Binary(binary) => {
+                let lhs = self.expr(binary.lhs())?;
+                let rhs = self.expr(binary.rhs())?;
+                self.set_line(binary.syntax());
+                binary_type(binary.operator(), lhs, rhs).map_err(|message| self.error(message))?
+            }
+            Expr::Unary(unary) => {
+                let ty = self.expr(unary.operand())?;
+                if !matches!(ty, Type::Int | Type::Float) {
+                    return Err(self.error(format!("cannot negate {}", ty)));
+                }
+                ty
+            }
+            Expr::Literal(literal) => {
+                let valid = match literal.value() {
+                    TokenValue::Int(value) => i32::try_from(value).is_ok(),
+                    TokenValue::Float(_) | TokenValue::Bool(_) => true,
+                };
+                if !valid {
+                    return Err(self.error(format!("invalid literal {}", literal.text())));
+                }
+                literal_type(literal.kind())
+            }
+            Expr::Name(name) => match self.locals.iter().find(|local| local.name == name.name()) {
+                Some(local) => local.ty,
+                None => {
+                    return Err(self.error(format!(
+                        "variable '{}' is used before it is assigned",
+                        name.name()
+                    )))
+                }
+            },
+            Expr::Call(call) => match self.function(call.name())? {
+                Type::Void => {
+                    return Err(self.error(format!(
+                        "void function '{}' cannot be used as a value",
+                        call.name()
+                    )))
+                }
+                ty => ty,
+            },
+            Expr::Paren(paren) => self.expr(paren.inner())?,
+        };
+        Ok(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Program, Statement, Type};
+    use crate::typeck::{check, check_files, TypeError, Variable};
+    use crate::{C1Parser, SourceMap};
+
+    fn check_text(text: &str) -> Result<Vec<(String, Vec<Variable>)>, TypeError> {
+        let tree = C1Parser::parse_tree(text).unwrap();
+        let checked = check(Program::cast(&tree.root).unwrap())?;
+        Ok(checked
+            .functions()
+            .iter()
+            .map(|function| (function.name.clone(), function.locals.clone()))
+            .collect())
+    }
+
+    fn variable(name: &str, ty: Type) -> Variable {
+        Variable {
+            name: name.to_string(),
+            ty,
+        }
+    }
+
+    #[test]
+    fn check_example() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let functions = check_text(&text).unwrap();
+        assert_eq!(functions.len(), 3);
+        assert_eq!(
+            functions[1],
+            (
+                "blah".to_string(),
+                vec![variable("a", Type::Int), variable("b", Type::Int)]
+            )
+        );
+    }
+
+    #[test]
+    fn expression_types() {
+        let tree = C1Parser::parse_tree(
+            "float f() { x = 1; y = x < 2.5; printf(x * 2.0); printf(y == true); return x; }",
+        )
+        .unwrap();
+        let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+        let (definition, function) = checked.definitions().next().unwrap();
+        assert_eq!(
+            function.locals,
+            vec![variable("x", Type::Int), variable("y", Type::Bool)]
+        );
+        let types: Vec<Type> = definition
+            .body()
+            .statements()
+            .filter_map(|statement| match statement {
+                Statement::Printf(printf) => Some(checked.expr_type(function, printf.argument())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(types, vec![Type::Float, Type::Bool]);
+    }
+
+    #[test]
+    fn type_errors() {
+        let message = |text: &str| check_text(text).unwrap_err().message;
+        assert_eq!(
+            message("void f() { if (1) {} }"),
+            "condition must be bool, found int"
+        );
+        assert_eq!(
+            message("void f() { x = true + 1; }"),
+            "operator '+' cannot be applied to bool and int"
+        );
+        assert_eq!(
+            message("void f() { x = y; }"),
+            "variable 'y' is used before it is assigned"
+        );
+        assert_eq!(
+            message("int f() { return true; }"),
+            "cannot return bool from a function returning int"
+        );
+        assert_eq!(
+            message("void f() { x = f(); }"),
+            "void function 'f' cannot be used as a value"
+        );
+        assert_eq!(
+            message("void f() {}\nint f() { return 1; }"),
+            "function 'f' is defined more than once"
+        );
+        assert_eq!(
+            message("int f() { return 2147483648; }"),
+            "invalid literal 2147483648"
+        );
+        assert_eq!(check_text("void f() {\n\n g();\n}").unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn several_files() {
+        let mut sources = SourceMap::new();
+        let main = sources.add("main", "void main() {\n  printf(half() + one());\n}\n");
+        let half = sources.add("half", "float half() { return 0.5; }\n");
+        sources.add("one", "int one() { return 1; }\n");
+        let duplicate = sources.add("duplicate", "\nint half() { return 0; }\n");
+        let trees: Vec<_> = sources
+            .file_ids()
+            .map(|file| (file, C1Parser::parse_file(&sources, file).unwrap()))
+            .collect();
+        let programs: Vec<_> = trees
+            .iter()
+            .map(|(file, tree)| (*file, Program::cast(&tree.root).unwrap()))
+            .collect();
+
+        let checked = check_files(&programs[..3]).unwrap();
+        let (definition, function) = checked[0].definitions().next().unwrap();
+        let Some(Statement::Printf(printf)) = definition.body().statements().next() else {
+            panic!("main starts with printf");
+        };
+        assert_eq!(
+            checked[0].expr_type(function, printf.argument()),
+            Type::Float
+        );
+
+        assert_eq!(
+            check_files(&programs[..2]).unwrap_err(),
+            TypeError {
+                message: "function 'one' is not defined".to_string(),
+                file: Some(main),
+                line: 2,
+            }
+        );
+        let error = check_files(&programs).unwrap_err();
+        assert_eq!(error.message, "function 'half' is defined more than once");
+        assert_eq!((error.file, error.line), (Some(duplicate), 2));
+        let error = check_files(&[programs[3], programs[1]]).unwrap_err();
+        assert_eq!((error.file, error.line), (Some(half), 1));
+    }
+}
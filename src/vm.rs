@@ -0,0 +1,683 @@
+//! # Overview
+//! Virtual machine that executes the [`bytecode`](crate::bytecode) of a compiled program.
+//!
+//! Values are 32-bit integers with wrapping arithmetic, 64-bit floats and booleans. Arithmetic
+//! and comparisons on an integer and a float convert the integer to a float first; booleans can
+//! only be compared for (in)equality. Conditions must be booleans. `printf` prints its argument on
+//! a line of its own, floats with six decimal places like C's `%f`. Return values are converted
+//! to the return type of the function.
+//!
+//! Untrusted programs can be run safely with [`Limits`] on the number of executed instructions,
+//! the depth of the call stack and the wall-clock time. A run that hits one of them stops with
+//! [`RuntimeError::LimitExceeded`]. By default, only the call depth is limited.
+//!
+//! # Examples
+//! ```
+//! use cb_3::ast::Program;
+//! use cb_3::bytecode::compile;
+//! use cb_3::vm::{Value, Vm};
+//! use cb_3::C1Parser;
+//!
+//! let tree = C1Parser::parse_tree("int main() { x = 6; printf(x * 7); return x; }").unwrap();
+//! let module = compile(Program::cast(&tree.root).unwrap()).unwrap();
+//!
+//! let mut output = Vec::new();
+//! let result = Vm::new(&module).run("main", &mut output).unwrap();
+//! assert_eq!(result, Value::Int(6));
+//! assert_eq!(output, b"42\n");
+//! ```
+
+use crate::ast::Type;
+use crate::bytecode::{Instruction, Module};
+use std::fmt;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// How often the wall-clock time limit is checked, in executed instructions
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// The call depth that [`Limits::default`] allows
+pub const DEFAULT_CALL_DEPTH: usize = 10_000;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    /// The result of a void function
+    Void,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:.6}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Void => f.write_str("void"),
+        }
+    }
+}
+
+/// Resource limits of a single run. `None` means unlimited. The default only limits the call depth,
+/// to [`DEFAULT_CALL_DEPTH`], so that endless recursion stops with an error instead of using up
+/// all memory.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Limits {
+    /// Maximum number of executed instructions
+    pub steps: Option<u64>,
+    /// Maximum number of active function calls, including the entry function
+    pub call_depth: Option<usize>,
+    /// Maximum wall-clock time of the run
+    pub time: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            steps: None,
+            call_depth: Some(DEFAULT_CALL_DEPTH),
+            time: None,
+        }
+    }
+}
+
+/// A limit that was exceeded, with its configured value
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Limit {
+    Steps(u64),
+    CallDepth(usize),
+    Time(Duration),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps(steps) => write!(f, "step limit of {} instructions", steps),
+            Limit::CallDepth(depth) => write!(f, "call depth limit of {}", depth),
+            Limit::Time(time) => write!(f, "time limit of {:?}", time),
+        }
+    }
+}
+
+/// The place in the program where a runtime error occurred
+#[derive(Debug, PartialEq, Clone)]
+pub struct Location {
+    pub function: String,
+    /// Index of the instruction in the code of the function
+    pub position: usize,
+    pub line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "in {}() at line {}", self.function, self.line)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RuntimeError {
+    /// The requested entry function does not exist
+    UnknownFunction(String),
+    DivisionByZero(Location),
+    TypeMismatch {
+        message: String,
+        location: Location,
+    },
+    UninitializedVariable {
+        name: String,
+        location: Location,
+    },
+    /// A function with a return type reached its end without returning a value
+    MissingReturn(Location),
+    /// One of the configured [`Limits`] was hit
+    LimitExceeded {
+        limit: Limit,
+        location: Location,
+    },
+    /// Writing the output of `printf` failed
+    Output(String),
+}
+
+impl RuntimeError {
+    /// Where the error occurred, `None` if no code was running
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            RuntimeError::UnknownFunction(_) | RuntimeError::Output(_) => None,
+            RuntimeError::DivisionByZero(location)
+            | RuntimeError::TypeMismatch { location, .. }
+            | RuntimeError::UninitializedVariable { location, .. }
+            | RuntimeError::MissingReturn(location)
+            | RuntimeError::LimitExceeded { location, .. } => Some(location),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::UnknownFunction(name) => write!(f, "function '{}' is not defined", name),
+            RuntimeError::DivisionByZero(location) => write!(f, "division by zero {}", location),
+            RuntimeError::TypeMismatch { message, location } => {
+                write!(f, "{} {}", message, location)
+            }
+            RuntimeError::UninitializedVariable { name, location } => {
+                write!(
+                    f,
+                    "variable '{}' is read before it is assigned {}",
+                    name, location
+                )
+            }
+            RuntimeError::MissingReturn(location) => write!(f, "missing return value {}", location),
+            RuntimeError::LimitExceeded { limit, location } => {
+                write!(f, "{} exceeded {}", limit, location)
+            }
+            RuntimeError::Output(message) => write!(f, "cannot write output: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Activation record of a function call
+struct Frame {
+    function: usize,
+    pc: usize,
+    /// Index of the first local of the function in the locals stack
+    locals_base: usize,
+}
+
+/// Executes a compiled module
+pub struct Vm<'m> {
+    module: &'m Module,
+    limits: Limits,
+}
+
+impl<'m> Vm<'m> {
+    pub fn new(module: &'m Module) -> Vm<'m> {
+        Vm {
+            module,
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Vm<'m> {
+        self.limits = limits;
+        self
+    }
+
+    /// Stop after the given number of executed instructions
+    pub fn with_step_limit(mut self, steps: u64) -> Vm<'m> {
+        self.limits.steps = Some(steps);
+        self
+    }
+
+    /// Stop when a call would make more than the given number of calls active
+    pub fn with_call_depth_limit(mut self, depth: usize) -> Vm<'m> {
+        self.limits.call_depth = Some(depth);
+        self
+    }
+
+    /// Stop when the run takes longer than the given time
+    pub fn with_time_limit(mut self, time: Duration) -> Vm<'m> {
+        self.limits.time = Some(time);
+        self
+    }
+
+    /// Call the function with the given name and run until it returns. The output of `printf` is
+    /// written to `output`.
+    pub fn run(&self, entry: &str, output: &mut dyn Write) -> Result<Value, RuntimeError> {
+        let entry = self
+            .module
+            .function_index(entry)
+            .ok_or_else(|| RuntimeError::UnknownFunction(entry.to_string()))?;
+        let mut execution = Execution {
+            module: self.module,
+            frames: Vec::new(),
+            stack: Vec::new(),
+            locals: Vec::new(),
+            limits: self.limits,
+            steps: 0,
+            start: Instant::now(),
+        };
+        execution.call(entry)?;
+        execution.run(output)
+    }
+}
+
+/// State of a single run of the machine
+struct Execution<'m> {
+    module: &'m Module,
+    frames: Vec<Frame>,
+    stack: Vec<Value>,
+    locals: Vec<Option<Value>>,
+    limits: Limits,
+    steps: u64,
+    start: Instant,
+}
+
+impl<'m> Execution<'m> {
+    fn call(&mut self, function: usize) -> Result<(), RuntimeError> {
+        if let Some(depth) = self.limits.call_depth {
+            if self.frames.len() >= depth {
+                let location = match self.frames.last() {
+                    Some(_) => self.location(),
+                    // A limit of zero does not even allow to enter the entry function
+                    None => Location {
+                        function: self.module.functions[function].name.clone(),
+                        position: 0,
+                        line: self.module.functions[function].lines[0],
+                    },
+                };
+                return Err(RuntimeError::LimitExceeded {
+                    limit: Limit::CallDepth(depth),
+                    location,
+                });
+            }
+        }
+        let locals_base = self.locals.len();
+        let local_count = self.module.functions[function].locals.len();
+        self.locals.resize(locals_base + local_count, None);
+        self.frames.push(Frame {
+            function,
+            pc: 0,
+            locals_base,
+        });
+        Ok(())
+    }
+
+    fn limit_exceeded(&self, limit: Limit) -> RuntimeError {
+        RuntimeError::LimitExceeded {
+            limit,
+            location: self.location(),
+        }
+    }
+
+    /// Count an executed instruction and check the step and time limits
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        if let Some(steps) = self.limits.steps {
+            if self.steps >= steps {
+                return Err(self.limit_exceeded(Limit::Steps(steps)));
+            }
+        }
+        if let Some(time) = self.limits.time {
+            if self.steps.is_multiple_of(TIME_CHECK_INTERVAL) && self.start.elapsed() > time {
+                return Err(self.limit_exceeded(Limit::Time(time)));
+            }
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn location(&self) -> Location {
+        let frame = self.frames.last().expect("no active frame");
+        let function = &self.module.functions[frame.function];
+        // The program counter already points behind the current instruction
+        let position = frame.pc.saturating_sub(1);
+        Location {
+            function: function.name.clone(),
+            position,
+            line: function.lines[position],
+        }
+    }
+
+    fn type_mismatch(&self, message: String) -> RuntimeError {
+        RuntimeError::TypeMismatch {
+            message,
+            location: self.location(),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    fn run(&mut self, output: &mut dyn Write) -> Result<Value, RuntimeError> {
+        loop {
+            let frame = self.frames.last_mut().expect("no active frame");
+            let function = &self.module.functions[frame.function];
+            let instruction = function.code[frame.pc];
+            frame.pc += 1;
+            let locals_base = frame.locals_base;
+
+            self.step()?;
+
+            match instruction {
+                Instruction::PushInt(value) => self.stack.push(Value::Int(value)),
+                Instruction::PushFloat(value) => self.stack.push(Value::Float(value)),
+                Instruction::PushBool(value) => self.stack.push(Value::Bool(value)),
+                Instruction::Load(slot) => match self.locals[locals_base + slot as usize] {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        let frame = self.frames.last().expect("no active frame");
+                        let name =
+                            self.module.functions[frame.function].locals[slot as usize].clone();
+                        return Err(RuntimeError::UninitializedVariable {
+                            name,
+                            location: self.location(),
+                        });
+                    }
+                },
+                Instruction::Store(slot) => {
+                    let value = self.pop();
+                    self.locals[locals_base + slot as usize] = Some(value);
+                }
+                Instruction::Dup => {
+                    let value = *self.stack.last().expect("operand stack underflow");
+                    self.stack.push(value);
+                }
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Neg => {
+                    let value = match self.pop() {
+                        Value::Int(value) => Value::Int(value.wrapping_neg()),
+                        Value::Float(value) => Value::Float(-value),
+                        other => return Err(self.type_mismatch(format!("cannot negate {}", other))),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Equal
+                | Instruction::NotEqual
+                | Instruction::Less
+                | Instruction::LessEqual
+                | Instruction::Greater
+                | Instruction::GreaterEqual => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = self.binary(instruction, lhs, rhs)?;
+                    self.stack.push(value);
+                }
+                Instruction::Jump(target) => self.jump(target),
+                Instruction::JumpIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.jump(target),
+                    other => {
+                        return Err(
+                            self.type_mismatch(format!("condition {} is not a boolean", other))
+                        )
+                    }
+                },
+                Instruction::Call(callee) => self.call(callee as usize)?,
+                Instruction::Return => {
+                    let value = self.pop();
+                    let value = self.convert_return(value)?;
+                    if let Some(result) = self.ret(value) {
+                        return Ok(result);
+                    }
+                }
+                Instruction::ReturnVoid => {
+                    if function.return_type != Type::Void {
+                        return Err(RuntimeError::MissingReturn(self.location()));
+                    }
+                    if let Some(result) = self.ret(Value::Void) {
+                        return Ok(result);
+                    }
+                }
+                Instruction::Print => {
+                    let value = self.pop();
+                    writeln!(output, "{}", value)
+                        .map_err(|error| RuntimeError::Output(error.to_string()))?;
+                }
+            }
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().expect("no active frame").pc = target as usize;
+    }
+
+    /// Leave the current function. Returns the result of the program once the entry function
+    /// returns.
+    fn ret(&mut self, value: Value) -> Option<Value> {
+        let frame = self.frames.pop().expect("no active frame");
+        self.locals.truncate(frame.locals_base);
+        if self.frames.is_empty() {
+            return Some(value);
+        }
+        if value != Value::Void {
+            self.stack.push(value);
+        }
+        None
+    }
+
+    fn convert_return(&self, value: Value) -> Result<Value, RuntimeError> {
+        let frame = self.frames.last().expect("no active frame");
+        let return_type = self.module.functions[frame.function].return_type;
+        match (return_type, value) {
+            (Type::Int, Value::Int(_))
+            | (Type::Float, Value::Float(_))
+            | (Type::Bool, Value::Bool(_)) => Ok(value),
+            (Type::Int, Value::Float(value)) => Ok(Value::Int(value as i32)),
+            (Type::Float, Value::Int(value)) => Ok(Value::Float(value as f64)),
+            _ => Err(self.type_mismatch(format!(
+                "cannot return {} from a {} function",
+                value, return_type
+            ))),
+        }
+    }
+
+    fn binary(
+        &self,
+        instruction: Instruction,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<Value, RuntimeError> {
+        use Instruction::*;
+        let value = match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => match instruction {
+                Add => Value::Int(lhs.wrapping_add(rhs)),
+                Sub => Value::Int(lhs.wrapping_sub(rhs)),
+                Mul => Value::Int(lhs.wrapping_mul(rhs)),
+                Div if rhs == 0 => return Err(RuntimeError::DivisionByZero(self.location())),
+                Div => Value::Int(lhs.wrapping_div(rhs)),
+                _ => Value::Bool(compare(instruction, lhs.cmp(&rhs))),
+            },
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                let (lhs, rhs) = (as_float(lhs), as_float(rhs));
+                match instruction {
+                    Add => Value::Float(lhs + rhs),
+                    Sub => Value::Float(lhs - rhs),
+                    Mul => Value::Float(lhs * rhs),
+                    Div => Value::Float(lhs / rhs),
+                    _ => match lhs.partial_cmp(&rhs) {
+                        Some(ordering) => Value::Bool(compare(instruction, ordering)),
+                        // Comparisons with NaN are false, except for !=
+                        None => Value::Bool(instruction == NotEqual),
+                    },
+                }
+            }
+            (Value::Bool(lhs), Value::Bool(rhs)) if matches!(instruction, Equal | NotEqual) => {
+                Value::Bool((lhs == rhs) == (instruction == Equal))
+            }
+            _ => {
+                return Err(self.type_mismatch(format!(
+                    "'{}' cannot be applied to {} and {}",
+                    instruction, lhs, rhs
+                )))
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn as_float(value: Value) -> f64 {
+    match value {
+        Value::Int(value) => value as f64,
+        Value::Float(value) => value,
+        _ => unreachable!("{} is not a number", value),
+    }
+}
+
+fn compare(instruction: Instruction, ordering: std::cmp::Ordering) -> bool {
+    use std::cmp::Ordering::*;
+    match instruction {
+        Instruction::Equal => ordering == Equal,
+        Instruction::NotEqual => ordering != Equal,
+        Instruction::Less => ordering == Less,
+        Instruction::LessEqual => ordering != Greater,
+        Instruction::Greater => ordering == Greater,
+        Instruction::GreaterEqual => ordering != Less,
+        _ => unreachable!("{:?} is not a comparison", instruction),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::bytecode::compile;
+    use crate::vm::{Limit, Limits, RuntimeError, Value, Vm, DEFAULT_CALL_DEPTH};
+    use crate::C1Parser;
+    use std::time::Duration;
+
+    fn run(text: &str) -> (Result<Value, RuntimeError>, String) {
+        let tree = C1Parser::parse_tree(text).unwrap();
+        let module = compile(Program::cast(&tree.root).unwrap()).unwrap();
+        let mut output = Vec::new();
+        let result = Vm::new(&module).run("main", &mut output);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn run_example() {
+        let text = std::fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+        let (result, output) = run(&text);
+        assert_eq!(result, Ok(Value::Void));
+        assert_eq!(output, "3\n17\n3.141590\n");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (_, output) = run("void main() {\n\
+             printf(7 / 2);\n\
+             printf(7 / 2.0);\n\
+             printf(-3 * 4 + 1);\n\
+             printf(2147483647 + 1);\n\
+             printf(1 < 2.5);\n\
+             printf(true == false);\n\
+             x = y = 3;\n\
+             printf(x + y);\n\
+             }");
+        assert_eq!(output, "3\n3.500000\n-11\n-2147483648\ntrue\nfalse\n6\n");
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let (_, output) = run("bool loud() { printf(1); return true; }\n\
+             void main() {\n\
+             printf(false && loud());\n\
+             printf(true || loud());\n\
+             printf(true && loud());\n\
+             printf(false || false);\n\
+             }");
+        assert_eq!(output, "false\ntrue\n1\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn return_values_are_converted() {
+        let (result, _) = run("int main() { return 2.9; }");
+        assert_eq!(result, Ok(Value::Int(2)));
+        let (result, _) = run("float main() { return 2; }");
+        assert_eq!(result, Ok(Value::Float(2.0)));
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let (result, _) = run("void main() {\n x = 0;\n printf(1 / x);\n}");
+        match result {
+            Err(RuntimeError::DivisionByZero(location)) => {
+                assert_eq!((location.function.as_str(), location.line), ("main", 3))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        let (result, _) = run("void main() { if (false) x = 1; printf(x); }");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::UninitializedVariable { .. })
+        ));
+        let (result, _) = run("void main() { if (1) {} }");
+        assert!(matches!(result, Err(RuntimeError::TypeMismatch { .. })));
+        let (result, _) = run("int main() { if (false) return 1; }");
+        assert!(matches!(result, Err(RuntimeError::MissingReturn(_))));
+    }
+
+    #[test]
+    fn limits() {
+        let tree = C1Parser::parse_tree("int main() {\n return main();\n}").unwrap();
+        let module = compile(Program::cast(&tree.root).unwrap()).unwrap();
+        let run = |vm: Vm| vm.run("main", &mut std::io::sink());
+
+        match run(Vm::new(&module).with_step_limit(1000)) {
+            Err(RuntimeError::LimitExceeded { limit, location }) => {
+                assert_eq!(limit, Limit::Steps(1000));
+                assert_eq!(location.function, "main");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        match run(Vm::new(&module).with_call_depth_limit(64)) {
+            Err(RuntimeError::LimitExceeded { limit, location }) => {
+                assert_eq!(limit, Limit::CallDepth(64));
+                assert_eq!((location.function.as_str(), location.line), ("main", 2));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let tree = C1Parser::parse_tree("void main() { x = true; if (x) main(); }").unwrap();
+        let module = compile(Program::cast(&tree.root).unwrap()).unwrap();
+        let limits = Limits {
+            steps: Some(100),
+            call_depth: Some(1000),
+            time: Some(Duration::from_secs(60)),
+        };
+        assert!(matches!(
+            run(Vm::new(&module).with_limits(limits)),
+            Err(RuntimeError::LimitExceeded {
+                limit: Limit::Steps(100),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn endless_recursion_is_limited_by_default() {
+        let (result, _) = run("void main() { printf(f()); }\nint f() { return f(); }");
+        match result {
+            Err(RuntimeError::LimitExceeded { limit, location }) => {
+                assert_eq!(limit, Limit::CallDepth(DEFAULT_CALL_DEPTH));
+                assert_eq!((location.function.as_str(), location.line), ("f", 2));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn time_limit() {
+        // Every function calls the next one twice, which takes 2^40 calls
+        let mut text = String::from("void main() { f0(); }\n");
+        for i in 0..40 {
+            text.push_str(&format!(
+                "void f{}() {{ f{}(); f{}(); }}\n",
+                i,
+                i + 1,
+                i + 1
+            ));
+        }
+        text.push_str("void f40() {}");
+        let tree = C1Parser::parse_tree(&text).unwrap();
+        let module = compile(Program::cast(&tree.root).unwrap()).unwrap();
+        let result = Vm::new(&module)
+            .with_time_limit(Duration::from_millis(50))
+            .run("main", &mut std::io::sink());
+        assert!(matches!(
+            result,
+            Err(RuntimeError::LimitExceeded {
+                limit: Limit::Time(_),
+                ..
+            })
+        ));
+    }
+}
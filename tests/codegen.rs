@@ -0,0 +1,85 @@
+mod common;
+
+use cb_3::ast::Program;
+use cb_3::codegen::{c, llvm, wasm, x86_64};
+use cb_3::typeck::{check, CheckedProgram};
+use cb_3::C1Parser;
+use common::assert_snapshot;
+use std::fs;
+
+fn with_checked(file: &str, f: impl FnOnce(&CheckedProgram)) {
+    let text = fs::read_to_string(format!("tests/data/{}", file)).unwrap();
+    let tree = C1Parser::parse_tree(&text).unwrap();
+    let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+    f(&checked)
+}
+
+#[test]
+fn c_example() {
+    with_checked("beispiel.c-1", |checked| {
+        assert_snapshot("beispiel.c", &c::emit(checked))
+    });
+}
+
+#[test]
+fn c_features() {
+    with_checked("features.c-1", |checked| {
+        assert_snapshot("features.c", &c::emit(checked))
+    });
+}
+
+#[test]
+fn llvm_example() {
+    with_checked("beispiel.c-1", |checked| {
+        assert_snapshot("beispiel.ll", &llvm::emit(checked))
+    });
+}
+
+#[test]
+fn llvm_features() {
+    with_checked("features.c-1", |checked| {
+        assert_snapshot("features.ll", &llvm::emit(checked))
+    });
+}
+
+#[test]
+fn x86_64_example() {
+    with_checked("beispiel.c-1", |checked| {
+        assert_snapshot("beispiel.s", &x86_64::emit(checked))
+    });
+}
+
+#[test]
+fn x86_64_features() {
+    with_checked("features.c-1", |checked| {
+        assert_snapshot("features.s", &x86_64::emit(checked))
+    });
+}
+
+#[test]
+fn wasm_example() {
+    with_checked("beispiel.c-1", |checked| {
+        assert_snapshot("beispiel.wat", &wasm::emit(checked))
+    });
+}
+
+#[test]
+fn wasm_features() {
+    with_checked("features.c-1", |checked| {
+        assert_snapshot("features.wat", &wasm::emit(checked))
+    });
+}
+
+#[test]
+fn names_with_underscores() {
+    let text = "int print_i32() { double = 1; double_ = 2; return double + double_; }\n\
+                void main() { printf(print_i32()); }";
+    let tree = C1Parser::parse_tree(text).unwrap();
+    let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+    let source = c::emit(&checked);
+    assert!(source.contains("c1_double = 1;"), "{}", source);
+    assert!(source.contains("c1_double_ = 2;"), "{}", source);
+    let module = wasm::emit(&checked);
+    assert!(module.contains("(func $print_i32 (export"), "{}", module);
+    assert!(module.contains("call $env.print_i32"), "{}", module);
+}
@@ -0,0 +1,17 @@
+use std::fs;
+
+/// Compare the output with the snapshot file in `tests/snapshots`. Setting the environment
+/// variable `UPDATE_SNAPSHOTS` writes the output to the snapshot instead.
+pub fn assert_snapshot(name: &str, output: &str) {
+    let path = format!("tests/snapshots/{}", name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, output).unwrap();
+    }
+    let expected = fs::read_to_string(&path).unwrap();
+    assert!(
+        expected == output,
+        "output differs from {}:\n{}",
+        path,
+        output
+    );
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use cb_3::ast::Program;
+use cb_3::ir::dataflow::{solve, AvailableExpressions, Expression, Liveness, ReachingDefinitions};
+use cb_3::ir::{self, BlockId, Dominators, Function, Module, UnaryOp, Var};
+use cb_3::typeck::check;
+use cb_3::C1Parser;
+use common::assert_snapshot;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+
+fn lower(file: &str) -> Module {
+    let text = fs::read_to_string(format!("tests/data/{}", file)).unwrap();
+    let tree = C1Parser::parse_tree(&text).unwrap();
+    let checked = check(Program::cast(&tree.root).unwrap()).unwrap();
+    ir::lower(&checked)
+}
+
+fn ssa(module: &Module) -> String {
+    let functions: Vec<String> = module
+        .functions
+        .iter()
+        .map(|function| ir::to_ssa(function).to_string())
+        .collect();
+    functions.join("\n")
+}
+
+fn vars(function: &Function, vars: &BTreeSet<Var>) -> String {
+    let names: Vec<String> = vars.iter().map(|&var| function.var_text(var)).collect();
+    format!("{{{}}}", names.join(", "))
+}
+
+/// The dominator tree and the facts of all analyses at the start and end of every block
+fn analyses(module: &Module) -> String {
+    let mut output = String::new();
+    for function in &module.functions {
+        writeln!(output, "function {}", function.name).unwrap();
+        let dominators = Dominators::compute(function);
+        let frontiers = dominators.frontiers(function);
+        let liveness = solve(&Liveness, function);
+        let reaching = solve(&ReachingDefinitions, function);
+        let available = AvailableExpressions::new(function);
+        let available_results = solve(&available, function);
+        let expressions = |facts: &BTreeSet<usize>| {
+            let expressions: Vec<String> = facts
+                .iter()
+                .map(|&index| match available.expressions[index] {
+                    Expression::Unary(UnaryOp::Neg, operand) => {
+                        format!("-{}", function.operand_text(operand))
+                    }
+                    Expression::Unary(UnaryOp::Convert, operand) => {
+                        format!("convert({})", function.operand_text(operand))
+                    }
+                    Expression::Binary(op, lhs, rhs) => format!(
+                        "{} {} {}",
+                        function.operand_text(lhs),
+                        op.symbol(),
+                        function.operand_text(rhs)
+                    ),
+                })
+                .collect();
+            format!("{{{}}}", expressions.join(", "))
+        };
+        for block in function.block_ids() {
+            let index = block.0 as usize;
+            let idom = dominators
+                .immediate_dominator(block)
+                .map_or("-".to_string(), |idom| idom.to_string());
+            let frontier: Vec<String> = frontiers[index].iter().map(BlockId::to_string).collect();
+            let definitions: Vec<String> = reaching.block_entry[index]
+                .iter()
+                .map(|location| format!("{}.{}", location.block, location.index))
+                .collect();
+            writeln!(
+                output,
+                "  {}: idom {}, frontier [{}]",
+                block,
+                idom,
+                frontier.join(", ")
+            )
+            .unwrap();
+            writeln!(
+                output,
+                "    live in {}, live out {}",
+                vars(function, &liveness.block_entry[index]),
+                vars(function, &liveness.block_exit[index])
+            )
+            .unwrap();
+            writeln!(output, "    reaching [{}]", definitions.join(", ")).unwrap();
+            writeln!(
+                output,
+                "    available in {}, available out {}",
+                expressions(&available_results.block_entry[index]),
+                expressions(&available_results.block_exit[index])
+            )
+            .unwrap();
+        }
+    }
+    output
+}
+
+#[test]
+fn ir_example() {
+    let module = lower("beispiel.c-1");
+    assert_snapshot("beispiel.ir", &module.to_string());
+    assert_snapshot("beispiel.dot", &module.to_dot());
+}
+
+#[test]
+fn ir_features() {
+    assert_snapshot("features.ir", &lower("features.c-1").to_string())
+}
+
+#[test]
+fn ssa_nested_ifs() {
+    let module = lower("nested.c-1");
+    assert_snapshot("nested.ir", &module.to_string());
+    assert_snapshot("nested.ssa", &ssa(&module));
+    assert_snapshot("features.ssa", &ssa(&lower("features.c-1")));
+}
+
+#[test]
+fn analyses_nested_ifs() {
+    assert_snapshot("nested.analyses", &analyses(&lower("nested.c-1")));
+    assert_snapshot("beispiel.analyses", &analyses(&lower("beispiel.c-1")));
+}
+
+#[test]
+fn constant_folding() {
+    for (file, snapshot) in [
+        ("beispiel.c-1", "beispiel.folded"),
+        ("features.c-1", "features.folded"),
+    ] {
+        let mut module = lower(file);
+        for function in &mut module.functions {
+            ir::fold_constants(function);
+        }
+        assert_snapshot(snapshot, &module.to_string());
+    }
+}
+
+#[test]
+fn dead_code() {
+    let mut module = lower("nested.c-1");
+    let warnings: Vec<String> = module
+        .functions
+        .iter()
+        .flat_map(ir::dead_code_warnings)
+        .map(|warning| warning.to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        vec!["value assigned to 'z' is never read at line 10"]
+    );
+    for function in &mut module.functions {
+        ir::fold_constants(function);
+        ir::remove_dead_code(function);
+    }
+    assert_snapshot("nested.optimized", &module.to_string());
+}
+
+#[test]
+fn call_graph_and_inlining() {
+    let mut module = lower("beispiel.c-1");
+    let graph = ir::CallGraph::new(&module);
+    assert_snapshot("beispiel.calls.dot", &graph.to_dot());
+    assert!(graph.unreachable_from_main().is_empty());
+
+    assert_eq!(ir::inline_calls(&mut module, ir::DEFAULT_INLINE_SIZE), 5);
+    for function in &mut module.functions {
+        ir::fold_constants(function);
+        ir::remove_dead_code(function);
+    }
+    assert_snapshot("beispiel.inlined", &module.to_string());
+}
+
+#[test]
+fn register_allocation() {
+    let module = lower("nested.c-1");
+    for (snapshot, registers) in [
+        ("nested.regalloc", ir::RegisterFile::x86_64()),
+        (
+            "nested.spilled",
+            ir::RegisterFile::new(&["r0", "r1"], &["f0"]),
+        ),
+    ] {
+        let mut output = String::new();
+        for function in &module.functions {
+            let allocation = ir::allocate_registers(function, &registers);
+            ir::verify(function, &allocation, &registers).unwrap();
+            output.push_str(&allocation.listing(function, &registers));
+        }
+        assert_snapshot(snapshot, &output);
+    }
+}
@@ -0,0 +1,326 @@
+use cb_3::json::JsonValue;
+use cb_3::lsp::{self, read_message, write_message};
+use std::fs;
+use std::io::{BufReader, Cursor, Write};
+use std::process::{Command, Stdio};
+
+const URI: &str = "file:///beispiel.c-1";
+
+/// Send the given messages to a fresh `c1-lsp` process and collect everything it answers
+fn run_session(messages: &[JsonValue]) -> (Vec<JsonValue>, Option<i32>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_c1-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages {
+        write_message(&mut stdin, message).unwrap();
+    }
+    stdin.flush().unwrap();
+    drop(stdin);
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut replies = Vec::new();
+    while let Some(reply) = read_message(&mut stdout).unwrap() {
+        replies.push(reply);
+    }
+    (replies, child.wait().unwrap().code())
+}
+
+fn request(id: usize, method: &str, params: JsonValue) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+fn notification(method: &str, params: JsonValue) -> JsonValue {
+    JsonValue::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+fn did_open(text: &str) -> JsonValue {
+    notification(
+        "textDocument/didOpen",
+        JsonValue::object(vec![(
+            "textDocument",
+            JsonValue::object(vec![
+                ("uri", URI.into()),
+                ("languageId", "c-1".into()),
+                ("version", 1usize.into()),
+                ("text", text.into()),
+            ]),
+        )]),
+    )
+}
+
+fn empty() -> JsonValue {
+    JsonValue::Object(Vec::new())
+}
+
+fn at(line: usize, character: usize) -> JsonValue {
+    JsonValue::object(vec![
+        ("textDocument", JsonValue::object(vec![("uri", URI.into())])),
+        (
+            "position",
+            JsonValue::object(vec![("line", line.into()), ("character", character.into())]),
+        ),
+        (
+            "context",
+            JsonValue::object(vec![("includeDeclaration", true.into())]),
+        ),
+    ])
+}
+
+fn shutdown_and_exit(id: usize) -> [JsonValue; 2] {
+    [
+        request(id, "shutdown", JsonValue::Null),
+        notification("exit", JsonValue::Null),
+    ]
+}
+
+fn result(replies: &[JsonValue], id: i64) -> &JsonValue {
+    replies
+        .iter()
+        .find(|reply| reply.get("id").and_then(JsonValue::as_i64) == Some(id))
+        .and_then(|reply| reply.get("result"))
+        .unwrap()
+}
+
+fn start_line(location: &JsonValue) -> Option<i64> {
+    location
+        .pointer(&["range", "start", "line"])
+        .and_then(JsonValue::as_i64)
+}
+
+#[test]
+fn navigation_in_example() {
+    let text = fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+    let mut messages = vec![
+        request(
+            1,
+            "initialize",
+            JsonValue::object(vec![("capabilities", empty())]),
+        ),
+        notification("initialized", empty()),
+        did_open(&text),
+        // the call `blub()` in line 13 of the file
+        request(2, "textDocument/definition", at(12, 10)),
+        // the definition of `blub`
+        request(3, "textDocument/references", at(0, 5)),
+        request(4, "textDocument/hover", at(28, 9)),
+        request(
+            5,
+            "textDocument/documentSymbol",
+            JsonValue::object(vec![(
+                "textDocument",
+                JsonValue::object(vec![("uri", URI.into())]),
+            )]),
+        ),
+    ];
+    messages.extend(shutdown_and_exit(6));
+    let (replies, exit_code) = run_session(&messages);
+    assert_eq!(exit_code, Some(0));
+
+    let capabilities = result(&replies, 1).get("capabilities").unwrap();
+    assert_eq!(
+        capabilities.get("hoverProvider"),
+        Some(&JsonValue::Bool(true))
+    );
+
+    let diagnostics = replies
+        .iter()
+        .find(|reply| {
+            reply.get("method").and_then(JsonValue::as_str)
+                == Some("textDocument/publishDiagnostics")
+        })
+        .and_then(|reply| reply.pointer(&["params", "diagnostics"]))
+        .and_then(JsonValue::as_array)
+        .unwrap();
+    assert!(diagnostics.is_empty());
+
+    let definition = result(&replies, 2);
+    assert_eq!(start_line(definition), Some(0));
+    assert_eq!(
+        definition
+            .pointer(&["range", "start", "character"])
+            .and_then(JsonValue::as_i64),
+        Some(4)
+    );
+
+    let references = result(&replies, 3).as_array().unwrap();
+    let lines: Vec<Option<i64>> = references.iter().map(start_line).collect();
+    assert_eq!(
+        lines,
+        vec![Some(0), Some(12), Some(13), Some(14), Some(14), Some(27)]
+    );
+
+    let hover = result(&replies, 4);
+    assert_eq!(
+        hover
+            .pointer(&["contents", "value"])
+            .and_then(JsonValue::as_str),
+        Some("```c\nfloat blah()\n```")
+    );
+
+    let symbols = result(&replies, 5).as_array().unwrap();
+    let names: Vec<&str> = symbols
+        .iter()
+        .filter_map(|symbol| symbol.get("name").and_then(JsonValue::as_str))
+        .collect();
+    assert_eq!(names, vec!["blub", "blah", "main"]);
+    assert_eq!(start_line(&symbols[2]), Some(20));
+    assert_eq!(
+        symbols[2]
+            .pointer(&["range", "end", "line"])
+            .and_then(JsonValue::as_i64),
+        Some(29)
+    );
+}
+
+#[test]
+fn diagnostics_follow_changes() {
+    let change = |text: &str| {
+        notification(
+            "textDocument/didChange",
+            JsonValue::object(vec![
+                (
+                    "textDocument",
+                    JsonValue::object(vec![("uri", URI.into()), ("version", 2usize.into())]),
+                ),
+                (
+                    "contentChanges",
+                    JsonValue::Array(vec![JsonValue::object(vec![("text", text.into())])]),
+                ),
+            ]),
+        )
+    };
+    let mut messages = vec![
+        did_open("void main() {\n  x = 1\n}"),
+        change("void main() {\n  x = 1;\n}"),
+        change("void main() {\n  x = "),
+    ];
+    messages.extend(shutdown_and_exit(1));
+    let (replies, _) = run_session(&messages);
+
+    let published: Vec<&[JsonValue]> = replies
+        .iter()
+        .filter_map(|reply| reply.pointer(&["params", "diagnostics"]))
+        .filter_map(JsonValue::as_array)
+        .collect();
+    assert_eq!(published.len(), 3);
+
+    // the missing semicolon is reported at the closing brace
+    assert_eq!(published[0].len(), 1);
+    let range = published[0][0].get("range").unwrap();
+    assert_eq!(
+        range
+            .pointer(&["start", "line"])
+            .and_then(JsonValue::as_i64),
+        Some(2)
+    );
+    assert_eq!(
+        range
+            .pointer(&["start", "character"])
+            .and_then(JsonValue::as_i64),
+        Some(0)
+    );
+
+    assert!(published[1].is_empty());
+
+    // running out of text is reported at the end of the document
+    let range = published[2][0].get("range").unwrap();
+    assert_eq!(
+        range
+            .pointer(&["start", "line"])
+            .and_then(JsonValue::as_i64),
+        Some(1)
+    );
+    assert_eq!(
+        range
+            .pointer(&["start", "character"])
+            .and_then(JsonValue::as_i64),
+        Some(6)
+    );
+}
+
+#[test]
+fn exit_without_shutdown_fails() {
+    let messages = [
+        request(1, "textDocument/unknown", JsonValue::Null),
+        notification("exit", JsonValue::Null),
+    ];
+    let (replies, exit_code) = run_session(&messages);
+    assert_eq!(
+        replies[0]
+            .pointer(&["error", "code"])
+            .and_then(JsonValue::as_i64),
+        Some(-32601)
+    );
+    assert_eq!(exit_code, Some(1));
+}
+
+#[test]
+fn malformed_messages_are_answered() {
+    let mut input = b"Content-Length: 5\r\n\r\n{bad}".to_vec();
+    for message in shutdown_and_exit(1) {
+        write_message(&mut input, &message).unwrap();
+    }
+    let mut output = Vec::new();
+    let exit_code = lsp::run(Cursor::new(input), &mut output).unwrap();
+    assert_eq!(exit_code, 0);
+
+    let mut output = Cursor::new(output);
+    let error = read_message(&mut output).unwrap().unwrap();
+    assert_eq!(error.get("id"), Some(&JsonValue::Null));
+    assert_eq!(
+        error
+            .pointer(&["error", "code"])
+            .and_then(JsonValue::as_i64),
+        Some(-32700)
+    );
+    let shutdown = read_message(&mut output).unwrap().unwrap();
+    assert_eq!(shutdown.get("id").and_then(JsonValue::as_i64), Some(1));
+    assert_eq!(read_message(&mut output).unwrap(), None);
+}
+
+#[test]
+fn negative_positions_are_rejected() {
+    let position = JsonValue::object(vec![
+        ("textDocument", JsonValue::object(vec![("uri", URI.into())])),
+        (
+            "position",
+            JsonValue::object(vec![("line", (-1i64).into()), ("character", 0usize.into())]),
+        ),
+    ]);
+    let mut messages = vec![
+        did_open("void main() {}"),
+        request(1, "textDocument/hover", position),
+    ];
+    messages.extend(shutdown_and_exit(2));
+    let (replies, exit_code) = run_session(&messages);
+    let error = replies
+        .iter()
+        .find(|reply| reply.get("id").and_then(JsonValue::as_i64) == Some(1))
+        .unwrap();
+    assert_eq!(
+        error
+            .pointer(&["error", "code"])
+            .and_then(JsonValue::as_i64),
+        Some(-32602)
+    );
+    assert_eq!(exit_code, Some(0));
+}
+
+#[test]
+fn content_length_is_not_trusted() {
+    let input = b"Content-Length: 99999999999999999\r\n\r\n{}".to_vec();
+    let error = lsp::run(Cursor::new(input), Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+}
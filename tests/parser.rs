@@ -1,5 +1,8 @@
-use cb_3::C1Parser;
+#[cfg(feature = "serde")]
+use cb_3::{json::JsonValue, Token};
+use cb_3::{tokenize, C1Lexer, C1Parser, C1Token, Identifiers, TokenSource};
 use std::fs;
+use std::ops::Range;
 
 #[test]
 fn run_example() {
@@ -7,3 +10,208 @@ fn run_example() {
     let result = C1Parser::parse(text.as_str());
     assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
 }
+
+#[test]
+fn block_comments() {
+    let text = "/* a * b / c\n * http://example.org\n */\nvoid main() {\n  /** x */ printf(1 /* 2 */);\n}\n";
+    assert!(C1Parser::parse(text).is_ok());
+
+    let error = C1Parser::parse("void main() {\n  /* printf(1);\n}\n").unwrap_err();
+    assert_eq!(
+        error.message,
+        "Lexical error at line 2: unterminated comment"
+    );
+    assert_eq!(error.line, Some(2));
+}
+
+/// The line number of every identifier whose name says on which line it is
+fn check_identifier_lines(text: &str) -> usize {
+    let mut lexer = C1Lexer::new(text);
+    let mut checked = 0;
+    while let Some(token) = lexer.current_token() {
+        let name = lexer.current_text().unwrap();
+        if token == C1Token::Identifier && name.starts_with("line") {
+            assert_eq!(
+                lexer.current_line_number(),
+                Some(name["line".len()..].parse().unwrap()),
+                "{}",
+                name
+            );
+            checked += 1;
+        }
+        lexer.eat();
+    }
+    checked
+}
+
+#[test]
+fn lines_in_comment_heavy_files() {
+    let text = fs::read_to_string("tests/data/comments.c-1").unwrap();
+    assert!(C1Parser::parse(&text).is_ok());
+    assert_eq!(check_identifier_lines(&text), 6);
+
+    let mut lexer = C1Lexer::new(&text);
+    assert_eq!(lexer.current_token(), Some(C1Token::KwInt));
+    assert_eq!(lexer.current_line_number(), Some(7));
+    lexer.eat();
+    assert_eq!(lexer.current_column(), Some(5));
+}
+
+#[test]
+fn lines_in_crlf_files() {
+    let text = fs::read_to_string("tests/data/comments.c-1").unwrap();
+    let crlf = text.replace('\n', "\r\n");
+    assert!(C1Parser::parse(&crlf).is_ok());
+    assert_eq!(check_identifier_lines(&crlf), 6);
+
+    let text = fs::read_to_string("tests/data/beispiel.c-1").unwrap();
+    let crlf = text.replace('\n', "\r\n");
+    let (mut lf, mut crlf) = (C1Lexer::new(&text), C1Lexer::new(&crlf));
+    while lf.current_token().is_some() {
+        assert_eq!(lf.current_token(), crlf.current_token());
+        assert_eq!(lf.current_line_number(), crlf.current_line_number());
+        assert_eq!(lf.current_column(), crlf.current_column());
+        lf.eat();
+        crlf.eat();
+    }
+    assert_eq!(crlf.current_token(), None);
+
+    let error = C1Parser::parse("void main() {\r\n  x = 1;\r\n  y = ;\r\n}\r\n").unwrap_err();
+    assert_eq!(error.line, Some(3));
+}
+
+#[test]
+fn identifiers() {
+    let text = "int _count() { my_var = 1; integer = my_var; return integer; }";
+    assert!(C1Parser::parse(text).is_ok());
+
+    let text = "void main() {\n  größe = 1;\n}\n";
+    let error = C1Parser::parse_tree(text).unwrap_err();
+    assert_eq!(error.line, Some(2));
+    assert!(error.message.contains("identifier 'größe'"), "{}", error);
+    let tree = C1Parser::parse_tree_with(text, Identifiers::Unicode).unwrap();
+    assert_eq!(tree.to_string(), text);
+}
+
+#[test]
+fn malformed_literals() {
+    let error = C1Parser::parse("void main() {\n  x = 1.5e;\n}").unwrap_err();
+    assert_eq!(
+        error.message,
+        "Lexical error at line 2: the exponent has no digits in '1.5e'"
+    );
+    assert!(C1Parser::parse("void main() { x = 0x1F + 0b1_0 + 1_000; }").is_ok());
+}
+
+#[test]
+fn parse_tokens() {
+    for file in ["beispiel.c-1", "features.c-1", "comments.c-1"] {
+        let text = fs::read_to_string(format!("tests/data/{}", file)).unwrap();
+        assert_eq!(
+            C1Parser::parse_tokens(&tokenize(&text)),
+            C1Parser::parse(&text)
+        );
+    }
+
+    let text = "void main() {\n  x = 1\n}\n";
+    let error = C1Parser::parse_tokens(&tokenize(text)).unwrap_err();
+    assert_eq!(Err(error.clone()), C1Parser::parse(text));
+    assert_eq!(error.line, Some(3));
+}
+
+/// Hands out the given tokens, each on its own line
+struct Tokens(Vec<(C1Token, &'static str)>, usize);
+
+impl TokenSource<'static> for Tokens {
+    fn current_token(&self) -> Option<C1Token> {
+        self.0.get(self.1).map(|&(token, _)| token)
+    }
+
+    fn current_text(&self) -> Option<&'static str> {
+        self.0.get(self.1).map(|&(_, text)| text)
+    }
+
+    fn current_line_number(&self) -> Option<usize> {
+        self.current_token().map(|_| self.1 + 1)
+    }
+
+    fn current_span(&self) -> Option<Range<usize>> {
+        self.current_token().map(|_| self.1..self.1 + 1)
+    }
+
+    fn peek_token(&self) -> Option<C1Token> {
+        self.0.get(self.1 + 1).map(|&(token, _)| token)
+    }
+
+    fn eat(&mut self) {
+        self.1 += 1;
+    }
+}
+
+#[test]
+fn token_sources() {
+    use C1Token::*;
+    let function = vec![
+        (KwVoid, "void"),
+        (Identifier, "main"),
+        (LeftParenthesis, "("),
+        (RightParenthesis, ")"),
+        (LeftBrace, "{"),
+        (KwReturn, "return"),
+        (Semicolon, ";"),
+        (RightBrace, "}"),
+    ];
+    assert_eq!(C1Parser::parse_source(Tokens(function.clone(), 0)), Ok(()));
+    let tree = C1Parser::parse_tree_from(Tokens(function.clone(), 0)).unwrap();
+    assert_eq!(tree.text(), "voidmain(){return;}");
+
+    let mut broken = function;
+    broken[6] = (Error, "$");
+    let error = C1Parser::parse_source(Tokens(broken, 0)).unwrap_err();
+    assert_eq!(
+        error.message,
+        "Lexical error at line 7: unexpected character '$'"
+    );
+    assert_eq!(error.span, Some(6..7));
+}
+
+#[test]
+fn token_json_keeps_literal_values() {
+    let tokens = tokenize("9007199254740993 1.0 0.5e-7");
+    let values: Vec<String> = tokens
+        .iter()
+        .filter_map(|token| token.to_json().get("value").map(ToString::to_string))
+        .collect();
+    assert_eq!(
+        values,
+        [
+            r#"{"Int":9007199254740993}"#,
+            r#"{"Float":1.0}"#,
+            r#"{"Float":5e-8}"#
+        ]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialized_tokens() {
+    let text = fs::read_to_string("tests/data/features.c-1").unwrap();
+    let tokens = tokenize(&text);
+    let json = serde_json::to_string(&tokens).unwrap();
+    let deserialized: Vec<Token> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, tokens);
+
+    // The JSON written by `c1 tokens --format json` deserializes just the same
+    let to_json: Vec<JsonValue> = tokens.iter().map(Token::to_json).collect();
+    let from_cli: Vec<Token> = serde_json::from_str(&JsonValue::from(to_json).to_string()).unwrap();
+    assert_eq!(from_cli, tokens);
+    assert!(C1Parser::parse_tokens(&deserialized).is_ok());
+
+    // Integers and floats without exponent are written like serde_json writes them
+    for token in tokenize("9007199254740993 1.0 0.1 true") {
+        assert_eq!(
+            token.to_json().to_string(),
+            serde_json::to_string(&token).unwrap()
+        );
+    }
+}
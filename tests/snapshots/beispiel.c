@@ -0,0 +1,50 @@
+#include <stdbool.h>
+#include <stdio.h>
+
+int c1_blub(void);
+double c1_blah(void);
+int main(void);
+
+int c1_blub(void) {
+	int c1_blub1;
+	int c1_blub2;
+	int c1_blub3;
+	int c1_blub4;
+	c1_blub1 = 23;
+	c1_blub2 = 17;
+	c1_blub3 = 42;
+	c1_blub4 = (int)((unsigned)c1_blub1 * (unsigned)((int)((unsigned)c1_blub2 + (unsigned)c1_blub3)));
+	if (c1_blub1 < c1_blub4) {
+		return c1_blub2;
+	}
+	return c1_blub3;
+}
+
+double c1_blah(void) {
+	int c1_a;
+	int c1_b;
+	c1_a = 1;
+	c1_b = 2;
+	if (c1_a < c1_blub()) {
+		if (c1_b > c1_blub()) {
+			printf("%d\n", (int)((unsigned)c1_blub() + (unsigned)c1_blub()));
+		}
+	}
+	return 3.14159;
+}
+
+int main(void) {
+	int c1_a;
+	int c1_b;
+	c1_a = 1;
+	c1_b = 2;
+	if (c1_a <= c1_b) {
+		printf("%d\n", (int)((unsigned)c1_a + (unsigned)c1_b));
+	}
+	if (c1_a >= c1_b) {
+		printf("%d\n", (int)((unsigned)c1_a - (unsigned)c1_b));
+	}
+	printf("%d\n", c1_blub());
+	printf("%f\n", c1_blah());
+	return 0;
+}
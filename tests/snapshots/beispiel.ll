@@ -0,0 +1,93 @@
+@.fmt.int = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.fmt.float = private unnamed_addr constant [4 x i8] c"%f\0A\00"
+
+declare i32 @printf(ptr, ...)
+
+define i32 @blub() {
+entry:
+  %blub1.addr = alloca i32
+  %blub2.addr = alloca i32
+  %blub3.addr = alloca i32
+  %blub4.addr = alloca i32
+  store i32 23, ptr %blub1.addr
+  store i32 17, ptr %blub2.addr
+  store i32 42, ptr %blub3.addr
+  %t0 = load i32, ptr %blub1.addr
+  %t1 = load i32, ptr %blub2.addr
+  %t2 = load i32, ptr %blub3.addr
+  %t3 = add i32 %t1, %t2
+  %t4 = mul i32 %t0, %t3
+  store i32 %t4, ptr %blub4.addr
+  %t5 = load i32, ptr %blub1.addr
+  %t6 = load i32, ptr %blub4.addr
+  %t7 = icmp slt i32 %t5, %t6
+  br i1 %t7, label %if.then0, label %if.end0
+if.then0:
+  %t8 = load i32, ptr %blub2.addr
+  ret i32 %t8
+if.end0:
+  %t9 = load i32, ptr %blub3.addr
+  ret i32 %t9
+}
+
+define double @blah() {
+entry:
+  %a.addr = alloca i32
+  %b.addr = alloca i32
+  store i32 1, ptr %a.addr
+  store i32 2, ptr %b.addr
+  %t0 = load i32, ptr %a.addr
+  %t1 = call i32 @blub()
+  %t2 = icmp slt i32 %t0, %t1
+  br i1 %t2, label %if.then0, label %if.end0
+if.then0:
+  %t3 = load i32, ptr %b.addr
+  %t4 = call i32 @blub()
+  %t5 = icmp sgt i32 %t3, %t4
+  br i1 %t5, label %if.then1, label %if.end1
+if.then1:
+  %t6 = call i32 @blub()
+  %t7 = call i32 @blub()
+  %t8 = add i32 %t6, %t7
+  %t9 = call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 %t8)
+  br label %if.end1
+if.end1:
+  br label %if.end0
+if.end0:
+  ret double 0x400921F9F01B866E
+}
+
+define i32 @main() {
+entry:
+  %a.addr = alloca i32
+  %b.addr = alloca i32
+  store i32 1, ptr %a.addr
+  store i32 2, ptr %b.addr
+  %t0 = load i32, ptr %a.addr
+  %t1 = load i32, ptr %b.addr
+  %t2 = icmp sle i32 %t0, %t1
+  br i1 %t2, label %if.then0, label %if.end0
+if.then0:
+  %t3 = load i32, ptr %a.addr
+  %t4 = load i32, ptr %b.addr
+  %t5 = add i32 %t3, %t4
+  %t6 = call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 %t5)
+  br label %if.end0
+if.end0:
+  %t7 = load i32, ptr %a.addr
+  %t8 = load i32, ptr %b.addr
+  %t9 = icmp sge i32 %t7, %t8
+  br i1 %t9, label %if.then1, label %if.end1
+if.then1:
+  %t10 = load i32, ptr %a.addr
+  %t11 = load i32, ptr %b.addr
+  %t12 = sub i32 %t10, %t11
+  %t13 = call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 %t12)
+  br label %if.end1
+if.end1:
+  %t14 = call i32 @blub()
+  %t15 = call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 %t14)
+  %t16 = call double @blah()
+  %t17 = call i32 (ptr, ...) @printf(ptr @.fmt.float, double %t16)
+  ret i32 0
+}
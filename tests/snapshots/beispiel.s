@@ -0,0 +1,180 @@
+	.text
+
+	.globl	blub
+	.type	blub, @function
+blub:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$32, %rsp
+	movl	$23, %eax
+	movl	%eax, -8(%rbp)
+	movl	$17, %eax
+	movl	%eax, -16(%rbp)
+	movl	$42, %eax
+	movl	%eax, -24(%rbp)
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	pushq	%rax
+	movl	-24(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	addl	%ecx, %eax
+	movl	%eax, %ecx
+	popq	%rax
+	imull	%ecx, %eax
+	movl	%eax, -32(%rbp)
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-32(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setl	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Lif_end0
+	movl	-16(%rbp), %eax
+	leave
+	ret
+.Lif_end0:
+	movl	-24(%rbp), %eax
+	leave
+	ret
+	movl	$0, %eax
+	leave
+	ret
+	.size	blub, .-blub
+
+	.globl	blah
+	.type	blah, @function
+blah:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$16, %rsp
+	movl	$1, %eax
+	movl	%eax, -8(%rbp)
+	movl	$2, %eax
+	movl	%eax, -16(%rbp)
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	subq	$8, %rsp
+	call	blub
+	addq	$8, %rsp
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setl	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Lif_end1
+	movl	-16(%rbp), %eax
+	pushq	%rax
+	subq	$8, %rsp
+	call	blub
+	addq	$8, %rsp
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setg	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Lif_end2
+	call	blub
+	pushq	%rax
+	subq	$8, %rsp
+	call	blub
+	addq	$8, %rsp
+	movl	%eax, %ecx
+	popq	%rax
+	addl	%ecx, %eax
+	movl	%eax, %esi
+	leaq	.Lformat_int(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+.Lif_end2:
+.Lif_end1:
+	movsd	.Lfloat0(%rip), %xmm0
+	leave
+	ret
+	pxor	%xmm0, %xmm0
+	leave
+	ret
+	.size	blah, .-blah
+
+	.globl	main
+	.type	main, @function
+main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$16, %rsp
+	movl	$1, %eax
+	movl	%eax, -8(%rbp)
+	movl	$2, %eax
+	movl	%eax, -16(%rbp)
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setle	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Lif_end3
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	addl	%ecx, %eax
+	movl	%eax, %esi
+	leaq	.Lformat_int(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+.Lif_end3:
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setge	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Lif_end4
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	subl	%ecx, %eax
+	movl	%eax, %esi
+	leaq	.Lformat_int(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+.Lif_end4:
+	call	blub
+	movl	%eax, %esi
+	leaq	.Lformat_int(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+	call	blah
+	leaq	.Lformat_float(%rip), %rdi
+	movl	$1, %eax
+	call	printf@PLT
+	movl	$0, %eax
+	leave
+	ret
+	.size	main, .-main
+
+	.section	.rodata
+.Lformat_int:
+	.string	"%d\n"
+.Lformat_float:
+	.string	"%f\n"
+	.p2align	4
+.Lfloat0:
+	.quad	0x400921F9F01B866E
+
+	.section	.note.GNU-stack,"",@progbits
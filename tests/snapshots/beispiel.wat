@@ -0,0 +1,85 @@
+(module
+  (import "env" "print_i32" (func $env.print_i32 (param i32)))
+  (import "env" "print_f64" (func $env.print_f64 (param f64)))
+  (func $blub (export "blub") (result i32)
+    (local $blub1 i32)
+    (local $blub2 i32)
+    (local $blub3 i32)
+    (local $blub4 i32)
+    i32.const 23
+    local.set $blub1
+    i32.const 17
+    local.set $blub2
+    i32.const 42
+    local.set $blub3
+    local.get $blub1
+    local.get $blub2
+    local.get $blub3
+    i32.add
+    i32.mul
+    local.set $blub4
+    local.get $blub1
+    local.get $blub4
+    i32.lt_s
+    if
+      local.get $blub2
+      return
+    end
+    local.get $blub3
+    return
+  )
+  (func $blah (export "blah") (result f64)
+    (local $a i32)
+    (local $b i32)
+    i32.const 1
+    local.set $a
+    i32.const 2
+    local.set $b
+    local.get $a
+    call $blub
+    i32.lt_s
+    if
+      local.get $b
+      call $blub
+      i32.gt_s
+      if
+        call $blub
+        call $blub
+        i32.add
+        call $env.print_i32
+      end
+    end
+    f64.const 3.14159
+    return
+  )
+  (func $main (export "main")
+    (local $a i32)
+    (local $b i32)
+    i32.const 1
+    local.set $a
+    i32.const 2
+    local.set $b
+    local.get $a
+    local.get $b
+    i32.le_s
+    if
+      local.get $a
+      local.get $b
+      i32.add
+      call $env.print_i32
+    end
+    local.get $a
+    local.get $b
+    i32.ge_s
+    if
+      local.get $a
+      local.get $b
+      i32.sub
+      call $env.print_i32
+    end
+    call $blub
+    call $env.print_i32
+    call $blah
+    call $env.print_f64
+  )
+)
@@ -0,0 +1,49 @@
+#include <stdbool.h>
+#include <stdio.h>
+
+double c1_half(void);
+int c1_truncated(void);
+bool c1_between(void);
+int main(void);
+
+double c1_half(void) {
+	double c1_x;
+	c1_x = 1.0;
+	c1_x = c1_x / 2;
+	return c1_x;
+}
+
+int c1_truncated(void) {
+	double c1_result;
+	c1_result = c1_half() * 5;
+	return c1_result;
+}
+
+bool c1_between(void) {
+	int c1_low;
+	int c1_high;
+	int c1_value;
+	bool c1_result;
+	c1_low = 1;
+	c1_high = 10;
+	c1_value = c1_truncated();
+	c1_result = (c1_low <= c1_value) && (c1_value < c1_high);
+	return c1_result;
+}
+
+int main(void) {
+	bool c1_inside;
+	int c1_total;
+	int c1_count;
+	printf("%f\n", c1_half());
+	printf("%d\n", (int)-(unsigned)c1_truncated());
+	c1_inside = c1_between();
+	if (c1_inside || false) {
+		if (c1_inside == true) {
+			printf("%s\n", c1_inside ? "true" : "false");
+		}
+		c1_count = c1_total = 3;
+		printf("%d\n", (int)((unsigned)c1_count + (unsigned)c1_total));
+	}
+	return 0;
+}
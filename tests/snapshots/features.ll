@@ -0,0 +1,95 @@
+@.fmt.int = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.fmt.float = private unnamed_addr constant [4 x i8] c"%f\0A\00"
+@.fmt.bool = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.str.true = private unnamed_addr constant [5 x i8] c"true\00"
+@.str.false = private unnamed_addr constant [6 x i8] c"false\00"
+
+declare i32 @printf(ptr, ...)
+
+define double @half() {
+entry:
+  %x.addr = alloca double
+  store double 1.0, ptr %x.addr
+  %t0 = load double, ptr %x.addr
+  %t1 = fdiv double %t0, 2.0
+  store double %t1, ptr %x.addr
+  %t2 = load double, ptr %x.addr
+  ret double %t2
+}
+
+define i32 @truncated() {
+entry:
+  %result.addr = alloca double
+  %t0 = call double @half()
+  %t1 = fmul double %t0, 5.0
+  store double %t1, ptr %result.addr
+  %t2 = load double, ptr %result.addr
+  %t3 = fptosi double %t2 to i32
+  ret i32 %t3
+}
+
+define i1 @between() {
+entry:
+  %low.addr = alloca i32
+  %high.addr = alloca i32
+  %value.addr = alloca i32
+  %result.addr = alloca i1
+  store i32 1, ptr %low.addr
+  store i32 10, ptr %high.addr
+  %t0 = call i32 @truncated()
+  store i32 %t0, ptr %value.addr
+  %t1 = load i32, ptr %low.addr
+  %t2 = load i32, ptr %value.addr
+  %t3 = icmp sle i32 %t1, %t2
+  br i1 %t3, label %and.rhs0, label %and.end0
+and.rhs0:
+  %t4 = load i32, ptr %value.addr
+  %t5 = load i32, ptr %high.addr
+  %t6 = icmp slt i32 %t4, %t5
+  br label %and.end0
+and.end0:
+  %t7 = phi i1 [ false, %entry ], [ %t6, %and.rhs0 ]
+  store i1 %t7, ptr %result.addr
+  %t8 = load i1, ptr %result.addr
+  ret i1 %t8
+}
+
+define i32 @main() {
+entry:
+  %inside.addr = alloca i1
+  %total.addr = alloca i32
+  %count.addr = alloca i32
+  %t0 = call double @half()
+  %t1 = call i32 (ptr, ...) @printf(ptr @.fmt.float, double %t0)
+  %t2 = call i32 @truncated()
+  %t3 = sub i32 0, %t2
+  %t4 = call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 %t3)
+  %t5 = call i1 @between()
+  store i1 %t5, ptr %inside.addr
+  %t6 = load i1, ptr %inside.addr
+  br i1 %t6, label %or.end0, label %or.rhs0
+or.rhs0:
+  br label %or.end0
+or.end0:
+  %t7 = phi i1 [ true, %entry ], [ false, %or.rhs0 ]
+  br i1 %t7, label %if.then1, label %if.end1
+if.then1:
+  %t8 = load i1, ptr %inside.addr
+  %t9 = icmp eq i1 %t8, true
+  br i1 %t9, label %if.then2, label %if.end2
+if.then2:
+  %t10 = load i1, ptr %inside.addr
+  %t11 = select i1 %t10, ptr @.str.true, ptr @.str.false
+  %t12 = call i32 (ptr, ...) @printf(ptr @.fmt.bool, ptr %t11)
+  br label %if.end2
+if.end2:
+  store i32 3, ptr %total.addr
+  store i32 3, ptr %count.addr
+  %t13 = load i32, ptr %count.addr
+  %t14 = load i32, ptr %total.addr
+  %t15 = add i32 %t13, %t14
+  %t16 = call i32 (ptr, ...) @printf(ptr @.fmt.int, i32 %t15)
+  br label %if.end1
+if.end1:
+  ret i32 0
+}
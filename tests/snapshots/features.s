@@ -0,0 +1,175 @@
+	.text
+
+	.globl	half
+	.type	half, @function
+half:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$16, %rsp
+	movsd	.Lfloat0(%rip), %xmm0
+	movsd	%xmm0, -8(%rbp)
+	movsd	-8(%rbp), %xmm0
+	subq	$8, %rsp
+	movsd	%xmm0, (%rsp)
+	movl	$2, %eax
+	cvtsi2sdl	%eax, %xmm0
+	movapd	%xmm0, %xmm1
+	movsd	(%rsp), %xmm0
+	addq	$8, %rsp
+	divsd	%xmm1, %xmm0
+	movsd	%xmm0, -8(%rbp)
+	movsd	-8(%rbp), %xmm0
+	leave
+	ret
+	pxor	%xmm0, %xmm0
+	leave
+	ret
+	.size	half, .-half
+
+	.globl	truncated
+	.type	truncated, @function
+truncated:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$16, %rsp
+	call	half
+	subq	$8, %rsp
+	movsd	%xmm0, (%rsp)
+	movl	$5, %eax
+	cvtsi2sdl	%eax, %xmm0
+	movapd	%xmm0, %xmm1
+	movsd	(%rsp), %xmm0
+	addq	$8, %rsp
+	mulsd	%xmm1, %xmm0
+	movsd	%xmm0, -8(%rbp)
+	movsd	-8(%rbp), %xmm0
+	cvttsd2si	%xmm0, %eax
+	leave
+	ret
+	movl	$0, %eax
+	leave
+	ret
+	.size	truncated, .-truncated
+
+	.globl	between
+	.type	between, @function
+between:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$32, %rsp
+	movl	$1, %eax
+	movl	%eax, -8(%rbp)
+	movl	$10, %eax
+	movl	%eax, -16(%rbp)
+	call	truncated
+	movl	%eax, -24(%rbp)
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	-24(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setle	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Llogic_end0
+	movl	-24(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	setl	%al
+	movzbl	%al, %eax
+.Llogic_end0:
+	movl	%eax, -32(%rbp)
+	movl	-32(%rbp), %eax
+	leave
+	ret
+	movl	$0, %eax
+	leave
+	ret
+	.size	between, .-between
+
+	.globl	main
+	.type	main, @function
+main:
+	pushq	%rbp
+	movq	%rsp, %rbp
+	subq	$32, %rsp
+	call	half
+	leaq	.Lformat_float(%rip), %rdi
+	movl	$1, %eax
+	call	printf@PLT
+	call	truncated
+	negl	%eax
+	movl	%eax, %esi
+	leaq	.Lformat_int(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+	call	between
+	movl	%eax, -8(%rbp)
+	movl	-8(%rbp), %eax
+	testl	%eax, %eax
+	jne	.Llogic_end2
+	movl	$0, %eax
+.Llogic_end2:
+	testl	%eax, %eax
+	je	.Lif_end1
+	movl	-8(%rbp), %eax
+	pushq	%rax
+	movl	$1, %eax
+	movl	%eax, %ecx
+	popq	%rax
+	cmpl	%ecx, %eax
+	sete	%al
+	movzbl	%al, %eax
+	testl	%eax, %eax
+	je	.Lif_end3
+	movl	-8(%rbp), %eax
+	leaq	.Lfalse(%rip), %rsi
+	leaq	.Ltrue(%rip), %rcx
+	testl	%eax, %eax
+	cmovne	%rcx, %rsi
+	leaq	.Lformat_bool(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+.Lif_end3:
+	movl	$3, %eax
+	movl	%eax, -16(%rbp)
+	movl	%eax, -24(%rbp)
+	movl	-24(%rbp), %eax
+	pushq	%rax
+	movl	-16(%rbp), %eax
+	movl	%eax, %ecx
+	popq	%rax
+	addl	%ecx, %eax
+	movl	%eax, %esi
+	leaq	.Lformat_int(%rip), %rdi
+	movl	$0, %eax
+	call	printf@PLT
+.Lif_end1:
+	movl	$0, %eax
+	leave
+	ret
+	movl	$0, %eax
+	leave
+	ret
+	.size	main, .-main
+
+	.section	.rodata
+.Lformat_int:
+	.string	"%d\n"
+.Lformat_float:
+	.string	"%f\n"
+.Lformat_bool:
+	.string	"%s\n"
+.Ltrue:
+	.string	"true"
+.Lfalse:
+	.string	"false"
+	.p2align	4
+.Lfloat0:
+	.quad	0x3FF0000000000000
+
+	.section	.note.GNU-stack,"",@progbits
@@ -0,0 +1,88 @@
+(module
+  (import "env" "print_i32" (func $env.print_i32 (param i32)))
+  (import "env" "print_f64" (func $env.print_f64 (param f64)))
+  (func $half (export "half") (result f64)
+    (local $x f64)
+    f64.const 1.0
+    local.set $x
+    local.get $x
+    i32.const 2
+    f64.convert_i32_s
+    f64.div
+    local.set $x
+    local.get $x
+    return
+  )
+  (func $truncated (export "truncated") (result i32)
+    (local $result f64)
+    call $half
+    i32.const 5
+    f64.convert_i32_s
+    f64.mul
+    local.set $result
+    local.get $result
+    i32.trunc_sat_f64_s
+    return
+  )
+  (func $between (export "between") (result i32)
+    (local $low i32)
+    (local $high i32)
+    (local $value i32)
+    (local $result i32)
+    i32.const 1
+    local.set $low
+    i32.const 10
+    local.set $high
+    call $truncated
+    local.set $value
+    local.get $low
+    local.get $value
+    i32.le_s
+    if (result i32)
+      local.get $value
+      local.get $high
+      i32.lt_s
+    else
+      i32.const 0
+    end
+    local.set $result
+    local.get $result
+    return
+  )
+  (func $main (export "main")
+    (local $inside i32)
+    (local $total i32)
+    (local $count i32)
+    call $half
+    call $env.print_f64
+    i32.const 0
+    call $truncated
+    i32.sub
+    call $env.print_i32
+    call $between
+    local.set $inside
+    local.get $inside
+    if (result i32)
+      i32.const 1
+    else
+      i32.const 0
+    end
+    if
+      local.get $inside
+      i32.const 1
+      i32.eq
+      if
+        local.get $inside
+        call $env.print_i32
+      end
+      i32.const 3
+      local.tee $total
+      local.set $count
+      local.get $count
+      local.get $total
+      i32.add
+      call $env.print_i32
+    end
+    return
+  )
+)